@@ -0,0 +1,577 @@
+// src/api.rs
+
+use crate::error::{ApiError, DownloadError};
+use crate::storage::{self, debug_log};
+use chrono::{DateTime, NaiveDate, Utc};
+use reqwest::{Method, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+/// Предел на всё скачивание результата: архивы бывают большими, общий
+/// таймаут клиента (минута) для них не годится
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+/// Сколько ждать очередную порцию данных, прежде чем счесть соединение зависшим
+const DOWNLOAD_STALL_TIMEOUT: Duration = Duration::from_secs(60);
+
+// --- Ответ на GET /auth/key ---
+#[derive(Deserialize, Debug)]
+pub struct AuthKey {
+    pub uuid: String,
+    pub data: String,
+}
+
+// --- Ответ на POST /auth/simpleSignIn ---
+#[derive(Deserialize)]
+struct SignInResponse {
+    token: String,
+}
+
+// --- Запрос на выгрузку ---
+#[derive(Serialize, Clone)]
+pub struct TaskRequest {
+    #[serde(rename = "name")]
+    pub name: String,
+    #[serde(rename = "dataStartDate")]
+    pub data_start_date: String,
+    #[serde(rename = "dataEndDate")]
+    pub data_end_date: String,
+    #[serde(rename = "format")]
+    pub format: String,
+    #[serde(rename = "periodicity")]
+    pub periodicity: String,
+    #[serde(rename = "params")]
+    pub params: String,
+    #[serde(rename = "productGroupCode")]
+    pub product_group_code: i32,
+}
+
+// --- Ответ на создание задачи ---
+#[derive(Deserialize, Clone, Debug)]
+pub struct TaskResponse {
+    #[serde(rename = "id")]
+    pub id: String,
+    #[serde(rename = "name")]
+    pub name: String,
+    #[serde(rename = "createDate")]
+    pub create_date: String,
+    #[serde(rename = "currentStatus")]
+    pub current_status: String,
+    #[serde(rename = "dataStartDate")]
+    pub data_start_date: String,
+    #[serde(rename = "dataEndDate")]
+    pub data_end_date: String,
+    #[serde(rename = "orgInn")]
+    pub org_inn: String,
+    #[serde(rename = "periodicity")]
+    pub periodicity: String,
+    #[serde(rename = "productGroupCode")]
+    pub product_group_code: i32,
+    #[serde(rename = "timeoutSecs")]
+    pub timeout_secs: i32,
+}
+
+// --- Ответ на GET /dispenser/tasks (список задач, постранично) ---
+#[derive(Deserialize, Clone, Debug)]
+pub struct TaskListResponse {
+    #[serde(rename = "list", default)]
+    pub list: Vec<TaskResponse>,
+    /// Всего задач по фильтру (если сервер его сообщает)
+    #[serde(rename = "total", default)]
+    pub total: Option<u64>,
+}
+
+/// Фильтр и страница списка задач
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TaskListQuery {
+    /// Номер страницы, с 0
+    pub page: u32,
+    pub size: u32,
+    pub status: Option<String>,
+    pub product_group: Option<i32>,
+    /// Задачи, созданные не раньше этой даты
+    pub date_from: Option<NaiveDate>,
+    /// Задачи, созданные не позже этой даты
+    pub date_to: Option<NaiveDate>,
+}
+
+impl TaskListQuery {
+    fn to_query(&self) -> String {
+        let mut params = vec![format!("page={}", self.page), format!("size={}", self.size)];
+        if let Some(status) = &self.status {
+            params.push(format!("status={}", status));
+        }
+        if let Some(pg) = self.product_group {
+            params.push(format!("pg={}", pg));
+        }
+        if let Some(date) = self.date_from {
+            params.push(format!("dateFrom={}", date.format("%Y-%m-%d")));
+        }
+        if let Some(date) = self.date_to {
+            params.push(format!("dateTo={}", date.format("%Y-%m-%d")));
+        }
+        params.join("&")
+    }
+}
+
+// --- Ответ на GET /dispenser/tasks/{id} ---
+#[derive(Deserialize, Clone, Debug)]
+pub struct ProductGroup {
+    #[serde(rename = "id")]
+    pub id: String,
+    #[serde(rename = "name")]
+    pub name: String,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct TaskStatusResponse {
+    #[serde(rename = "id")]
+    pub id: String,
+    #[serde(rename = "name")]
+    pub name: String,
+    #[serde(rename = "createDate")]
+    pub create_date: String,
+    #[serde(rename = "currentStatus")]
+    pub current_status: String,
+    #[serde(rename = "orgInn")]
+    pub org_inn: String,
+    #[serde(rename = "productGroupCode")]
+    pub product_group_code: i32,
+    #[serde(rename = "downloadingStorageDays")]
+    pub downloading_storage_days: i32,
+    #[serde(rename = "productGroups")]
+    pub product_groups: Vec<ProductGroup>,
+    #[serde(rename = "timeoutSecs")]
+    pub timeout_secs: i32,
+    #[serde(rename = "downloadUrl")]
+    pub download_url: Option<String>,
+}
+
+/// Тело запроса: JSON для True API или двоичные данные (например, запрос штампа времени)
+#[derive(Clone, Copy)]
+pub(crate) enum Body<'a> {
+    Json(&'a serde_json::Value),
+    Raw {
+        content_type: &'static str,
+        bytes: &'a [u8],
+    },
+}
+
+/// Клиент True API: базовый адрес текущего контура, общий HTTP-клиент
+/// (прокси, доверенные сертификаты, таймауты) и токен сессии.
+pub struct TrueApiClient {
+    base_url: String,
+    http: reqwest::Client,
+    /// Сессия, токен которой подставляется в запросы (None — без авторизации)
+    session_key: Option<String>,
+}
+
+impl TrueApiClient {
+    /// Клиент без авторизации (вход)
+    pub fn new() -> Result<Self, ApiError> {
+        let base_url = crate::settings::api_base_url();
+        crate::settings::check_base_url(&base_url).map_err(ApiError::Config)?;
+        Ok(Self {
+            base_url,
+            http: crate::net::shared_client()?,
+            session_key: None,
+        })
+    }
+
+    /// Клиент для сторонних служб (TSA): без адреса API и токена
+    pub fn external() -> Result<Self, ApiError> {
+        Ok(Self {
+            base_url: String::new(),
+            http: crate::net::shared_client()?,
+            session_key: None,
+        })
+    }
+
+    /// Клиент от имени сохранённой сессии
+    pub fn for_session(session_key: &str) -> Result<Self, ApiError> {
+        let mut client = Self::new()?;
+        client.session_key = Some(session_key.to_string());
+        Ok(client)
+    }
+
+    /// Полный адрес запроса; абсолютные адреса (ссылки на скачивание) не меняются
+    fn url(&self, path: &str) -> String {
+        if path.starts_with("http://") || path.starts_with("https://") {
+            return path.to_string();
+        }
+        format!("{}/{}", self.base_url, path.trim_start_matches('/'))
+    }
+
+    /// Токен читается из хранилища перед каждым запросом,
+    /// поэтому после продления сессии сразу используется новый
+    fn token(&self) -> Result<Option<String>, ApiError> {
+        match &self.session_key {
+            Some(key) => storage::load_session(key)
+                .map(|stored| Some(stored.token))
+                .map_err(|e| ApiError::NotSignedIn(e.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    /// Готовит запрос: адрес, токен и тело
+    fn build(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Body<'_>>,
+        timeout: Option<Duration>,
+    ) -> Result<reqwest::Request, ApiError> {
+        let url = self.url(path);
+        // Токен уходит только на адрес API, но не на сторонние ссылки скачивания
+        let own_host = url.starts_with(&format!("{}/", self.base_url));
+        let mut request = self.http.request(method, &url);
+        if own_host {
+            if let Some(token) = self.token()? {
+                request = request.bearer_auth(token);
+            }
+        }
+        match body {
+            Some(Body::Json(body)) => request = request.json(body),
+            Some(Body::Raw { content_type, bytes }) => {
+                request = request
+                    .header(reqwest::header::CONTENT_TYPE, content_type)
+                    .body(bytes.to_vec())
+            }
+            None => {}
+        }
+        if let Some(timeout) = timeout {
+            request = request.timeout(timeout);
+        }
+        request
+            .build()
+            .map_err(|e| ApiError::Config(format!("{}: {}", path, e)))
+    }
+
+    /// Ошибка по ответу сервера с неуспешным статусом
+    fn status_error(&self, status: StatusCode, headers: &reqwest::header::HeaderMap, bytes: &[u8]) -> ApiError {
+        let body = String::from_utf8_lossy(bytes).trim().to_string();
+        if status == StatusCode::UNAUTHORIZED {
+            if let Some(key) = &self.session_key {
+                crate::session::mark_unauthorized(key);
+            }
+            return ApiError::Unauthorized { body };
+        }
+        ApiError::Status {
+            status: status.as_u16(),
+            body,
+            retry_after: crate::retry::retry_after(headers),
+        }
+    }
+
+    /// Выполняет запрос; возвращает заголовки и тело успешного ответа
+    async fn execute(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Body<'_>>,
+    ) -> Result<(reqwest::header::HeaderMap, Vec<u8>), ApiError> {
+        let endpoint = crate::ratelimit::Endpoint::classify(&method, path);
+        crate::ratelimit::acquire(endpoint, &crate::settings::load().rate_limits).await;
+
+        let request = self.build(method, path, body, None)?;
+
+        // Обмен записывается в журнал консоли разработчика (секреты скрыты)
+        let mut exchange = crate::inspector::Exchange::start(
+            request.method().as_str(),
+            request.url().as_str(),
+            request.headers(),
+            request.body().and_then(|b| b.as_bytes()),
+        );
+        let started = std::time::Instant::now();
+        let result = self.http.execute(request).await;
+        let response = match result {
+            Ok(response) => response,
+            Err(e) => {
+                exchange.duration = started.elapsed();
+                exchange.error = Some(e.to_string());
+                crate::inspector::record(exchange);
+                return Err(ApiError::Network(format!("{}: {}", path, e)));
+            }
+        };
+
+        let status = response.status();
+        let headers = response.headers().clone();
+        let bytes = response.bytes().await;
+        exchange.duration = started.elapsed();
+        exchange.status = Some(status.as_u16());
+        exchange.response_headers = crate::inspector::redact_headers(&headers);
+        match &bytes {
+            Ok(bytes) => exchange.response_body = Some(crate::inspector::describe_body(bytes)),
+            Err(e) => exchange.error = Some(e.to_string()),
+        }
+        crate::inspector::record(exchange);
+        let bytes = bytes
+            .map_err(|e| ApiError::Network(format!("не удалось прочитать ответ: {}", e)))?
+            .to_vec();
+
+        if status.is_success() {
+            return Ok((headers, bytes));
+        }
+        Err(self.status_error(status, &headers, &bytes))
+    }
+
+    /// Выполняет запрос с повторами по политике из настроек.
+    /// Повторяются только временные ошибки (сеть, таймаут, 5xx, 429).
+    async fn execute_with_retry(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Body<'_>>,
+    ) -> Result<(reqwest::header::HeaderMap, Vec<u8>), ApiError> {
+        self.execute_retrying(method, path, body, crate::retry::should_retry).await
+    }
+
+    /// Повторяет запрос по политике из настроек после ошибок, для которых `retryable` — true
+    async fn execute_retrying(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Body<'_>>,
+        retryable: fn(&ApiError) -> bool,
+    ) -> Result<(reqwest::header::HeaderMap, Vec<u8>), ApiError> {
+        let policy = crate::settings::load().retry;
+        let mut retry = 0;
+
+        loop {
+            match self.execute(method.clone(), path, body).await {
+                Ok(res) => return Ok(res),
+                Err(e) if retry < policy.max_retries && retryable(&e) => {
+                    retry += 1;
+                    let retry_after = match &e {
+                        ApiError::Status { retry_after, .. } => *retry_after,
+                        _ => None,
+                    };
+                    let delay = policy.delay(retry, retry_after);
+                    let event = crate::retry::RetryEvent {
+                        path: path.to_string(),
+                        retry,
+                        max_retries: policy.max_retries,
+                        delay,
+                        error: e.to_string(),
+                    };
+                    debug_log(&format!("🔁 {}", event.describe()));
+                    crate::retry::emit(event);
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn parse<T: DeserializeOwned>(body: &[u8]) -> Result<T, ApiError> {
+        serde_json::from_slice(body).map_err(|e| ApiError::Parse(e.to_string()))
+    }
+
+    /// GET /auth/key — данные для подписи и время сервера из заголовка Date
+    pub async fn auth_key(&self) -> Result<(AuthKey, Option<DateTime<Utc>>), ApiError> {
+        let (headers, response) = self.execute_with_retry(Method::GET, "auth/key", None).await?;
+        Ok((Self::parse(&response)?, crate::clock::server_time(&headers)))
+    }
+
+    /// POST /auth/simpleSignIn[/{omsConnection}] — обмен подписи на токен.
+    /// Без повторов: uuid из /auth/key одноразовый.
+    pub async fn sign_in(
+        &self,
+        uuid: &str,
+        signature: &str,
+        oms_connection: Option<&str>,
+    ) -> Result<String, ApiError> {
+        let path = match oms_connection {
+            Some(connection) => format!("auth/simpleSignIn/{}", connection),
+            None => "auth/simpleSignIn".to_string(),
+        };
+        let body = serde_json::json!({ "uuid": uuid, "data": signature });
+        let (_, response) = self.execute(Method::POST, &path, Some(Body::Json(&body))).await?;
+        Ok(Self::parse::<SignInResponse>(&response)?.token)
+    }
+
+    /// POST /dispenser/tasks — создание задачи выгрузки
+    pub async fn create_task(&self, request: &TaskRequest) -> Result<TaskResponse, ApiError> {
+        let body = serde_json::to_value(request).map_err(|e| ApiError::Parse(e.to_string()))?;
+        // Создание задачи не идемпотентно: повтор только после 429
+        let (_, response) = self
+            .execute_retrying(
+                Method::POST,
+                "dispenser/tasks",
+                Some(Body::Json(&body)),
+                retry_task_creation,
+            )
+            .await?;
+        debug_log(&format!(
+            "📥 Ответ /dispenser/tasks: {}",
+            crate::inspector::describe_body(&response)
+        ));
+        Self::parse(&response)
+    }
+
+    /// GET /dispenser/tasks?page=&size=&... — задачи организации, одна страница
+    pub async fn list_tasks(&self, query: &TaskListQuery) -> Result<TaskListResponse, ApiError> {
+        let path = format!("dispenser/tasks?{}", query.to_query());
+        let (_, response) = self.execute_with_retry(Method::GET, &path, None).await?;
+        Self::parse(&response)
+    }
+
+    /// GET /dispenser/tasks/{id}?pg= — статус задачи
+    pub async fn task_status(&self, task_id: &str, product_group: i32) -> Result<TaskStatusResponse, ApiError> {
+        let path = format!("dispenser/tasks/{}?pg={}", task_id, product_group);
+        let (_, response) = self.execute_with_retry(Method::GET, &path, None).await?;
+        Self::parse(&response)
+    }
+
+    /// POST /dispenser/tasks/{id}/cancel?pg= — остановка задачи в очереди
+    pub async fn cancel_task(&self, task_id: &str, product_group: i32) -> Result<(), ApiError> {
+        let path = format!("dispenser/tasks/{}/cancel?pg={}", task_id, product_group);
+        self.execute_with_retry(Method::POST, &path, None).await?;
+        Ok(())
+    }
+
+    /// DELETE /dispenser/tasks/{id}?pg= — удаление задачи с сервера
+    pub async fn delete_task(&self, task_id: &str, product_group: i32) -> Result<(), ApiError> {
+        let path = format!("dispenser/tasks/{}?pg={}", task_id, product_group);
+        self.execute_with_retry(Method::DELETE, &path, None).await?;
+        Ok(())
+    }
+
+    /// POST двоичных данных на абсолютный адрес с повторами и записью в журнал;
+    /// возвращает тело ответа
+    pub async fn post_binary(&self, url: &str, content_type: &'static str, bytes: &[u8]) -> Result<Vec<u8>, ApiError> {
+        let body = Body::Raw { content_type, bytes };
+        let (_, response) = self.execute_with_retry(Method::POST, url, Some(body)).await?;
+        Ok(response)
+    }
+
+    /// GET по ссылке из `downloadUrl` — файл результата выгрузки.
+    /// Тело пишется в `dest` по частям, не собираясь в памяти;
+    /// при повторе файл перезаписывается с начала.
+    pub async fn download(&self, url: &str, dest: &Path) -> Result<reqwest::header::HeaderMap, DownloadError> {
+        let policy = crate::settings::load().retry;
+        let mut retry = 0;
+
+        loop {
+            match self.download_once(url, dest).await {
+                Ok(headers) => return Ok(headers),
+                Err(DownloadError::Api(e)) if retry < policy.max_retries && crate::retry::should_retry(&e) => {
+                    retry += 1;
+                    let retry_after = match &e {
+                        ApiError::Status { retry_after, .. } => *retry_after,
+                        _ => None,
+                    };
+                    let delay = policy.delay(retry, retry_after);
+                    let event = crate::retry::RetryEvent {
+                        path: url.to_string(),
+                        retry,
+                        max_retries: policy.max_retries,
+                        delay,
+                        error: e.to_string(),
+                    };
+                    debug_log(&format!("🔁 {}", event.describe()));
+                    crate::retry::emit(event);
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    async fn download_once(&self, url: &str, dest: &Path) -> Result<reqwest::header::HeaderMap, DownloadError> {
+        let endpoint = crate::ratelimit::Endpoint::classify(&Method::GET, url);
+        crate::ratelimit::acquire(endpoint, &crate::settings::load().rate_limits).await;
+
+        let request = self.build(Method::GET, url, None, Some(DOWNLOAD_TIMEOUT))?;
+        let mut exchange = crate::inspector::Exchange::start(
+            request.method().as_str(),
+            request.url().as_str(),
+            request.headers(),
+            None,
+        );
+        let started = std::time::Instant::now();
+        let mut response = match self.http.execute(request).await {
+            Ok(response) => response,
+            Err(e) => {
+                exchange.duration = started.elapsed();
+                exchange.error = Some(e.to_string());
+                crate::inspector::record(exchange);
+                return Err(ApiError::Network(format!("{}: {}", url, e)).into());
+            }
+        };
+
+        let status = response.status();
+        let headers = response.headers().clone();
+        exchange.status = Some(status.as_u16());
+        exchange.response_headers = crate::inspector::redact_headers(&headers);
+
+        if !status.is_success() {
+            let bytes = response.bytes().await.map(|b| b.to_vec()).unwrap_or_default();
+            exchange.duration = started.elapsed();
+            exchange.response_body = Some(crate::inspector::describe_body(&bytes));
+            crate::inspector::record(exchange);
+            return Err(self.status_error(status, &headers, &bytes).into());
+        }
+
+        // В журнал попадает только размер: результат может весить сотни мегабайт
+        let result = stream_to_file(&mut response, dest).await;
+        exchange.duration = started.elapsed();
+        match &result {
+            Ok(size) => exchange.response_body = Some(format!("<{} байт>", size)),
+            Err(e) => exchange.error = Some(e.to_string()),
+        }
+        crate::inspector::record(exchange);
+        result.map(|_| headers)
+    }
+}
+
+/// Повтор создания задачи: только после 429. Таймаут или 5xx могут прийти,
+/// когда сервер уже принял запрос, и повтор создал бы дубликат выгрузки.
+fn retry_task_creation(error: &ApiError) -> bool {
+    matches!(error, ApiError::Status { status: 429, .. })
+}
+
+/// Пишет тело ответа в файл; возвращает число байт
+async fn stream_to_file(response: &mut reqwest::Response, dest: &Path) -> Result<u64, DownloadError> {
+    let file_error = |e: std::io::Error| DownloadError::File(format!("{}: {}", dest.display(), e));
+    let mut file = std::io::BufWriter::new(std::fs::File::create(dest).map_err(file_error)?);
+    let mut size = 0u64;
+
+    loop {
+        let chunk = tokio::time::timeout(DOWNLOAD_STALL_TIMEOUT, response.chunk())
+            .await
+            .map_err(|_| {
+                ApiError::Network(format!(
+                    "данные не поступали {} с, скачивание прервано",
+                    DOWNLOAD_STALL_TIMEOUT.as_secs()
+                ))
+            })?
+            .map_err(|e| ApiError::Network(format!("не удалось прочитать ответ: {}", e)))?;
+        let Some(chunk) = chunk else {
+            break;
+        };
+        file.write_all(&chunk).map_err(file_error)?;
+        size += chunk.len() as u64;
+    }
+    file.flush().map_err(file_error)?;
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn task_creation_is_retried_only_after_429() {
+        let status = |status| ApiError::Status {
+            status,
+            body: String::new(),
+            retry_after: None,
+        };
+        assert!(retry_task_creation(&status(429)));
+        assert!(!retry_task_creation(&status(503)));
+        assert!(!retry_task_creation(&ApiError::Network("timeout".into())));
+    }
+}
@@ -0,0 +1,425 @@
+// src/asn1.rs
+
+//! Минимальный разбор DER/BER, достаточный для CMS-подписей и X.509-сертификатов.
+
+use base64::Engine;
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+
+// --- Теги ---
+pub const TAG_BOOLEAN: u8 = 0x01;
+pub const TAG_INTEGER: u8 = 0x02;
+pub const TAG_OCTET_STRING: u8 = 0x04;
+pub const TAG_NULL: u8 = 0x05;
+pub const TAG_OID: u8 = 0x06;
+pub const TAG_UTC_TIME: u8 = 0x17;
+pub const TAG_GENERALIZED_TIME: u8 = 0x18;
+pub const TAG_BMP_STRING: u8 = 0x1E;
+pub const TAG_SEQUENCE: u8 = 0x30;
+pub const TAG_SET: u8 = 0x31;
+pub const TAG_CONSTRUCTED_OCTET_STRING: u8 = 0x24;
+
+/// Предел вложенности элементов неопределённой длины и составных OCTET STRING:
+/// настоящим подписям хватает нескольких уровней, а без предела файл вида
+/// `30 80 30 80 …` переполнил бы стек
+pub const MAX_DEPTH: usize = 32;
+
+/// Контекстный тег `[n]` (constructed)
+pub const fn context(n: u8) -> u8 {
+    0xA0 | n
+}
+
+/// Один элемент TLV: тег, содержимое и исходные байты целиком
+#[derive(Clone, Copy, Debug)]
+pub struct Tlv<'a> {
+    pub tag: u8,
+    pub content: &'a [u8],
+    pub raw: &'a [u8],
+}
+
+impl<'a> Tlv<'a> {
+    /// Разбирает вложенные элементы (для SEQUENCE, SET и контекстных тегов)
+    pub fn children(&self) -> Result<Vec<Tlv<'a>>, String> {
+        read_all(self.content)
+    }
+
+    /// Возвращает содержимое OCTET STRING, склеивая части BER-формы
+    pub fn octets(&self) -> Result<Vec<u8>, String> {
+        self.octets_at(0)
+    }
+
+    fn octets_at(&self, depth: usize) -> Result<Vec<u8>, String> {
+        match self.tag {
+            TAG_OCTET_STRING => Ok(self.content.to_vec()),
+            TAG_CONSTRUCTED_OCTET_STRING => {
+                if depth >= MAX_DEPTH {
+                    return Err(too_deep());
+                }
+                let mut result = Vec::new();
+                for part in self.children()? {
+                    result.extend(part.octets_at(depth + 1)?);
+                }
+                Ok(result)
+            }
+            tag => Err(format!("Ожидалась OCTET STRING, получен тег 0x{:02X}", tag)),
+        }
+    }
+
+    /// Проверяет тег элемента
+    pub fn expect(self, tag: u8) -> Result<Self, String> {
+        if self.tag == tag {
+            Ok(self)
+        } else {
+            Err(format!(
+                "Ожидался тег 0x{:02X}, получен 0x{:02X}",
+                tag, self.tag
+            ))
+        }
+    }
+}
+
+fn too_deep() -> String {
+    format!("Слишком глубокая вложенность ASN.1 (больше {} уровней)", MAX_DEPTH)
+}
+
+/// Читает один элемент и возвращает остаток входа
+pub fn read_tlv(input: &[u8]) -> Result<(Tlv<'_>, &[u8]), String> {
+    read_tlv_at(input, 0)
+}
+
+/// `depth` — уровень вложенности элементов неопределённой длины
+fn read_tlv_at(input: &[u8], depth: usize) -> Result<(Tlv<'_>, &[u8]), String> {
+    if depth > MAX_DEPTH {
+        return Err(too_deep());
+    }
+    if input.len() < 2 {
+        return Err("Неожиданный конец данных ASN.1".to_string());
+    }
+
+    let tag = input[0];
+    if tag & 0x1F == 0x1F {
+        return Err("Многобайтовые теги ASN.1 не поддерживаются".to_string());
+    }
+
+    let first_len = input[1];
+    let mut pos = 2;
+
+    // Неопределённая длина (BER): читаем вложенные элементы до маркера 00 00
+    if first_len == 0x80 {
+        if tag & 0x20 == 0 {
+            return Err("Неопределённая длина у примитивного элемента".to_string());
+        }
+        let mut rest = &input[pos..];
+        let mut consumed = 0;
+        loop {
+            if rest.len() >= 2 && rest[0] == 0 && rest[1] == 0 {
+                break;
+            }
+            let (child, tail) = read_tlv_at(rest, depth + 1)?;
+            consumed += child.raw.len();
+            rest = tail;
+        }
+        let total = pos + consumed + 2;
+        let tlv = Tlv {
+            tag,
+            content: &input[pos..pos + consumed],
+            raw: &input[..total],
+        };
+        return Ok((tlv, &input[total..]));
+    }
+
+    let len = if first_len & 0x80 == 0 {
+        first_len as usize
+    } else {
+        let count = (first_len & 0x7F) as usize;
+        if count == 0 || count > 4 || input.len() < pos + count {
+            return Err("Некорректная длина элемента ASN.1".to_string());
+        }
+        let mut len = 0usize;
+        for &b in &input[pos..pos + count] {
+            len = (len << 8) | b as usize;
+        }
+        pos += count;
+        len
+    };
+
+    if input.len() < pos + len {
+        return Err("Длина элемента ASN.1 превышает размер данных".to_string());
+    }
+
+    let tlv = Tlv {
+        tag,
+        content: &input[pos..pos + len],
+        raw: &input[..pos + len],
+    };
+    Ok((tlv, &input[pos + len..]))
+}
+
+/// Читает все элементы подряд
+pub fn read_all(mut input: &[u8]) -> Result<Vec<Tlv<'_>>, String> {
+    let mut items = Vec::new();
+    while !input.is_empty() {
+        let (tlv, rest) = read_tlv(input)?;
+        items.push(tlv);
+        input = rest;
+    }
+    Ok(items)
+}
+
+/// Кодирует длину в DER
+fn encode_len(len: usize, out: &mut Vec<u8>) {
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let skip = bytes.iter().take_while(|&&b| b == 0).count();
+        out.push(0x80 | (bytes.len() - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+}
+
+/// Кодирует элемент TLV в DER
+pub fn encode(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(content.len() + 6);
+    out.push(tag);
+    encode_len(content.len(), &mut out);
+    out.extend_from_slice(content);
+    out
+}
+
+/// Кодирует составной элемент из уже закодированных частей
+pub fn encode_parts(tag: u8, parts: &[&[u8]]) -> Vec<u8> {
+    encode(tag, &parts.concat())
+}
+
+/// Кодирует OBJECT IDENTIFIER из точечной записи (вместе с тегом)
+pub fn encode_oid(oid: &str) -> Vec<u8> {
+    let arcs: Vec<u64> = oid.split('.').filter_map(|a| a.parse().ok()).collect();
+    let mut content = Vec::new();
+    if arcs.len() >= 2 {
+        let mut values = vec![arcs[0] * 40 + arcs[1]];
+        values.extend_from_slice(&arcs[2..]);
+        for v in values {
+            let mut chunk = vec![(v & 0x7F) as u8];
+            let mut rest = v >> 7;
+            while rest > 0 {
+                chunk.push(0x80 | (rest & 0x7F) as u8);
+                rest >>= 7;
+            }
+            chunk.reverse();
+            content.extend(chunk);
+        }
+    }
+    encode(TAG_OID, &content)
+}
+
+/// Кодирует неотрицательное INTEGER из байтов big-endian
+pub fn encode_unsigned(bytes: &[u8]) -> Vec<u8> {
+    let trimmed: Vec<u8> = bytes.iter().copied().skip_while(|&b| b == 0).collect();
+    let mut content = Vec::with_capacity(trimmed.len() + 1);
+    if trimmed.first().is_none_or(|&b| b & 0x80 != 0) {
+        content.push(0);
+    }
+    content.extend(trimmed);
+    encode(TAG_INTEGER, &content)
+}
+
+/// Декодирует OBJECT IDENTIFIER в точечную запись
+pub fn decode_oid(bytes: &[u8]) -> String {
+    let mut arcs: Vec<u64> = Vec::new();
+    let mut value: u64 = 0;
+    for &b in bytes {
+        value = (value << 7) | (b & 0x7F) as u64;
+        if b & 0x80 == 0 {
+            if arcs.is_empty() {
+                let first = (value / 40).min(2);
+                arcs.push(first);
+                arcs.push(value - first * 40);
+            } else {
+                arcs.push(value);
+            }
+            value = 0;
+        }
+    }
+    arcs.iter()
+        .map(|a| a.to_string())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Возвращает OID из элемента
+pub fn oid(tlv: &Tlv) -> Result<String, String> {
+    Ok(decode_oid(tlv.expect(TAG_OID)?.content))
+}
+
+/// Декодирует строковый тип ASN.1
+pub fn decode_string(tlv: &Tlv) -> String {
+    match tlv.tag {
+        TAG_BMP_STRING => {
+            let units: Vec<u16> = tlv
+                .content
+                .chunks(2)
+                .map(|c| u16::from_be_bytes([c[0], *c.get(1).unwrap_or(&0)]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => String::from_utf8_lossy(tlv.content).to_string(),
+    }
+}
+
+/// Разбирает UTCTime или GeneralizedTime
+pub fn decode_time(tlv: &Tlv) -> Option<DateTime<Utc>> {
+    let text = std::str::from_utf8(tlv.content).ok()?;
+    let text = text.trim_end_matches('Z');
+    // Дробные секунды в GeneralizedTime отбрасываем
+    let text = text.split('.').next()?;
+    let naive = match tlv.tag {
+        TAG_UTC_TIME => {
+            let full = if text.len() == 10 { format!("{}00", text) } else { text.to_string() };
+            let year: i32 = full.get(0..2)?.parse().ok()?;
+            let century = if year >= 50 { "19" } else { "20" };
+            NaiveDateTime::parse_from_str(&format!("{}{}", century, full), "%Y%m%d%H%M%S").ok()?
+        }
+        TAG_GENERALIZED_TIME => NaiveDateTime::parse_from_str(text, "%Y%m%d%H%M%S").ok()?,
+        _ => return None,
+    };
+    Some(Utc.from_utc_datetime(&naive))
+}
+
+/// Форматирует INTEGER как шестнадцатеричную строку через двоеточие
+pub fn format_integer(tlv: &Tlv) -> String {
+    let bytes = match tlv.content {
+        [0, rest @ ..] if !rest.is_empty() => rest,
+        other => other,
+    };
+    bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Короткие имена атрибутов DN, как их выводит CertNameToStrW
+fn attr_short_name(oid: &str) -> String {
+    match oid {
+        "2.5.4.3" => "CN",
+        "2.5.4.4" => "SN",
+        "2.5.4.42" => "G",
+        "2.5.4.6" => "C",
+        "2.5.4.7" => "L",
+        "2.5.4.8" => "S",
+        "2.5.4.9" => "STREET",
+        "2.5.4.10" => "O",
+        "2.5.4.11" => "OU",
+        "2.5.4.12" => "T",
+        "1.2.840.113549.1.9.1" => "E",
+        "1.2.643.3.131.1.1" => "INN",
+        "1.2.643.100.1" => "OGRN",
+        "1.2.643.100.3" => "SNILS",
+        "1.2.643.100.4" => "INNLE",
+        "1.2.643.100.5" => "OGRNIP",
+        other => return format!("OID.{}", other),
+    }
+    .to_string()
+}
+
+/// Декодирует Name (DN) в строку вида "CN=..., O=..., INN=..."
+pub fn decode_name(tlv: &Tlv) -> Result<String, String> {
+    let mut parts = Vec::new();
+    for rdn in tlv.expect(TAG_SEQUENCE)?.children()? {
+        for atv in rdn.expect(TAG_SET)?.children()? {
+            let fields = atv.expect(TAG_SEQUENCE)?.children()?;
+            if fields.len() < 2 {
+                continue;
+            }
+            let name = attr_short_name(&oid(&fields[0])?);
+            parts.push(format!("{}={}", name, decode_string(&fields[1])));
+        }
+    }
+    // CertNameToStrW выводит атрибуты в обратном порядке
+    parts.reverse();
+    Ok(parts.join(", "))
+}
+
+/// Превращает содержимое файла (DER, Base64 или PEM) в DER
+pub fn to_der(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    if bytes.first() == Some(&TAG_SEQUENCE) {
+        return Ok(bytes.to_vec());
+    }
+
+    let text = String::from_utf8_lossy(bytes);
+    let cleaned: String = text
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("-----"))
+        .collect::<String>()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+
+    base64::engine::general_purpose::STANDARD
+        .decode(cleaned.as_bytes())
+        .map_err(|e| format!("Файл не похож ни на DER, ни на Base64: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode_round_trip() {
+        let oid = encode_oid("1.2.643.7.1.1.1.1");
+        let serial = encode_unsigned(&[0x00, 0x8F, 0x01]);
+        let long = encode(TAG_OCTET_STRING, &[0xAB; 300]);
+        let der = encode_parts(TAG_SEQUENCE, &[&oid, &serial, &long]);
+
+        let (seq, rest) = read_tlv(&der).unwrap();
+        assert!(rest.is_empty());
+        let children = seq.expect(TAG_SEQUENCE).unwrap().children().unwrap();
+        assert_eq!(children.len(), 3);
+        assert_eq!(super::oid(&children[0]).unwrap(), "1.2.643.7.1.1.1.1");
+        // Старший бит установлен — INTEGER дополняется нулём
+        assert_eq!(children[1].content, &[0x00, 0x8F, 0x01]);
+        assert_eq!(format_integer(&children[1]), "8F:01");
+        assert_eq!(children[2].octets().unwrap(), vec![0xAB; 300]);
+    }
+
+    #[test]
+    fn indefinite_length_and_constructed_octets() {
+        // 24 80 { 04 02 01 02, 04 01 03 } 00 00
+        let ber = [0x24, 0x80, 0x04, 0x02, 0x01, 0x02, 0x04, 0x01, 0x03, 0x00, 0x00, 0xFF];
+        let (tlv, rest) = read_tlv(&ber).unwrap();
+        assert_eq!(rest, &[0xFF]);
+        assert_eq!(tlv.octets().unwrap(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn truncated_input_is_rejected() {
+        let der = encode(TAG_OCTET_STRING, &[1, 2, 3, 4]);
+        for len in 0..der.len() {
+            assert!(read_tlv(&der[..len]).is_err(), "длина {}", len);
+        }
+        // Неопределённая длина без маркера конца
+        assert!(read_tlv(&[0x30, 0x80, 0x05, 0x00]).is_err());
+        // Длина в пяти байтах
+        assert!(read_tlv(&[0x04, 0x85, 1, 0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn deep_nesting_is_rejected() {
+        let bomb: Vec<u8> = [0x30, 0x80].repeat(100_000);
+        assert!(read_tlv(&bomb).unwrap_err().contains("вложенность"));
+
+        let mut octets = encode(TAG_OCTET_STRING, &[1]);
+        for _ in 0..=MAX_DEPTH {
+            octets = encode(TAG_CONSTRUCTED_OCTET_STRING, &octets);
+        }
+        let (tlv, _) = read_tlv(&octets).unwrap();
+        assert!(tlv.octets().is_err());
+    }
+
+    #[test]
+    fn shallow_nesting_is_accepted() {
+        let mut ber = [0x30, 0x80].repeat(MAX_DEPTH);
+        ber.extend([0x00, 0x00].repeat(MAX_DEPTH));
+        assert!(read_tlv(&ber).is_ok());
+    }
+}
@@ -0,0 +1,334 @@
+// src/batch.rs
+
+use crate::certificate::CertificateInfo;
+use crate::error::SignError;
+use crate::signing::{self, SignatureKind};
+use chrono::Local;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::{mpsc::UnboundedSender, Semaphore};
+use zeroize::Zeroizing;
+
+const REPORT_PREFIX: &str = "signing_report_";
+
+/// Что делать, если рядом с файлом уже лежит подпись
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExistingSignature {
+    Skip,
+    Overwrite,
+}
+
+impl ExistingSignature {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ExistingSignature::Skip => "Пропускать подписанные",
+            ExistingSignature::Overwrite => "Перезаписывать подписи",
+        }
+    }
+}
+
+/// Параметры пакетной подписи
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BatchOptions {
+    pub kind: SignatureKind,
+    pub existing: ExistingSignature,
+    pub concurrency: usize,
+    pub recursive: bool,
+}
+
+/// Состояние подписи одного файла
+#[derive(Clone, Debug, PartialEq)]
+pub enum FileState {
+    Pending,
+    Signing,
+    Signed(PathBuf),
+    Skipped,
+    Failed(SignError),
+}
+
+impl FileState {
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, FileState::Pending | FileState::Signing)
+    }
+}
+
+/// Строка итогового отчёта
+#[derive(Serialize)]
+struct ReportRow {
+    file: String,
+    status: &'static str,
+    signature: String,
+    error: String,
+}
+
+/// Собирает файлы папки для подписи, пропуская сами подписи и отчёты
+pub fn collect_files(folder: &Path, recursive: bool) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    let entries = std::fs::read_dir(folder)
+        .map_err(|e| format!("Не удалось прочитать папку {}: {}", folder.display(), e))?;
+
+    for entry in entries.flatten() {
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let path = entry.path();
+        if file_type.is_dir() {
+            if recursive {
+                files.extend(collect_files(&path, true)?);
+            }
+            continue;
+        }
+        // Ссылки на папки (symlink, junction) не обходятся: петля из ссылок
+        // привела бы к бесконечной рекурсии
+        if file_type.is_symlink() && path.is_dir() {
+            continue;
+        }
+
+        let is_signature = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| {
+                // .partial — недописанная подпись, оставшаяся после сбоя
+                e.eq_ignore_ascii_case("sig") || e.eq_ignore_ascii_case("p7s") || e.eq_ignore_ascii_case("partial")
+            })
+            .unwrap_or(false);
+        let is_report = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .map(|n| n.starts_with(REPORT_PREFIX))
+            .unwrap_or(false);
+
+        if !is_signature && !is_report {
+            files.push(path);
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Подписывает один файл с учётом правила для существующих подписей
+async fn sign_one(
+    cert: Arc<CertificateInfo>,
+    path: PathBuf,
+    options: BatchOptions,
+    pin: Arc<Option<Zeroizing<String>>>,
+) -> FileState {
+    let output = signing::signature_path_for(&path, options.kind);
+
+    if output.exists() && options.existing == ExistingSignature::Skip {
+        return FileState::Skipped;
+    }
+
+    // Подпись пишется во временный файл рядом и заменяет старую только после
+    // успеха: неверный PIN или сбой КриптоПро не должны уничтожить прежнюю подпись
+    let partial = signing::partial_path(&output);
+    let target = partial.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        let pin = pin.as_deref().map(|p| p.as_str());
+        signing::run_cryptcp_sign(&cert, &path, &target, options.kind, true, pin)
+    })
+    .await;
+
+    let result = match result {
+        Ok(Ok(())) => Ok(signing::add_timestamp_if_enabled(&partial).await),
+        other => other,
+    };
+    let result = match result {
+        Ok(Ok(())) => Ok(std::fs::rename(&partial, &output).map_err(|e| {
+            SignError::File(format!("Не удалось сохранить подпись {}: {}", output.display(), e))
+        })),
+        other => other,
+    };
+
+    match result {
+        Ok(Ok(())) => FileState::Signed(output),
+        Ok(Err(e)) => {
+            let _ = std::fs::remove_file(&partial);
+            FileState::Failed(e)
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&partial);
+            FileState::Failed(interrupted(e))
+        }
+    }
+}
+
+/// Ошибка для подписи, задача которой завершилась аварийно
+pub fn interrupted(e: tokio::task::JoinError) -> SignError {
+    SignError::Csp(format!("подпись прервана: {}", e))
+}
+
+/// Подписывает файлы параллельно (не более `options.concurrency` процессов cryptcp
+/// одновременно). Изменения состояния отправляются в `progress` как (индекс, состояние).
+///
+/// Первый файл подписывается отдельно: если PIN не подошёл, остальные не трогаем,
+/// чтобы параллельные попытки не заблокировали носитель.
+pub async fn sign_files(
+    cert: CertificateInfo,
+    files: Vec<PathBuf>,
+    options: BatchOptions,
+    pin: Option<String>,
+    progress: UnboundedSender<(usize, FileState)>,
+) -> Vec<FileState> {
+    let pin = pin.map(Zeroizing::new);
+    let pin = Arc::new(signing::resolve_pin(&cert, pin.as_deref().map(|p| p.as_str())));
+    let cert = Arc::new(cert);
+    let mut states = vec![FileState::Pending; files.len()];
+
+    let Some(first) = files.first().cloned() else {
+        return states;
+    };
+    let _ = progress.send((0, FileState::Signing));
+    states[0] = sign_one(cert.clone(), first, options, pin.clone()).await;
+    let _ = progress.send((0, states[0].clone()));
+
+    if let FileState::Failed(e) = &states[0] {
+        if e.needs_pin() {
+            let blocked = FileState::Failed(e.clone());
+            for (idx, state) in states.iter_mut().enumerate().skip(1) {
+                *state = blocked.clone();
+                let _ = progress.send((idx, blocked.clone()));
+            }
+            return states;
+        }
+    }
+
+    let semaphore = Arc::new(Semaphore::new(options.concurrency.max(1)));
+    let mut handles = Vec::new();
+
+    for (idx, path) in files.into_iter().enumerate().skip(1) {
+        let semaphore = semaphore.clone();
+        let cert = cert.clone();
+        let pin = pin.clone();
+        let progress = progress.clone();
+
+        handles.push((
+            idx,
+            tokio::spawn(async move {
+                let _permit = match semaphore.acquire_owned().await {
+                    Ok(permit) => permit,
+                    Err(e) => return FileState::Failed(SignError::Csp(e.to_string())),
+                };
+                let _ = progress.send((idx, FileState::Signing));
+                let state = sign_one(cert, path, options, pin).await;
+                let _ = progress.send((idx, state.clone()));
+                state
+            }),
+        ));
+    }
+
+    for (idx, handle) in handles {
+        states[idx] = handle
+            .await
+            .unwrap_or_else(|e| FileState::Failed(interrupted(e)));
+    }
+    states
+}
+
+/// Экранирует поле CSV
+fn csv_field(value: &str) -> String {
+    if value.contains([';', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Пишет итоговый отчёт в папку в двух форматах: CSV и JSON.
+/// Возвращает пути к обоим файлам.
+pub fn write_report(
+    folder: &Path,
+    files: &[PathBuf],
+    states: &[FileState],
+) -> Result<(PathBuf, PathBuf), String> {
+    let rows: Vec<ReportRow> = files
+        .iter()
+        .zip(states)
+        .map(|(file, state)| {
+            let (status, signature, error) = match state {
+                FileState::Signed(sig) => ("signed", sig.display().to_string(), String::new()),
+                FileState::Skipped => ("skipped", String::new(), String::new()),
+                FileState::Failed(e) => ("failed", String::new(), e.to_string()),
+                FileState::Pending | FileState::Signing => ("pending", String::new(), String::new()),
+            };
+            ReportRow {
+                file: file.display().to_string(),
+                status,
+                signature,
+                error,
+            }
+        })
+        .collect();
+
+    let stamp = Local::now().format("%Y%m%d_%H%M%S");
+    let csv_path = folder.join(format!("{}{}.csv", REPORT_PREFIX, stamp));
+    let json_path = folder.join(format!("{}{}.json", REPORT_PREFIX, stamp));
+
+    let mut csv = String::from("file;status;signature;error\n");
+    for row in &rows {
+        csv.push_str(&format!(
+            "{};{};{};{}\n",
+            csv_field(&row.file),
+            row.status,
+            csv_field(&row.signature),
+            csv_field(&row.error)
+        ));
+    }
+    std::fs::write(&csv_path, csv)
+        .map_err(|e| format!("Не удалось записать отчёт {}: {}", csv_path.display(), e))?;
+
+    let json = serde_json::to_string_pretty(&rows)
+        .map_err(|e| format!("Не удалось сериализовать отчёт: {}", e))?;
+    std::fs::write(&json_path, json)
+        .map_err(|e| format!("Не удалось записать отчёт {}: {}", json_path.display(), e))?;
+
+    Ok((csv_path, json_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("czn-batch-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn skips_signatures_reports_and_partials() {
+        let dir = temp_dir("collect");
+        for name in ["a.pdf", "a.pdf.sig", "b.xml.p7s", "c.txt.sig.partial", "nested/d.doc"] {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, b"x").unwrap();
+        }
+        std::fs::write(dir.join(format!("{}1.csv", REPORT_PREFIX)), b"x").unwrap();
+
+        assert_eq!(collect_files(&dir, false).unwrap(), vec![dir.join("a.pdf")]);
+        assert_eq!(
+            collect_files(&dir, true).unwrap(),
+            vec![dir.join("a.pdf"), dir.join("nested").join("d.doc")]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn does_not_follow_directory_links() {
+        let dir = temp_dir("loop");
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("sub").join("file.txt"), b"x").unwrap();
+        // sub/back → папка выше: петля
+        std::os::unix::fs::symlink(&dir, dir.join("sub").join("back")).unwrap();
+
+        assert_eq!(
+            collect_files(&dir, true).unwrap(),
+            vec![dir.join("sub").join("file.txt")]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -37,7 +37,20 @@ impl fmt::Display for CertificateInfo {
     }
 }
 
+impl CertificateInfo {
+    /// Срок действия сертификата (UTC)
+    pub fn validity(&self) -> Option<(chrono::DateTime<chrono::Utc>, chrono::DateTime<chrono::Utc>)> {
+        let not_before = filetime_to_system_time(self.not_before)?;
+        let not_after = filetime_to_system_time(self.not_after)?;
+        Some((not_before.into(), not_after.into()))
+    }
+}
+
 pub fn find_certificates() -> Vec<CertificateInfo> {
+    if crate::demo::is_active() {
+        return crate::demo::certificates();
+    }
+
     let mut certificates = Vec::new();
 
     unsafe {
@@ -194,6 +207,20 @@ fn format_file_time(file_time: FILETIME) -> String {
     }
 }
 
+/// Обратное преобразование — для учебных сертификатов демо-режима
+pub(crate) fn system_time_to_filetime(time: SystemTime) -> FILETIME {
+    const WINDOWS_TO_UNIX_EPOCH_DIFF_SECS: u64 = 11_644_473_600;
+    let unix_ticks = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| (d.as_nanos() / 100) as u64)
+        .unwrap_or_default();
+    let ticks = unix_ticks + WINDOWS_TO_UNIX_EPOCH_DIFF_SECS * 10_000_000;
+    FILETIME {
+        dwLowDateTime: ticks as u32,
+        dwHighDateTime: (ticks >> 32) as u32,
+    }
+}
+
 fn filetime_to_system_time(file_time: FILETIME) -> Option<SystemTime> {
     // FILETIME is 100-nanosecond intervals since Jan 1, 1601 (UTC)
     const WINDOWS_TO_UNIX_EPOCH_DIFF_SECS: u64 = 11_644_473_600;
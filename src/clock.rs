@@ -0,0 +1,148 @@
+// src/clock.rs
+
+use crate::certificate::CertificateInfo;
+use chrono::{DateTime, Duration, Local, Utc};
+use reqwest::header::{HeaderMap, DATE};
+
+/// Время сервера из заголовка Date (RFC 7231: "Sun, 06 Nov 1994 08:49:37 GMT")
+pub fn server_time(headers: &HeaderMap) -> Option<DateTime<Utc>> {
+    let value = headers.get(DATE)?.to_str().ok()?;
+    DateTime::parse_from_rfc2822(value)
+        .ok()
+        .map(|time| time.with_timezone(&Utc))
+}
+
+/// "3 ч 12 мин", "4 мин 05 с", "12 с"
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.num_seconds().abs();
+    let (hours, minutes, seconds) = (secs / 3600, secs % 3600 / 60, secs % 60);
+    if hours > 0 {
+        format!("{} ч {:02} мин", hours, minutes)
+    } else if minutes > 0 {
+        format!("{} мин {:02} с", minutes, seconds)
+    } else {
+        format!("{} с", seconds)
+    }
+}
+
+fn local_text(time: DateTime<Utc>) -> String {
+    time.with_timezone(&Local).format("%d.%m.%Y %H:%M:%S").to_string()
+}
+
+/// Сверяет часы компьютера со временем сервера и сроком действия сертификата.
+/// Возвращает понятное описание проблемы, из-за которой вход не пройдёт.
+/// `threshold_secs` = 0 отключает проверку расхождения часов.
+pub fn check(
+    server: DateTime<Utc>,
+    local: DateTime<Utc>,
+    cert: &CertificateInfo,
+    threshold_secs: u32,
+) -> Result<(), String> {
+    if let Some((not_before, not_after)) = cert.validity() {
+        if server < not_before || server > not_after {
+            return Err(format!(
+                "Сертификат действителен с {} по {}, а время сервера {}: вход этим сертификатом невозможен",
+                local_text(not_before),
+                local_text(not_after),
+                local_text(server)
+            ));
+        }
+        if local < not_before || local > not_after {
+            return Err(format!(
+                "По часам компьютера ({}) сертификат недействителен (срок {} — {}), хотя по времени сервера ({}) он действует. \
+                 Проверьте дату и время на компьютере",
+                local_text(local),
+                local_text(not_before),
+                local_text(not_after),
+                local_text(server)
+            ));
+        }
+    }
+
+    let skew = local - server;
+    if threshold_secs > 0 && skew.num_seconds().abs() > threshold_secs as i64 {
+        let direction = if skew > Duration::zero() { "спешат" } else { "отстают" };
+        return Err(format!(
+            "Часы компьютера {} на {} (на компьютере {}, на сервере {}). \
+             Синхронизируйте время (например, w32tm /resync) и повторите вход",
+            direction,
+            format_duration(skew),
+            local_text(local),
+            local_text(server)
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cert(not_before: DateTime<Utc>, not_after: DateTime<Utc>) -> CertificateInfo {
+        CertificateInfo {
+            subject_name: "CN=Тест".to_string(),
+            issuer_name: "CN=Тестовый УЦ".to_string(),
+            serial_number: "01".to_string(),
+            thumbprint: "AA".to_string(),
+            valid_from: String::new(),
+            valid_to: String::new(),
+            not_before: crate::certificate::system_time_to_filetime(not_before.into()),
+            not_after: crate::certificate::system_time_to_filetime(not_after.into()),
+        }
+    }
+
+    fn valid_cert(now: DateTime<Utc>) -> CertificateInfo {
+        cert(now - Duration::days(30), now + Duration::days(30))
+    }
+
+    #[test]
+    fn accepts_small_skew() {
+        let server = Utc::now();
+        let local = server + Duration::seconds(30);
+        assert_eq!(check(server, local, &valid_cert(server), 300), Ok(()));
+    }
+
+    #[test]
+    fn reports_fast_and_slow_clock() {
+        let server = Utc::now();
+        let fast = check(server, server + Duration::minutes(10), &valid_cert(server), 300).unwrap_err();
+        assert!(fast.contains("спешат на 10 мин 00 с"), "{}", fast);
+
+        let slow = check(server, server - Duration::hours(2), &valid_cert(server), 300).unwrap_err();
+        assert!(slow.contains("отстают на 2 ч 00 мин"), "{}", slow);
+    }
+
+    #[test]
+    fn zero_threshold_disables_skew_check() {
+        let server = Utc::now();
+        assert_eq!(check(server, server + Duration::hours(3), &valid_cert(server), 0), Ok(()));
+    }
+
+    #[test]
+    fn expired_certificate_by_server_time() {
+        let server = Utc::now();
+        let expired = cert(server - Duration::days(60), server - Duration::days(1));
+        let error = check(server, server, &expired, 300).unwrap_err();
+        assert!(error.contains("вход этим сертификатом невозможен"), "{}", error);
+    }
+
+    #[test]
+    fn certificate_invalid_only_by_local_clock() {
+        let server = Utc::now();
+        let cert = cert(server - Duration::days(1), server + Duration::days(1));
+        let error = check(server, server + Duration::days(2), &cert, 0).unwrap_err();
+        assert!(error.contains("Проверьте дату и время"), "{}", error);
+    }
+
+    #[test]
+    fn server_time_from_date_header() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(server_time(&headers), None);
+        headers.insert(DATE, "Sun, 06 Nov 1994 08:49:37 GMT".parse().unwrap());
+        assert_eq!(
+            server_time(&headers).unwrap().to_rfc3339(),
+            "1994-11-06T08:49:37+00:00"
+        );
+    }
+}
@@ -0,0 +1,729 @@
+// src/demo.rs
+
+//! Демо-режим: встроенная имитация True API, учебные сертификаты и подпись
+//! без КриптоПро. Позволяет обучать сотрудников и проверять приложение
+//! без сети и без настоящей ЭП.
+//!
+//! Сервер запускается при первом обращении в отдельном потоке на 127.0.0.1
+//! и поддерживает `/auth/key`, `/auth/simpleSignIn[/{omsConnection}]`,
+//! `/dispenser/tasks` (создание, статус, список, отмена и удаление) и скачивание
+//! CSV по готовой задаче.
+
+use crate::certificate::CertificateInfo;
+use crate::signing::SignatureKind;
+use base64::Engine;
+use chrono::{Duration as ChronoDuration, Utc};
+use once_cell::sync::{Lazy, OnceCell};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Через сколько после создания задача переходит в COMPLETED
+const TASK_PREPARATION: Duration = Duration::from_secs(20);
+/// Срок жизни демо-токена True API
+const TOKEN_LIFETIME: ChronoDuration = ChronoDuration::hours(10);
+/// Метка подписи демо-подписанта
+const SIGNATURE_MARK: &str = "CZN-DEMO-SIGNATURE";
+/// Ограничение на размер запроса к демо-серверу
+const MAX_REQUEST: usize = 1024 * 1024;
+
+/// Учебная организация: сертификат и ИНН
+struct DemoOrg {
+    subject: &'static str,
+    inn: &'static str,
+    thumbprint: &'static str,
+}
+
+const ORGS: [DemoOrg; 2] = [
+    DemoOrg {
+        subject: "CN=ООО «Ромашка» (демо), O=ООО «Ромашка», SN=Петров, G=Пётр Петрович, INN=7701234567, L=Москва",
+        inn: "7701234567",
+        thumbprint: "DE0000000000000000000000000000000000A001",
+    },
+    DemoOrg {
+        subject: "CN=ИП Иванов Иван Иванович (демо), SN=Иванов, G=Иван Иванович, INN=771234567890, L=Тверь",
+        inn: "771234567890",
+        thumbprint: "DE0000000000000000000000000000000000A002",
+    },
+];
+
+/// Включён ли демо-режим
+pub fn is_active() -> bool {
+    crate::settings::environment() == crate::settings::Environment::Demo
+}
+
+// --- Учебные сертификаты ---
+
+/// Сертификаты учебных организаций: выданы месяц назад, действуют ещё 11 месяцев
+pub fn certificates() -> Vec<CertificateInfo> {
+    let now = Utc::now();
+    let not_before = now - ChronoDuration::days(30);
+    let not_after = now + ChronoDuration::days(335);
+
+    ORGS.iter()
+        .enumerate()
+        .map(|(i, org)| CertificateInfo {
+            subject_name: org.subject.to_string(),
+            issuer_name: "CN=Учебный УЦ (демо), O=czn-dioxus".to_string(),
+            serial_number: format!("DE{:030}", i + 1),
+            thumbprint: org.thumbprint.to_string(),
+            valid_from: not_before.format("%d.%m.%Y").to_string(),
+            valid_to: not_after.format("%d.%m.%Y").to_string(),
+            not_before: crate::certificate::system_time_to_filetime(not_before.into()),
+            not_after: crate::certificate::system_time_to_filetime(not_after.into()),
+        })
+        .collect()
+}
+
+// --- Демо-подписант ---
+
+/// Подпись демо-подписанта: отпечаток сертификата и SHA-256 данных.
+/// Такую подпись принимает только демо-сервер.
+fn signature_for(thumbprint: &str, data: &[u8]) -> String {
+    let payload = serde_json::json!({
+        "mark": SIGNATURE_MARK,
+        "thumbprint": thumbprint,
+        "sha256": hex::encode(Sha256::digest(data)),
+    });
+    base64::engine::general_purpose::STANDARD.encode(payload.to_string())
+}
+
+/// Замена cryptcp.exe: «подписывает» файл учебным сертификатом.
+/// Для присоединённой подписи данные сохраняются вместе с подписью.
+pub fn sign_file(
+    cert: &CertificateInfo,
+    input: &Path,
+    output: &Path,
+    kind: SignatureKind,
+) -> Result<(), String> {
+    let data = std::fs::read(input)
+        .map_err(|e| format!("Не удалось прочитать {}: {}", input.display(), e))?;
+    let mut signature = signature_for(&cert.thumbprint, &data);
+    if kind == SignatureKind::Attached {
+        signature.push('.');
+        signature.push_str(&base64::engine::general_purpose::STANDARD.encode(&data));
+    }
+    crate::storage::debug_log(&format!("🎓 Демо-подпись {} → {}", input.display(), output.display()));
+    std::fs::write(output, signature)
+        .map_err(|e| format!("Не удалось записать подпись {}: {}", output.display(), e))
+}
+
+// --- Демо-сервер ---
+
+struct DemoTask {
+    id: String,
+    inn: String,
+    product_group_code: i32,
+    data_start_date: String,
+    data_end_date: String,
+    create_date: String,
+    created: Instant,
+    canceled: bool,
+}
+
+impl DemoTask {
+    fn status(&self) -> &'static str {
+        if self.canceled {
+            "CANCELED"
+        } else if self.created.elapsed() >= TASK_PREPARATION {
+            "COMPLETED"
+        } else {
+            "PREPARATION"
+        }
+    }
+
+    fn to_json(&self, base_url: &str) -> serde_json::Value {
+        let completed = self.status() == "COMPLETED";
+        serde_json::json!({
+            "id": self.id,
+            "name": "VIOLATIONS",
+            "createDate": self.create_date,
+            "currentStatus": self.status(),
+            "dataStartDate": self.data_start_date,
+            "dataEndDate": self.data_end_date,
+            "orgInn": self.inn,
+            "periodicity": "SINGLE",
+            "productGroupCode": self.product_group_code,
+            "downloadingStorageDays": 3,
+            "productGroups": [],
+            "timeoutSecs": TASK_PREPARATION.as_secs(),
+            "downloadUrl": completed.then(|| format!("{}/dispenser/results/{}/file", base_url, self.id)),
+        })
+    }
+
+    /// Учебная выгрузка нарушений
+    fn csv(&self) -> String {
+        let mut csv = String::from("inn;product_group;date;violation_category;violation_kind;gtin;cis\n");
+        for i in 0..10 {
+            csv.push_str(&format!(
+                "{};{};{};{};{};0460{:09};010460{:09}21DEMO{:04}\n",
+                self.inn,
+                self.product_group_code,
+                self.data_start_date,
+                i % 5 + 1,
+                i + 1,
+                i,
+                i,
+                i
+            ));
+        }
+        csv
+    }
+}
+
+#[derive(Default)]
+struct DemoState {
+    /// uuid → данные для подписи из /auth/key
+    challenges: HashMap<String, String>,
+    tasks: Vec<DemoTask>,
+    /// Организации, для которых уже созданы задачи «из личного кабинета»
+    seeded: HashSet<String>,
+}
+
+static STATE: Lazy<Mutex<DemoState>> = Lazy::new(|| Mutex::new(DemoState::default()));
+static BASE_URL: OnceCell<String> = OnceCell::new();
+
+/// Адрес демо-сервера; при первом вызове сервер запускается
+pub fn base_url() -> String {
+    BASE_URL
+        .get_or_init(|| match start() {
+            Ok(url) => url,
+            Err(e) => {
+                eprintln!("🚨 Не удалось запустить демо-сервер: {}", e);
+                // Заведомо недоступный адрес: запросы завершатся ошибкой сети
+                "http://127.0.0.1:9".to_string()
+            }
+        })
+        .clone()
+}
+
+/// Запускает сервер в отдельном потоке со своим runtime,
+/// чтобы он не зависел от того, откуда впервые запрошен адрес
+fn start() -> Result<String, String> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").map_err(|e| e.to_string())?;
+    listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+    let url = format!("http://{}", listener.local_addr().map_err(|e| e.to_string())?);
+
+    let base_url = url.clone();
+    std::thread::Builder::new()
+        .name("czn-demo-api".to_string())
+        .spawn(move || {
+            let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                Ok(runtime) => runtime,
+                Err(e) => {
+                    eprintln!("🚨 Демо-сервер: {}", e);
+                    return;
+                }
+            };
+            runtime.block_on(async move {
+                let listener = match tokio::net::TcpListener::from_std(listener) {
+                    Ok(listener) => listener,
+                    Err(e) => {
+                        eprintln!("🚨 Демо-сервер: {}", e);
+                        return;
+                    }
+                };
+                loop {
+                    if let Ok((stream, _)) = listener.accept().await {
+                        let base_url = base_url.clone();
+                        tokio::spawn(async move {
+                            if let Err(e) = serve(stream, &base_url).await {
+                                crate::storage::debug_log(&format!("🎓 Демо-сервер: {}", e));
+                            }
+                        });
+                    }
+                }
+            });
+        })
+        .map_err(|e| e.to_string())?;
+
+    crate::storage::debug_log(&format!("🎓 Демо-сервер True API запущен: {}", url));
+    Ok(url)
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: HashMap<String, String>,
+    authorization: Option<String>,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn json(status: u16, value: serde_json::Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json; charset=utf-8",
+            body: value.to_string().into_bytes(),
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self::json(status, serde_json::json!({ "error_message": message }))
+    }
+}
+
+/// Читает HTTP/1.1-запрос (заголовки и тело по Content-Length)
+async fn read_request(stream: &mut TcpStream) -> Result<Request, String> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos;
+        }
+        if buf.len() > MAX_REQUEST {
+            return Err("слишком большой запрос".to_string());
+        }
+        let n = stream.read(&mut chunk).await.map_err(|e| e.to_string())?;
+        if n == 0 {
+            return Err("соединение закрыто до конца заголовков".to_string());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let target = request_line.next().unwrap_or_default().to_string();
+
+    let mut content_length = 0;
+    let mut authorization = None;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-length" => content_length = value.trim().parse().unwrap_or(0),
+            "authorization" => authorization = Some(value.trim().to_string()),
+            _ => {}
+        }
+    }
+    if content_length > MAX_REQUEST {
+        return Err("слишком большой запрос".to_string());
+    }
+
+    let mut body = buf[header_end + 4..].to_vec();
+    while body.len() < content_length {
+        let n = stream.read(&mut chunk).await.map_err(|e| e.to_string())?;
+        if n == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+    body.truncate(content_length);
+
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), query),
+        None => (target, ""),
+    };
+    let query = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+    Ok(Request {
+        method,
+        path,
+        query,
+        authorization,
+        body,
+    })
+}
+
+async fn serve(mut stream: TcpStream, base_url: &str) -> Result<(), String> {
+    let request = read_request(&mut stream).await?;
+    let response = route(&request, base_url);
+    crate::storage::debug_log(&format!(
+        "🎓 Демо-сервер: {} {} → {}",
+        request.method, request.path, response.status
+    ));
+
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nDate: {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason(response.status),
+        Utc::now().format("%a, %d %b %Y %H:%M:%S GMT"),
+        response.content_type,
+        response.body.len()
+    );
+    stream.write_all(head.as_bytes()).await.map_err(|e| e.to_string())?;
+    stream.write_all(&response.body).await.map_err(|e| e.to_string())?;
+    stream.shutdown().await.map_err(|e| e.to_string())
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Unknown",
+    }
+}
+
+fn route(request: &Request, base_url: &str) -> Response {
+    let path = request.path.trim_end_matches('/');
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+
+    match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["auth", "key"]) => auth_key(),
+        ("POST", ["auth", "simpleSignIn"]) => sign_in(request, None),
+        ("POST", ["auth", "simpleSignIn", connection]) => sign_in(request, Some(connection)),
+        _ => {
+            let inn = match authorize(request) {
+                Ok(inn) => inn,
+                Err(response) => return response,
+            };
+            match (request.method.as_str(), segments.as_slice()) {
+                ("POST", ["dispenser", "tasks"]) => create_task(request, &inn, base_url),
+                ("GET", ["dispenser", "tasks"]) => list_tasks(request, &inn, base_url),
+                ("GET", ["dispenser", "tasks", id]) => task_status(request, id, &inn, base_url),
+                ("POST", ["dispenser", "tasks", id, "cancel"]) => cancel_task(id, &inn, base_url),
+                ("DELETE", ["dispenser", "tasks", id]) => delete_task(id, &inn),
+                ("GET", ["dispenser", "results", id, "file"]) => download(id, &inn),
+                (_, ["dispenser", ..]) => Response::error(405, "Метод не поддерживается демо-сервером"),
+                _ => Response::error(404, "Неизвестный метод демо-сервера"),
+            }
+        }
+    }
+}
+
+/// Случайная строка из шестнадцатеричных цифр
+fn random_hex(len: usize) -> String {
+    use std::hash::{BuildHasher, Hasher};
+
+    let mut out = String::new();
+    while out.len() < len {
+        let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+        hasher.write_u128(
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or_default(),
+        );
+        out.push_str(&format!("{:016x}", hasher.finish()));
+    }
+    out.truncate(len);
+    out
+}
+
+fn random_uuid() -> String {
+    let hex = random_hex(32);
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+fn auth_key() -> Response {
+    let uuid = random_uuid();
+    let data = random_hex(30).to_uppercase();
+    STATE.lock().unwrap().challenges.insert(uuid.clone(), data.clone());
+    Response::json(200, serde_json::json!({ "uuid": uuid, "data": data }))
+}
+
+fn sign_in(request: &Request, oms_connection: Option<&str>) -> Response {
+    #[derive(serde::Deserialize)]
+    struct SignIn {
+        uuid: String,
+        data: String,
+    }
+    #[derive(serde::Deserialize)]
+    struct DemoSignature {
+        mark: String,
+        thumbprint: String,
+        sha256: String,
+    }
+
+    let Ok(body) = serde_json::from_slice::<SignIn>(&request.body) else {
+        return Response::error(400, "Ожидаются поля uuid и data");
+    };
+    let Some(challenge) = STATE.lock().unwrap().challenges.remove(&body.uuid) else {
+        return Response::error(400, "Неизвестный или уже использованный uuid");
+    };
+    // Вход подписывается присоединённой подписью: «подпись.данные» (см. `sign_file`)
+    let engine = base64::engine::general_purpose::STANDARD;
+    let (signature, attached) = match body.data.trim().split_once('.') {
+        Some((signature, data)) => (signature, Some(data)),
+        None => (body.data.trim(), None),
+    };
+    if attached.is_some_and(|data| engine.decode(data).ok().as_deref() != Some(challenge.as_bytes())) {
+        return Response::error(400, "Подписанные данные не совпадают с данными из /auth/key");
+    }
+    let signature = engine
+        .decode(signature)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice::<DemoSignature>(&bytes).ok());
+    let Some(signature) = signature.filter(|s| s.mark == SIGNATURE_MARK) else {
+        return Response::error(400, "Подпись сделана не демо-подписантом");
+    };
+    if signature.sha256 != hex::encode(Sha256::digest(challenge.as_bytes())) {
+        return Response::error(400, "Подпись не соответствует данным из /auth/key");
+    }
+    let Some(org) = ORGS.iter().find(|o| o.thumbprint == signature.thumbprint) else {
+        return Response::error(401, "Сертификат не зарегистрирован в демо-режиме");
+    };
+
+    let token = match oms_connection {
+        // Токен СУЗ не является JWT
+        Some(_) => random_uuid(),
+        None => demo_jwt(org.inn),
+    };
+    Response::json(200, serde_json::json!({ "token": token }))
+}
+
+/// JWT без подписи с ИНН организации и сроком действия
+fn demo_jwt(inn: &str) -> String {
+    let engine = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    let now = Utc::now();
+    let header = serde_json::json!({ "alg": "none", "typ": "JWT" });
+    let claims = serde_json::json!({
+        "inn": inn,
+        "iat": now.timestamp(),
+        "exp": (now + TOKEN_LIFETIME).timestamp(),
+        "demo": true,
+    });
+    format!(
+        "{}.{}.demo",
+        engine.encode(header.to_string()),
+        engine.encode(claims.to_string())
+    )
+}
+
+/// Проверяет Bearer-токен; возвращает ИНН организации
+fn authorize(request: &Request) -> Result<String, Response> {
+    #[derive(serde::Deserialize)]
+    struct Claims {
+        inn: String,
+        exp: i64,
+    }
+
+    let unauthorized = || Response::error(401, "Требуется токен демо-режима");
+    let token = request
+        .authorization
+        .as_deref()
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or_else(unauthorized)?;
+    let mut parts = token.trim().split('.');
+    let (_, payload, signature) = (parts.next(), parts.next(), parts.next());
+    if signature != Some("demo") {
+        return Err(unauthorized());
+    }
+    let claims = payload
+        .and_then(|p| base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(p).ok())
+        .and_then(|bytes| serde_json::from_slice::<Claims>(&bytes).ok())
+        .ok_or_else(unauthorized)?;
+    if claims.exp < Utc::now().timestamp() {
+        return Err(Response::error(401, "Срок действия токена истёк"));
+    }
+    Ok(claims.inn)
+}
+
+fn create_task(request: &Request, inn: &str, base_url: &str) -> Response {
+    #[derive(serde::Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct NewTask {
+        data_start_date: String,
+        data_end_date: String,
+        product_group_code: i32,
+    }
+
+    let Ok(body) = serde_json::from_slice::<NewTask>(&request.body) else {
+        return Response::error(400, "Некорректное тело запроса на выгрузку");
+    };
+    let task = DemoTask {
+        id: random_uuid(),
+        inn: inn.to_string(),
+        product_group_code: body.product_group_code,
+        data_start_date: body.data_start_date,
+        data_end_date: body.data_end_date,
+        create_date: chrono::Local::now().format("%Y-%m-%d").to_string(),
+        created: Instant::now(),
+        canceled: false,
+    };
+    let json = task.to_json(base_url);
+    STATE.lock().unwrap().tasks.push(task);
+    Response::json(200, json)
+}
+
+fn task_status(request: &Request, id: &str, inn: &str, base_url: &str) -> Response {
+    let pg = request.query.get("pg").and_then(|pg| pg.parse::<i32>().ok());
+    let state = STATE.lock().unwrap();
+    let task = state
+        .tasks
+        .iter()
+        .find(|t| t.id == id && t.inn == inn && pg.is_none_or(|pg| pg == t.product_group_code));
+    match task {
+        Some(task) => Response::json(200, task.to_json(base_url)),
+        None => Response::error(404, "Задача не найдена"),
+    }
+}
+
+/// Отменить можно только задачу, которая ещё готовится
+fn cancel_task(id: &str, inn: &str, base_url: &str) -> Response {
+    let mut state = STATE.lock().unwrap();
+    match state.tasks.iter_mut().find(|t| t.id == id && t.inn == inn) {
+        Some(task) if task.status() == "PREPARATION" => {
+            task.canceled = true;
+            Response::json(200, task.to_json(base_url))
+        }
+        Some(_) => Response::error(400, "Задача уже завершена, отменить её нельзя"),
+        None => Response::error(404, "Задача не найдена"),
+    }
+}
+
+fn delete_task(id: &str, inn: &str) -> Response {
+    let mut state = STATE.lock().unwrap();
+    let before = state.tasks.len();
+    state.tasks.retain(|t| !(t.id == id && t.inn == inn));
+    if state.tasks.len() < before {
+        Response::json(200, serde_json::json!({}))
+    } else {
+        Response::error(404, "Задача не найдена")
+    }
+}
+
+/// Список задач организации с фильтрами status, pg, dateFrom, dateTo и страницами
+fn list_tasks(request: &Request, inn: &str, base_url: &str) -> Response {
+    let query = |name: &str| request.query.get(name).map(|v| v.trim()).filter(|v| !v.is_empty());
+    let page = query("page").and_then(|v| v.parse::<usize>().ok()).unwrap_or(0);
+    let size = query("size").and_then(|v| v.parse::<usize>().ok()).unwrap_or(20).clamp(1, 1000);
+    let pg = query("pg").and_then(|v| v.parse::<i32>().ok());
+
+    let mut state = STATE.lock().unwrap();
+    seed_external_tasks(&mut state, inn);
+
+    let matching: Vec<&DemoTask> = state
+        .tasks
+        .iter()
+        .filter(|t| t.inn == inn)
+        .filter(|t| query("status").is_none_or(|s| s == t.status()))
+        .filter(|t| pg.is_none_or(|pg| pg == t.product_group_code))
+        // Даты в формате ГГГГ-ММ-ДД сравниваются как строки
+        .filter(|t| query("dateFrom").is_none_or(|d| t.create_date.as_str() >= d))
+        .filter(|t| query("dateTo").is_none_or(|d| t.create_date.as_str() <= d))
+        .collect();
+    let list: Vec<serde_json::Value> = matching
+        .iter()
+        .skip(page * size)
+        .take(size)
+        .map(|t| t.to_json(base_url))
+        .collect();
+    Response::json(200, serde_json::json!({ "list": list, "total": matching.len() }))
+}
+
+/// Задачи, будто бы созданные в личном кабинете: чтобы синхронизации было что найти
+fn seed_external_tasks(state: &mut DemoState, inn: &str) {
+    if !state.seeded.insert(inn.to_string()) {
+        return;
+    }
+    let today = chrono::Local::now().date_naive();
+    for (days_ago, product_group_code) in [(1, 12), (3, 5)] {
+        let date = today - ChronoDuration::days(days_ago);
+        state.tasks.push(DemoTask {
+            id: random_uuid(),
+            inn: inn.to_string(),
+            product_group_code,
+            data_start_date: (date - ChronoDuration::days(7)).format("%Y-%m-%d").to_string(),
+            data_end_date: date.format("%Y-%m-%d").to_string(),
+            create_date: date.format("%Y-%m-%d").to_string(),
+            // Давно созданные задачи уже готовы
+            created: Instant::now().checked_sub(TASK_PREPARATION).unwrap_or_else(Instant::now),
+            canceled: false,
+        });
+    }
+}
+
+fn download(id: &str, inn: &str) -> Response {
+    let state = STATE.lock().unwrap();
+    match state.tasks.iter().find(|t| t.id == id && t.inn == inn) {
+        Some(task) if task.status() == "COMPLETED" => Response {
+            status: 200,
+            content_type: "text/csv; charset=utf-8",
+            body: task.csv().into_bytes(),
+        },
+        Some(_) => Response::error(400, "Выгрузка ещё не готова"),
+        None => Response::error(404, "Задача не найдена"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("czn-demo-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Подписывает данные из /auth/key так же, как вход в приложении
+    fn sign_challenge(cert: &CertificateInfo, kind: SignatureKind) -> (String, String) {
+        let key = serde_json::from_slice::<serde_json::Value>(&auth_key().body).unwrap();
+        let uuid = key["uuid"].as_str().unwrap().to_string();
+        let dir = temp_dir(&uuid);
+        let (input, output) = (dir.join("key"), dir.join("key.sig"));
+        std::fs::write(&input, key["data"].as_str().unwrap()).unwrap();
+        sign_file(cert, &input, &output, kind).unwrap();
+        let signature = std::fs::read_to_string(&output).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        (uuid, signature)
+    }
+
+    fn sign_in_request(uuid: &str, signature: &str) -> Request {
+        Request {
+            method: "POST".to_string(),
+            path: "/auth/simpleSignIn".to_string(),
+            query: HashMap::new(),
+            authorization: None,
+            body: serde_json::json!({ "uuid": uuid, "data": signature }).to_string().into_bytes(),
+        }
+    }
+
+    #[test]
+    fn attached_signature_signs_in() {
+        let cert = &certificates()[0];
+        let (uuid, signature) = sign_challenge(cert, SignatureKind::Attached);
+        let response = sign_in(&sign_in_request(&uuid, &signature), None);
+        assert_eq!(response.status, 200);
+
+        let token = serde_json::from_slice::<serde_json::Value>(&response.body).unwrap();
+        let request = Request {
+            authorization: Some(format!("Bearer {}", token["token"].as_str().unwrap())),
+            ..sign_in_request(&uuid, "")
+        };
+        assert_eq!(authorize(&request).ok().as_deref(), Some(ORGS[0].inn));
+    }
+
+    #[test]
+    fn detached_signature_signs_in() {
+        let (uuid, signature) = sign_challenge(&certificates()[1], SignatureKind::Detached);
+        assert_eq!(sign_in(&sign_in_request(&uuid, &signature), None).status, 200);
+    }
+
+    #[test]
+    fn attached_data_must_match_challenge() {
+        let (uuid, signature) = sign_challenge(&certificates()[0], SignatureKind::Attached);
+        let (signature, _) = signature.split_once('.').unwrap();
+        let forged = format!("{}.{}", signature, base64::engine::general_purpose::STANDARD.encode("other"));
+        assert_eq!(sign_in(&sign_in_request(&uuid, &forged), None).status, 400);
+    }
+
+    #[test]
+    fn challenge_is_single_use() {
+        let (uuid, signature) = sign_challenge(&certificates()[0], SignatureKind::Attached);
+        assert_eq!(sign_in(&sign_in_request(&uuid, &signature), None).status, 200);
+        assert_eq!(sign_in(&sign_in_request(&uuid, &signature), None).status, 400);
+    }
+}
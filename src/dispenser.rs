@@ -1,425 +1,1161 @@
 // src/dispenser.rs
 
-use crate::signing;
-use chrono::{Datelike, Duration, Local, NaiveDate};
-use reqwest;
-use serde::Serialize;
-use std::io::Write;
-use std::sync::Mutex;
+use crate::api::{TaskRequest, TaskStatusResponse, TrueApiClient};
+use crate::error::{ApiError, DownloadError};
+use crate::storage::{self, debug_log, StoredToken};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
 use once_cell::sync::Lazy;
 use tokio::task;
 
-// --- Потокобезопасное хранилище задач ---
-static TASKS: Lazy<Mutex<Vec<TaskInfo>>> = Lazy::new(|| Mutex::new(Vec::new()));
-
-// --- Логирование в файл через storage ---
-fn debug_log(msg: &str) {
-    let msg = msg.to_string();
-    std::thread::spawn(move || {
-        let log_path = match crate::storage::log_path() {
-            Ok(p) => p,
-            Err(_) => return,
-        };
+// --- Потокобезопасное хранилище задач (копия реестра на диске) ---
+static TASKS: Lazy<Mutex<Registry>> = Lazy::new(|| {
+    Mutex::new(Registry {
+        path: None,
+        tasks: Vec::new(),
+    })
+});
 
-        let _ = std::fs::OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(log_path)
-            .and_then(|mut file| {
-                let timestamp = Local::now().format("[%Y-%m-%d %H:%M:%S]").to_string();
-                writeln!(file, "{} {}", timestamp, msg)
-            });
-    });
+/// Реестр задач и файл, из которого он прочитан: у каждого контура свой файл
+struct Registry {
+    path: Option<PathBuf>,
+    tasks: Vec<TaskInfo>,
 }
 
 #[derive(Clone, Debug)]
 pub struct TaskStatusForUI {
     pub id: String,
+    pub org_inn: String,
     pub product_group_code: i32,
     pub status: String,
     pub create_date: String,
     pub is_completed: bool,
     pub error: Option<String>,
+    /// Готовый результат уже скачан сюда (файл или папка с архивом)
+    pub downloaded: Option<PathBuf>,
+    /// До какого дня результат хранится на сервере
+    pub expires_on: Option<NaiveDate>,
+    /// Ошибка последней автоматической загрузки
+    pub download_error: Option<String>,
 }
 
 impl TaskStatusForUI {
     pub fn display_name(&self) -> &'static str {
-        match self.product_group_code {
-            1 => "Одежда и бельё",
-            2 => "Обувь",
-            3 => "Табачная продукция",
-            4 => "Духи и туалетная вода",
-            5 => "Шины",
-            6 => "Фотокамеры и вспышки",
-            8 => "Молочная продукция",
-            9 => "Велосипеды",
-            10 => "Медицинские изделия",
-            11 => "Алкоголь",
-            12 => "Альтернативная табачная продукция",
-            13 => "Упакованная вода",
-            14 => "Товары из меха",
-            15 => "Пиво и слабоалкогольные напитки",
-            16 => "Никотиносодержащая продукция",
-            17 => "БАДы",
-            19 => "Антисептики",
-            20 => "Корма для животных",
-            21 => "Морепродукты",
-            22 => "Безалкогольное пиво",
-            23 => "Соки и безалкогольные напитки",
-            25 => "Мясные изделия",
-            26 => "Ветеринарные препараты",
-            27 => "Игрушки",
-            28 => "Радиоэлектроника",
-            31 => "Титановая продукция",
-            32 => "Консервы",
-            33 => "Растительные масла",
-            34 => "Оптоволокно",
-            35 => "Косметика и бытовая химия",
-            36 => "Печатная продукция",
-            37 => "Бакалея",
-            38 => "Фармсырьё и лекарства",
-            39 => "Строительные материалы",
-            40 => "Пиротехника и огнетушители",
-            41 => "Отопительные приборы",
-            42 => "Кабельная продукция",
-            43 => "Моторные масла",
-            44 => "Полимерные трубы",
-            45 => "Конфеты и сладости",
-            48 => "Автозапчасти",
-            50 => "Электронные системы доставки никотина",
-            51 => "Смартфоны и ноутбуки",
-            _ => "Неизвестно",
-        }
-    }
-}
-
-// --- Запрос на выгрузку ---
-#[derive(Serialize, Clone)]
-struct TaskRequest {
-    #[serde(rename = "name")]
-    name: String,
-    #[serde(rename = "dataStartDate")]
-    data_start_date: String,
-    #[serde(rename = "dataEndDate")]
-    data_end_date: String,
-    #[serde(rename = "format")]
-    format: String,
-    #[serde(rename = "periodicity")]
-    periodicity: String,
-    #[serde(rename = "params")]
-    params: String,
-    #[serde(rename = "productGroupCode")]
-    product_group_code: i32,
-}
-
-// --- Ответ на создание задачи ---
-#[derive(serde::Deserialize, Clone, Debug)]
-pub struct TaskResponse {
-    #[serde(rename = "id")]
-    pub id: String,
-    #[serde(rename = "name")]
-    pub name: String,
-    #[serde(rename = "createDate")]
-    pub create_date: String,
-    #[serde(rename = "currentStatus")]
-    pub current_status: String,
-    #[serde(rename = "dataStartDate")]
-    pub data_start_date: String,
-    #[serde(rename = "dataEndDate")]
-    pub data_end_date: String,
-    #[serde(rename = "orgInn")]
-    pub org_inn: String,
-    #[serde(rename = "periodicity")]
-    pub periodicity: String,
-    #[serde(rename = "productGroupCode")]
-    pub product_group_code: i32,
-    #[serde(rename = "timeoutSecs")]
-    pub timeout_secs: i32,
+        product_group_name(self.product_group_code)
+    }
+
+    /// Задача ещё готовится: её можно отменить
+    pub fn is_in_progress(&self) -> bool {
+        !self.is_completed && self.error.is_none() && !is_final_status(&self.status)
+    }
+
+    /// Название статуса для пользователя
+    pub fn status_label(&self) -> &str {
+        TASK_STATUSES
+            .iter()
+            .find(|(status, _)| *status == self.status)
+            .map(|(_, label)| *label)
+            .unwrap_or(&self.status)
+    }
+
+    /// Готовый результат не скачан, а срок хранения на сервере подходит к концу
+    pub fn expires_soon(&self, today: NaiveDate, warn_days: u32) -> bool {
+        self.is_completed
+            && self.downloaded.is_none()
+            && self
+                .expires_on
+                .is_some_and(|date| (date - today).num_days() <= warn_days as i64)
+    }
+}
+
+/// Товарные группы True API: код и название
+pub const PRODUCT_GROUPS: &[(i32, &str)] = &[
+    (1, "Одежда и бельё"),
+    (2, "Обувь"),
+    (3, "Табачная продукция"),
+    (4, "Духи и туалетная вода"),
+    (5, "Шины"),
+    (6, "Фотокамеры и вспышки"),
+    (8, "Молочная продукция"),
+    (9, "Велосипеды"),
+    (10, "Медицинские изделия"),
+    (11, "Алкоголь"),
+    (12, "Альтернативная табачная продукция"),
+    (13, "Упакованная вода"),
+    (14, "Товары из меха"),
+    (15, "Пиво и слабоалкогольные напитки"),
+    (16, "Никотиносодержащая продукция"),
+    (17, "БАДы"),
+    (19, "Антисептики"),
+    (20, "Корма для животных"),
+    (21, "Морепродукты"),
+    (22, "Безалкогольное пиво"),
+    (23, "Соки и безалкогольные напитки"),
+    (25, "Мясные изделия"),
+    (26, "Ветеринарные препараты"),
+    (27, "Игрушки"),
+    (28, "Радиоэлектроника"),
+    (31, "Титановая продукция"),
+    (32, "Консервы"),
+    (33, "Растительные масла"),
+    (34, "Оптоволокно"),
+    (35, "Косметика и бытовая химия"),
+    (36, "Печатная продукция"),
+    (37, "Бакалея"),
+    (38, "Фармсырьё и лекарства"),
+    (39, "Строительные материалы"),
+    (40, "Пиротехника и огнетушители"),
+    (41, "Отопительные приборы"),
+    (42, "Кабельная продукция"),
+    (43, "Моторные масла"),
+    (44, "Полимерные трубы"),
+    (45, "Конфеты и сладости"),
+    (48, "Автозапчасти"),
+    (50, "Электронные системы доставки никотина"),
+    (51, "Смартфоны и ноутбуки"),
+];
+
+/// Название товарной группы по коду
+pub fn product_group_name(code: i32) -> &'static str {
+    PRODUCT_GROUPS
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, name)| *name)
+        .unwrap_or("Неизвестно")
 }
 
 // --- Хранение задачи ---
-#[derive(Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct TaskInfo {
     pub id: String,
+    /// Сессия (ИНН + сертификат), от имени которой создана задача
+    pub session_key: String,
+    pub org_inn: String,
     pub product_group_code: i32,
     pub data_start_date: String,
     pub data_end_date: String,
+    #[serde(default)]
+    pub format: ExportFormat,
+    #[serde(default)]
+    pub periodicity: Periodicity,
+    /// Поле `params` запроса: категории и виды нарушений (JSON)
+    #[serde(default)]
+    pub params: String,
     pub status: String,
+    /// Смены статуса, от создания задачи
+    #[serde(default)]
+    pub history: Vec<StatusChange>,
     pub create_date: NaiveDate,
+    /// До какого дня результат хранится на сервере
+    #[serde(default)]
+    pub expires_on: Option<NaiveDate>,
+    /// Куда скачан результат
+    #[serde(default)]
+    pub downloaded: Option<PathBuf>,
+    /// Неудачные попытки автоматической загрузки и последняя ошибка
+    #[serde(default)]
+    pub auto_download_attempts: u32,
+    #[serde(default)]
+    pub download_error: Option<String>,
+    /// Задача загружена из списка на сервере, а не создана в приложении:
+    /// её результат скачивается только вручную
+    #[serde(default)]
+    pub synced: bool,
 }
 
-// --- Ответ на GET /tasks/{id} ---
-#[derive(serde::Deserialize, Clone, Debug)]
-pub struct ProductGroup {
-    #[serde(rename = "id")]
-    pub id: String,
-    #[serde(rename = "name")]
-    pub name: String,
+/// Запись истории статусов задачи
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct StatusChange {
+    pub status: String,
+    pub at: DateTime<Utc>,
 }
 
-#[derive(serde::Deserialize, Clone, Debug)]
-pub struct TaskStatusResponse {
-    #[serde(rename = "id")]
-    pub id: String,
-    #[serde(rename = "name")]
+impl TaskInfo {
+    /// Статус больше не изменится: опрашивать сервер незачем
+    fn is_final(&self) -> bool {
+        match self.status.as_str() {
+            "COMPLETED" => self.downloaded.is_some(),
+            status => is_final_status(status),
+        }
+    }
+
+    /// Запоминает новый статус; возвращает true, если он изменился
+    fn set_status(&mut self, status: &str) -> bool {
+        if self.status == status {
+            return false;
+        }
+        self.status = status.to_string();
+        self.history.push(StatusChange {
+            status: status.to_string(),
+            at: Utc::now(),
+        });
+        true
+    }
+}
+
+/// Задача в этом статусе больше не выполняется
+fn is_final_status(status: &str) -> bool {
+    matches!(status, "CANCELED" | "ARCHIVED" | "FAILED")
+}
+
+// --- Реестр задач на диске ---
+
+/// Реестр текущего контура; после смены контура в настройках перечитывается
+/// из его файла, чтобы задачи разных контуров не смешивались
+fn registry() -> MutexGuard<'static, Registry> {
+    let mut registry = TASKS.lock().unwrap();
+    let path = storage::tasks_path().ok();
+    if registry.path != path {
+        registry.tasks = path.as_deref().map(load_registry).unwrap_or_default();
+        registry.path = path;
+    }
+    registry
+}
+
+/// Читает реестр; повреждённый файл откладывается в сторону, а не затирается молча
+fn load_registry(path: &Path) -> Vec<TaskInfo> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(_) => return Vec::new(),
+    };
+    match serde_json::from_str::<Vec<TaskInfo>>(&text) {
+        Ok(mut tasks) => {
+            apply_retention(&mut tasks);
+            tasks
+        }
+        Err(e) => {
+            eprintln!("⚠️ Реестр задач {} повреждён: {}", path.display(), e);
+            let _ = std::fs::rename(path, path.with_extension("json.bak"));
+            Vec::new()
+        }
+    }
+}
+
+/// Записывает реестр в его файл через временный, чтобы сбой не оставил его наполовину записанным
+fn persist(registry: &Registry) {
+    let Some(path) = &registry.path else {
+        return;
+    };
+    let result = serde_json::to_string_pretty(&registry.tasks)
+        .map_err(|e| format!("Не удалось сериализовать реестр задач: {}", e))
+        .and_then(|json| {
+            let tmp = path.with_extension("json.tmp");
+            std::fs::write(&tmp, json)
+                .and_then(|_| std::fs::rename(&tmp, path))
+                .map_err(|e| format!("Не удалось сохранить реестр задач {}: {}", path.display(), e))
+        });
+    if let Err(e) = result {
+        eprintln!("⚠️ {}", e);
+    }
+}
+
+/// Изменяет реестр и сразу сохраняет его на диск
+fn with_tasks<R>(f: impl FnOnce(&mut Vec<TaskInfo>) -> R) -> R {
+    let mut registry = registry();
+    let result = f(&mut registry.tasks);
+    persist(&registry);
+    result
+}
+
+/// Убирает задачи старше срока хранения из настроек (0 — хранить всё)
+fn apply_retention(tasks: &mut Vec<TaskInfo>) {
+    let days = crate::settings::load().task_retention_days;
+    let today = Local::now().date_naive();
+    tasks.retain(|t| within_retention(t.create_date, days, today));
+}
+
+/// Задача, созданная в этот день, ещё хранится в реестре
+fn within_retention(create_date: NaiveDate, days: u32, today: NaiveDate) -> bool {
+    days == 0 || (today - create_date).num_days() < days as i64
+}
+
+/// Сколько раз пытаться скачать результат автоматически
+const AUTO_DOWNLOAD_ATTEMPTS: u32 = 3;
+
+/// Последний день хранения результата: от даты создания задачи
+/// (дата готовности в ответе не приходит, поэтому берём раннюю оценку)
+fn expiry_date(create_date: &str, storage_days: i32) -> Option<NaiveDate> {
+    if storage_days <= 0 {
+        return None;
+    }
+    let date = NaiveDate::parse_from_str(create_date.get(..10)?, "%Y-%m-%d").ok()?;
+    Some(date + Duration::days(storage_days.into()))
+}
+
+// --- Параметры выгрузки ---
+
+/// Период данных выгрузки
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PeriodPreset {
+    /// Прошлая неделя (пн—вс)
+    #[default]
+    LastWeek,
+    /// Вчерашний день
+    Yesterday,
+    /// Прошлый календарный месяц
+    LastMonth,
+    /// Даты из `custom_start` / `custom_end`
+    Custom,
+}
+
+impl PeriodPreset {
+    pub const ALL: [PeriodPreset; 4] = [
+        PeriodPreset::LastWeek,
+        PeriodPreset::Yesterday,
+        PeriodPreset::LastMonth,
+        PeriodPreset::Custom,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PeriodPreset::LastWeek => "Прошлая неделя",
+            PeriodPreset::Yesterday => "Вчера",
+            PeriodPreset::LastMonth => "Прошлый месяц",
+            PeriodPreset::Custom => "Произвольный период",
+        }
+    }
+
+    pub fn slug(&self) -> &'static str {
+        match self {
+            PeriodPreset::LastWeek => "last_week",
+            PeriodPreset::Yesterday => "yesterday",
+            PeriodPreset::LastMonth => "last_month",
+            PeriodPreset::Custom => "custom",
+        }
+    }
+
+    pub fn from_slug(slug: &str) -> Option<PeriodPreset> {
+        PeriodPreset::ALL.into_iter().find(|p| p.slug() == slug.trim())
+    }
+}
+
+/// Формат файла выгрузки
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Xlsx,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 2] = [ExportFormat::Csv, ExportFormat::Xlsx];
+
+    /// Значение поля `format` в запросе к API
+    pub fn api_value(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "CSV",
+            ExportFormat::Xlsx => "XLSX",
+        }
+    }
+
+    pub fn from_api_value(value: &str) -> Option<ExportFormat> {
+        ExportFormat::ALL.into_iter().find(|f| f.api_value() == value)
+    }
+}
+
+/// Периодичность задачи выгрузки
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Periodicity {
+    /// Разовая выгрузка
+    #[default]
+    Single,
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+impl Periodicity {
+    pub const ALL: [Periodicity; 4] = [
+        Periodicity::Single,
+        Periodicity::Daily,
+        Periodicity::Weekly,
+        Periodicity::Monthly,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Periodicity::Single => "Разовая",
+            Periodicity::Daily => "Ежедневно",
+            Periodicity::Weekly => "Еженедельно",
+            Periodicity::Monthly => "Ежемесячно",
+        }
+    }
+
+    /// Значение поля `periodicity` в запросе к API
+    pub fn api_value(&self) -> &'static str {
+        match self {
+            Periodicity::Single => "SINGLE",
+            Periodicity::Daily => "DAILY",
+            Periodicity::Weekly => "WEEKLY",
+            Periodicity::Monthly => "MONTHLY",
+        }
+    }
+
+    pub fn from_api_value(value: &str) -> Option<Periodicity> {
+        Periodicity::ALL.into_iter().find(|p| p.api_value() == value)
+    }
+}
+
+/// Параметры выгрузки нарушений (текущие хранятся в настройках)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct ExportParams {
+    /// Коды товарных групп; по каждой создаётся отдельная задача
+    pub product_groups: Vec<i32>,
+    pub period: PeriodPreset,
+    /// Начало произвольного периода, ГГГГ-ММ-ДД
+    pub custom_start: String,
+    /// Конец произвольного периода, ГГГГ-ММ-ДД
+    pub custom_end: String,
+    /// Категории нарушений (violationCategory)
+    pub violation_categories: Vec<i32>,
+    /// Виды нарушений (violationKind)
+    pub violation_kinds: Vec<i32>,
+    pub format: ExportFormat,
+    pub periodicity: Periodicity,
+}
+
+impl Default for ExportParams {
+    fn default() -> Self {
+        Self {
+            product_groups: vec![12, 16, 20],
+            period: PeriodPreset::LastWeek,
+            custom_start: String::new(),
+            custom_end: String::new(),
+            violation_categories: (1..=20).collect(),
+            violation_kinds: (1..=60).collect(),
+            format: ExportFormat::Csv,
+            periodicity: Periodicity::Single,
+        }
+    }
+}
+
+impl ExportParams {
+    /// Даты начала и конца периода относительно `today`
+    pub fn date_range(&self, today: NaiveDate) -> Result<(NaiveDate, NaiveDate), String> {
+        let (start, end) = match self.period {
+            PeriodPreset::LastWeek => {
+                let current_week_start =
+                    today - Duration::days(today.weekday().num_days_from_monday().into());
+                let start = current_week_start - Duration::days(7);
+                (start, start + Duration::days(6))
+            }
+            PeriodPreset::Yesterday => {
+                let yesterday = today - Duration::days(1);
+                (yesterday, yesterday)
+            }
+            PeriodPreset::LastMonth => {
+                let month_start = today.with_day(1).unwrap_or(today);
+                let end = month_start - Duration::days(1);
+                (end.with_day(1).unwrap_or(end), end)
+            }
+            PeriodPreset::Custom => {
+                let parse = |value: &str, what: &str| {
+                    NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+                        .map_err(|_| format!("Некорректная дата {} периода: «{}»", what, value))
+                };
+                (parse(&self.custom_start, "начала")?, parse(&self.custom_end, "конца")?)
+            }
+        };
+        if start > end {
+            return Err("Начало периода позже его конца".to_string());
+        }
+        if end >= today {
+            return Err("Период должен закончиться до сегодняшнего дня".to_string());
+        }
+        Ok((start, end))
+    }
+
+    /// Проверяет параметры перед отправкой
+    pub fn validate(&self) -> Result<(), String> {
+        if self.product_groups.is_empty() {
+            return Err("Не выбрана ни одна товарная группа".to_string());
+        }
+        if self.violation_categories.is_empty() || self.violation_kinds.is_empty() {
+            return Err("Не заданы категории или виды нарушений".to_string());
+        }
+        self.date_range(Local::now().date_naive()).map(|_| ())
+    }
+
+    /// Краткое описание для интерфейса
+    pub fn summary(&self) -> String {
+        let period = match self.date_range(Local::now().date_naive()) {
+            Ok((start, end)) => format!("{}—{}", start.format("%d.%m.%Y"), end.format("%d.%m.%Y")),
+            Err(e) => e,
+        };
+        format!(
+            "{}: {}, товарных групп: {}, {}, {}",
+            self.period.label(),
+            period,
+            self.product_groups.len(),
+            self.format.api_value(),
+            self.periodicity.label().to_lowercase()
+        )
+    }
+}
+
+/// Именованный набор параметров выгрузки
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(default)]
+pub struct ExportProfile {
     pub name: String,
-    #[serde(rename = "createDate")]
-    pub create_date: String,
-    #[serde(rename = "currentStatus")]
-    pub current_status: String,
-    #[serde(rename = "orgInn")]
-    pub org_inn: String,
-    #[serde(rename = "productGroupCode")]
-    pub product_group_code: i32,
-    #[serde(rename = "downloadingStorageDays")]
-    pub downloading_storage_days: i32,
-    #[serde(rename = "productGroups")]
-    pub product_groups: Vec<ProductGroup>,
-    #[serde(rename = "timeoutSecs")]
-    pub timeout_secs: i32,
-    #[serde(rename = "downloadUrl")]
-    pub download_url: Option<String>,
-}
-
-// --- Конфиг ---
-const PRODUCT_GROUP_CODES: [i32; 3] = [12, 16, 20];
-const VIOLATION_CATEGORY: &[i32] = &[
-    1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20,
-];
-const VIOLATION_KIND: &[i32] = &[
-    1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26,
-    27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47, 48, 49, 50,
-    51, 52, 53, 54, 55, 56, 57, 58, 59, 60,
-];
+    pub params: ExportParams,
+}
 
-// --- Вспомогательные функции ---
-async fn send_with_retry<F, T>(mut action: F) -> Result<T, String>
-where
-    F: FnMut() -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<T, String>> + Send>>,
-    T: Send,
-{
-    let mut attempts = 0;
-    let mut delay = 1;
-
-    loop {
-        match action().await {
-            Ok(res) => return Ok(res),
-            Err(e) if attempts < 3 => {
-                attempts += 1;
-                debug_log(&format!(
-                    "🔁 Повтор запроса через {} сек (ошибка: {})",
-                    delay, e
-                ));
-                tokio::time::sleep(tokio::time::Duration::from_secs(delay)).await;
-                delay *= 2;
+/// Наибольшее число кодов в одном диапазоне: опечатка вроде «1-99999999» не должна
+/// разворачиваться в гигантский список
+pub const MAX_RANGE: i32 = 1000;
+
+/// Разбирает список кодов: «1-5, 8, 10» → [1, 2, 3, 4, 5, 8, 10]
+pub fn parse_codes(text: &str) -> Result<Vec<i32>, String> {
+    let mut codes = Vec::new();
+    for part in text.split(|c: char| c == ',' || c == ';' || c.is_whitespace()) {
+        let part = part.trim();
+        if part.is_empty() {
+            continue;
+        }
+        let invalid = || format!("Некорректный код или диапазон: «{}»", part);
+        let code = |text: &str| -> Result<i32, String> {
+            match text.trim().parse::<i32>() {
+                Ok(code) if code > 0 => Ok(code),
+                Ok(_) => Err(format!("Код должен быть положительным: «{}»", part)),
+                Err(_) => Err(invalid()),
             }
-            Err(e) => return Err(e),
+        };
+        match part.split_once('-') {
+            Some((from, to)) => {
+                let from = code(from)?;
+                let to = code(to)?;
+                if from > to {
+                    return Err(invalid());
+                }
+                if to - from >= MAX_RANGE {
+                    return Err(format!(
+                        "Слишком большой диапазон «{}»: не больше {} кодов",
+                        part, MAX_RANGE
+                    ));
+                }
+                codes.extend(from..=to);
+            }
+            None => codes.push(code(part)?),
+        }
+    }
+    codes.sort_unstable();
+    codes.dedup();
+    Ok(codes)
+}
+
+/// Обратное к `parse_codes`: подряд идущие коды сворачиваются в диапазоны
+pub fn format_codes(codes: &[i32]) -> String {
+    let mut sorted = codes.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
+
+    let mut parts = Vec::new();
+    let mut i = 0;
+    while i < sorted.len() {
+        let start = sorted[i];
+        let mut end = start;
+        while i + 1 < sorted.len() && sorted[i + 1] == end + 1 {
+            i += 1;
+            end = sorted[i];
         }
+        parts.push(if start == end {
+            start.to_string()
+        } else {
+            format!("{}-{}", start, end)
+        });
+        i += 1;
     }
+    parts.join(", ")
+}
+
+/// Для каких организаций запрашивать выгрузку
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExportScope {
+    /// Одна сессия (ключ из `StoredToken::key`)
+    Session(String),
+    /// Все сохранённые сессии
+    All,
 }
 
 // --- Основная функция: запрос выгрузки ---
-pub async fn fetch_violation_tasks() -> Result<Vec<String>, String> {
-    let token = signing::load_auth_token().map_err(|e| format!("Не авторизован: {}", e))?;
+pub async fn fetch_violation_tasks(
+    scope: ExportScope,
+    params: &ExportParams,
+) -> Result<Vec<String>, ApiError> {
+    params.validate().map_err(ApiError::InvalidParams)?;
 
-    let today = Local::now().date_naive();
-    let current_week_start = today - Duration::days(today.weekday().num_days_from_monday().into());
-    let last_week_start = current_week_start - Duration::days(7);
-    let last_week_end = last_week_start + Duration::days(6);
+    let sessions = match scope {
+        ExportScope::Session(key) => {
+            let session = storage::load_session(&key).map_err(|e| ApiError::NotSignedIn(e.to_string()))?;
+            if session.is_oms() {
+                return Err(ApiError::NotSignedIn(
+                    "токен СУЗ не подходит для выгрузки, выберите сессию True API".to_string(),
+                ));
+            }
+            vec![session]
+        }
+        ExportScope::All => storage::list_sessions()
+            .into_iter()
+            .filter(|s| !s.is_oms())
+            .collect(),
+    };
+    if sessions.is_empty() {
+        return Err(ApiError::NotSignedIn("нет сохранённых сессий".to_string()));
+    }
 
-    let data_start_date = last_week_start.format("%Y-%m-%d").to_string();
-    let data_end_date = last_week_end.format("%Y-%m-%d").to_string();
+    let (start, end) = params
+        .date_range(Local::now().date_naive())
+        .map_err(ApiError::InvalidParams)?;
+    let data_start_date = start.format("%Y-%m-%d").to_string();
+    let data_end_date = end.format("%Y-%m-%d").to_string();
     let period = format!("{}—{}", data_start_date, data_end_date);
 
     debug_log(&format!("📆 Запрос данных за период: {}", period));
 
     let params_json = serde_json::json!({
-        "violationCategory": VIOLATION_CATEGORY,
-        "violationKind": VIOLATION_KIND
+        "violationCategory": params.violation_categories,
+        "violationKind": params.violation_kinds
     })
     .to_string();
 
-    let client = reqwest::Client::new();
     let mut results = Vec::new();
     let mut new_tasks = Vec::new();
 
-    for &code in &PRODUCT_GROUP_CODES {
+    for session in &sessions {
+        let fetched = fetch_for_session(
+            session,
+            params,
+            &data_start_date,
+            &data_end_date,
+            &params_json,
+        )
+        .await;
+        match fetched {
+            Ok((session_results, session_tasks)) => {
+                results.extend(session_results);
+                new_tasks.extend(session_tasks);
+            }
+            // Ошибка одной организации не прерывает выгрузку остальных
+            Err(e) => {
+                let org_inn = session.inn.clone().unwrap_or_default();
+                debug_log(&format!("❌ Выгрузка ИНН {}: {}", org_inn, e));
+                results.push(format!("❌ ИНН {}: {}", org_inn, e));
+            }
+        }
+    }
+
+    with_tasks(|tasks| {
+        apply_retention(tasks);
+        tasks.extend(new_tasks);
+    });
+
+    Ok(results)
+}
+
+/// Создаёт задачи выгрузки по выбранным товарным группам от имени одной сессии
+async fn fetch_for_session(
+    session: &StoredToken,
+    params: &ExportParams,
+    data_start_date: &str,
+    data_end_date: &str,
+    params_json: &str,
+) -> Result<(Vec<String>, Vec<TaskInfo>), ApiError> {
+    let session_key = session.key();
+    let org_inn = session.inn.clone().unwrap_or_default();
+    let api = TrueApiClient::for_session(&session_key)?;
+    let mut results = Vec::new();
+    let mut new_tasks = Vec::new();
+
+    for &code in &params.product_groups {
         let body = TaskRequest {
             name: "VIOLATIONS".to_string(),
-            data_start_date: data_start_date.clone(),
-            data_end_date: data_end_date.clone(),
-            format: "CSV".to_string(),
-            periodicity: "SINGLE".to_string(),
-            params: params_json.clone(),
+            data_start_date: data_start_date.to_string(),
+            data_end_date: data_end_date.to_string(),
+            format: params.format.api_value().to_string(),
+            periodicity: params.periodicity.api_value().to_string(),
+            params: params_json.to_string(),
             product_group_code: code,
         };
 
-        let request_json = serde_json::to_string(&body)
-            .map_err(|e| format!("Не удалось сериализовать тело запроса: {}", e))?;
+        let request_json = serde_json::to_string(&body).map_err(|e| ApiError::Parse(e.to_string()))?;
 
         debug_log(&format!(
-            "📤 POST /dispenser/tasks (pg={})\n   Тело: {}",
-            code, request_json
+            "📤 POST /dispenser/tasks (ИНН {}, pg={})\n   Тело: {}",
+            org_inn,
+            code,
+            crate::inspector::redact_body(&request_json)
         ));
 
-        let token_clone = token.clone();
-        let client_clone = client.clone();
-
-        let response_result = send_with_retry(move || {
-            let client = client_clone.clone();
-            let body = body.clone();
-            let token = token_clone.clone();
-            Box::pin(async move {
-                let response = client
-                    .post("https://markirovka.crpt.ru/api/v3/true-api/dispenser/tasks")
-                    .bearer_auth(&token)
-                    .json(&body)
-                    .send()
-                    .await
-                    .map_err(|e| format!("Ошибка запроса: {}", e))?;
-
-                let status = response.status();
-                let response_text = response
-                    .text()
-                    .await
-                    .map_err(|e| format!("Не удалось прочитать ответ: {}", e))?;
-
-                if status.is_success() {
-                    Ok((status, response_text))
-                } else {
-                    Err(format!("Ошибка {}: {}", status, response_text))
-                }
-            })
-        })
-        .await;
+        match api.create_task(&body).await {
+            Ok(task) => {
+                let create_date = NaiveDate::parse_from_str(&task.create_date, "%Y-%m-%d")
+                    .unwrap_or_else(|_| Local::now().date_naive());
 
-        match response_result {
-            Ok((status, response_text)) => {
                 debug_log(&format!(
-                    "📥 Успешный ответ (pg={}): [{}] {}",
-                    code, status, response_text
+                    "✅ Задача создана: id={}, pg={}, статус={}",
+                    task.id, task.product_group_code, task.current_status
                 ));
 
-                match serde_json::from_str::<TaskResponse>(&response_text) {
-                    Ok(task) => {
-                        let create_date = NaiveDate::parse_from_str(&task.create_date, "%Y-%m-%d")
-                            .unwrap_or_else(|_| Local::now().date_naive());
-
-                        debug_log(&format!(
-                            "✅ Задача создана: id={}, pg={}, статус={}",
-                            task.id, task.product_group_code, task.current_status
-                        ));
-
-                        results.push(format!(
-                            "✅ Запрос #{}, {} (id: {})",
-                            task.product_group_code,
-                            task.product_group_code, // будет заменено на display_name в UI
-                            task.id
-                        ));
-
-                        new_tasks.push(TaskInfo {
-                            id: task.id,
-                            product_group_code: task.product_group_code,
-                            data_start_date: task.data_start_date,
-                            data_end_date: task.data_end_date,
-                            status: task.current_status,
-                            create_date,
-                        });
-                    }
-                    Err(e) => {
-                        debug_log(&format!("❌ Ошибка парсинга JSON: {}", e));
-                        results.push(format!("❌ Ошибка ответа: {}", response_text));
-                    }
-                }
+                results.push(format!(
+                    "✅ ИНН {}: запрос #{}, {} (id: {})",
+                    org_inn,
+                    task.product_group_code,
+                    product_group_name(task.product_group_code),
+                    task.id
+                ));
+
+                new_tasks.push(TaskInfo {
+                    id: task.id,
+                    session_key: session_key.clone(),
+                    org_inn: org_inn.clone(),
+                    product_group_code: task.product_group_code,
+                    data_start_date: task.data_start_date,
+                    data_end_date: task.data_end_date,
+                    format: params.format,
+                    periodicity: params.periodicity,
+                    params: params_json.to_string(),
+                    history: vec![StatusChange {
+                        status: task.current_status.clone(),
+                        at: Utc::now(),
+                    }],
+                    status: task.current_status,
+                    create_date,
+                    expires_on: None,
+                    downloaded: None,
+                    auto_download_attempts: 0,
+                    download_error: None,
+                    synced: false,
+                });
             }
             Err(e) => {
-                debug_log(&format!("❌ Запрос не удался после 3 попыток: {}", e));
+                debug_log(&format!("❌ Запрос не удался: {}", crate::inspector::redact_body(&e.to_string())));
                 results.push(format!(
-                    "❌ Не удалось создать задачу для pg={}: {}",
-                    code, e
+                    "❌ ИНН {}: не удалось создать задачу для pg={}: {}",
+                    org_inn, code, e
                 ));
             }
         }
     }
 
-    {
-        let mut tasks = TASKS.lock().unwrap();
-        tasks.retain(|t| (Local::now().date_naive() - t.create_date).num_days() < 7);
-        tasks.extend(new_tasks);
-    }
-
-    Ok(results)
+    Ok((results, new_tasks))
 }
 
 // --- Проверка статуса одной задачи ---
 pub async fn check_task_status(
     task_id: &str,
     product_code: i32,
-) -> Result<TaskStatusResponse, String> {
-    let token = signing::load_auth_token().map_err(|e| format!("Не авторизован: {}", e))?;
-
-    let url = format!(
-        "https://markirovka.crpt.ru/api/v3/true-api/dispenser/tasks/{}?pg={}",
-        task_id, product_code
-    );
-
+    session_key: &str,
+) -> Result<TaskStatusResponse, ApiError> {
     debug_log(&format!(
         "🔍 Проверка статуса: id={}, pg={}",
         task_id, product_code
     ));
 
-    send_with_retry(move || {
-        let client = reqwest::Client::new();
-        let url = url.clone();
-        let token = token.clone();
-        Box::pin(async move {
-            let response = client
-                .get(&url)
-                .bearer_auth(&token)
-                .send()
-                .await
-                .map_err(|e| format!("Ошибка сети: {}", e))?;
-
-            let status = response.status();
-            let response_text = response
-                .text()
-                .await
-                .map_err(|e| format!("Не удалось прочитать ответ: {}", e))?;
-
-            if status.is_success() {
-                let task_status: TaskStatusResponse = serde_json::from_str(&response_text)
-                    .map_err(|e| format!("Ошибка парсинга JSON: {}", e))?;
-                Ok(task_status)
-            } else {
-                Err(format!("Ошибка {}: {}", status, response_text))
+    TrueApiClient::for_session(session_key)?
+        .task_status(task_id, product_code)
+        .await
+}
+
+// --- Скачивание результата ---
+
+/// Скачивает результат готовой задачи в папку загрузок.
+/// Ссылка запрашивается заново: она может устареть с момента опроса статуса.
+pub async fn download_task(task_id: &str) -> Result<PathBuf, DownloadError> {
+    let task = registry()
+        .tasks
+        .iter()
+        .find(|t| t.id == task_id)
+        .cloned()
+        .ok_or_else(|| DownloadError::UnknownTask(task_id.to_string()))?;
+
+    let status = check_task_status(&task.id, task.product_group_code, &task.session_key).await?;
+    save_task_result(&task, &status).await
+}
+
+/// Скачивает результат по ссылке из свежего статуса и запоминает путь
+async fn save_task_result(task: &TaskInfo, status: &TaskStatusResponse) -> Result<PathBuf, DownloadError> {
+    let url = match &status.download_url {
+        Some(url) if status.current_status == "COMPLETED" && !url.trim().is_empty() => url,
+        _ => return Err(DownloadError::NotReady),
+    };
+
+    let api = TrueApiClient::for_session(&task.session_key)?;
+    let name = crate::download::ResultName {
+        org_inn: &task.org_inn,
+        product_group_code: task.product_group_code,
+        data_start_date: &task.data_start_date,
+        data_end_date: &task.data_end_date,
+    };
+    let path = crate::download::save_result(&api, url.trim(), &name).await?;
+
+    with_tasks(|tasks| {
+        if let Some(stored) = tasks.iter_mut().find(|t| t.id == task.id) {
+            stored.downloaded = Some(path.clone());
+            stored.download_error = None;
+        }
+    });
+    Ok(path)
+}
+
+/// Фоновая загрузка готового результата (если включена в настройках).
+/// После нескольких неудач задача остаётся для ручного скачивания.
+async fn auto_download(task: &TaskInfo, status: &TaskStatusResponse) -> TaskInfo {
+    let mut task = task.clone();
+    let enabled = crate::settings::load().auto_download;
+    if !enabled
+        || task.synced
+        || status.current_status != "COMPLETED"
+        || task.downloaded.is_some()
+        || task.auto_download_attempts >= AUTO_DOWNLOAD_ATTEMPTS
+    {
+        return task;
+    }
+
+    match save_task_result(&task, status).await {
+        Ok(path) => {
+            debug_log(&format!("⬇️ Результат задачи {} скачан автоматически", task.id));
+            task.downloaded = Some(path);
+            task.download_error = None;
+        }
+        Err(e) => {
+            debug_log(&format!("❌ Автоматическая загрузка задачи {}: {}", task.id, e));
+            task.auto_download_attempts += 1;
+            task.download_error = Some(e.to_string());
+            with_tasks(|tasks| {
+                if let Some(stored) = tasks.iter_mut().find(|t| t.id == task.id) {
+                    stored.auto_download_attempts = task.auto_download_attempts;
+                    stored.download_error = task.download_error.clone();
+                }
+            });
+        }
+    }
+    task
+}
+
+// --- Синхронизация с сервером ---
+
+/// Размер страницы списка задач и предел страниц на одну организацию
+const SYNC_PAGE_SIZE: u32 = 100;
+const SYNC_MAX_PAGES: u32 = 50;
+
+/// Статусы задач выгрузки и их названия
+pub const TASK_STATUSES: [(&str, &str); 5] = [
+    ("PREPARATION", "В обработке"),
+    ("COMPLETED", "Готово"),
+    ("FAILED", "Ошибка"),
+    ("CANCELED", "Отменена"),
+    ("ARCHIVED", "В архиве"),
+];
+
+/// Фильтр задач при синхронизации; пустые поля не ограничивают выборку
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TaskFilter {
+    pub status: Option<String>,
+    pub product_group: Option<i32>,
+    pub date_from: Option<NaiveDate>,
+    pub date_to: Option<NaiveDate>,
+}
+
+/// Итог синхронизации
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SyncSummary {
+    /// Новые задачи, созданные в личном кабинете или на другом рабочем месте
+    pub added: usize,
+    /// Известные задачи, у которых сменился статус
+    pub updated: usize,
+    /// Ошибки по отдельным организациям
+    pub errors: Vec<String>,
+}
+
+impl SyncSummary {
+    pub fn describe(&self) -> String {
+        let mut text = format!(
+            "🔄 Синхронизировано: новых задач {}, обновлено {}",
+            self.added, self.updated
+        );
+        for error in &self.errors {
+            text.push_str(&format!("\n❌ {}", error));
+        }
+        text
+    }
+}
+
+/// Загружает задачи всех сессий True API с сервера и добавляет их в реестр.
+/// Новые задачи дальше опрашиваются `check_all_tasks` наравне с созданными здесь,
+/// но их результаты автоматически не скачиваются.
+pub async fn sync_tasks(filter: &TaskFilter) -> Result<SyncSummary, ApiError> {
+    if let (Some(from), Some(to)) = (filter.date_from, filter.date_to) {
+        if from > to {
+            return Err(ApiError::InvalidParams(
+                "дата «с» позже даты «по»".to_string(),
+            ));
+        }
+    }
+    let sessions: Vec<StoredToken> = storage::list_sessions()
+        .into_iter()
+        .filter(|s| !s.is_oms())
+        .collect();
+    if sessions.is_empty() {
+        return Err(ApiError::NotSignedIn("нет сохранённых сессий".to_string()));
+    }
+
+    let mut summary = SyncSummary::default();
+    for session in &sessions {
+        let session_key = session.key();
+        let org = session.inn.clone().unwrap_or_default();
+        let remote = match list_remote_tasks(&session_key, filter).await {
+            Ok(remote) => remote,
+            Err(e) => {
+                debug_log(&format!("❌ Список задач ИНН {}: {}", org, e));
+                summary.errors.push(format!("ИНН {}: {}", org, e));
+                continue;
             }
-        })
-    })
-    .await
+        };
+        debug_log(&format!("📋 ИНН {}: на сервере {} задач", org, remote.len()));
+
+        let retention_days = crate::settings::load().task_retention_days;
+        let today = Local::now().date_naive();
+        with_tasks(|tasks| {
+            for task in remote {
+                if let Some(stored) = tasks.iter_mut().find(|t| t.id == task.id) {
+                    if stored.set_status(&task.current_status) {
+                        summary.updated += 1;
+                    }
+                    continue;
+                }
+                // Задачи старше срока хранения реестра не добавляются
+                let task = task_from_server(task, &session_key);
+                if within_retention(task.create_date, retention_days, today) {
+                    tasks.push(task);
+                    summary.added += 1;
+                }
+            }
+        });
+    }
+    Ok(summary)
+}
+
+/// Все страницы списка задач одной сессии
+async fn list_remote_tasks(
+    session_key: &str,
+    filter: &TaskFilter,
+) -> Result<Vec<crate::api::TaskResponse>, ApiError> {
+    let api = TrueApiClient::for_session(session_key)?;
+    let mut query = crate::api::TaskListQuery {
+        page: 0,
+        size: SYNC_PAGE_SIZE,
+        status: filter.status.clone(),
+        product_group: filter.product_group,
+        date_from: filter.date_from,
+        date_to: filter.date_to,
+    };
+    let mut all = Vec::new();
+
+    while query.page < SYNC_MAX_PAGES {
+        let page = api.list_tasks(&query).await?;
+        let received = page.list.len();
+        all.extend(page.list);
+        let done = match page.total {
+            Some(total) => all.len() as u64 >= total,
+            None => received < SYNC_PAGE_SIZE as usize,
+        };
+        if done || received == 0 {
+            return Ok(all);
+        }
+        query.page += 1;
+    }
+    debug_log(&format!(
+        "⚠️ Список задач обрезан: больше {} страниц",
+        SYNC_MAX_PAGES
+    ));
+    Ok(all)
+}
+
+/// Запись реестра для задачи, созданной вне приложения
+fn task_from_server(task: crate::api::TaskResponse, session_key: &str) -> TaskInfo {
+    let create_date = task
+        .create_date
+        .get(..10)
+        .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+        .unwrap_or_else(|| Local::now().date_naive());
+    TaskInfo {
+        id: task.id,
+        session_key: session_key.to_string(),
+        org_inn: task.org_inn,
+        product_group_code: task.product_group_code,
+        data_start_date: task.data_start_date,
+        data_end_date: task.data_end_date,
+        format: ExportFormat::default(),
+        periodicity: Periodicity::from_api_value(&task.periodicity).unwrap_or_default(),
+        params: String::new(),
+        history: vec![StatusChange {
+            status: task.current_status.clone(),
+            at: Utc::now(),
+        }],
+        status: task.current_status,
+        create_date,
+        expires_on: None,
+        downloaded: None,
+        auto_download_attempts: 0,
+        download_error: None,
+        synced: true,
+    }
+}
+
+// --- Отмена и удаление задач ---
+
+fn find_task(task_id: &str) -> Result<TaskInfo, ApiError> {
+    registry()
+        .tasks
+        .iter()
+        .find(|t| t.id == task_id)
+        .cloned()
+        .ok_or_else(|| ApiError::InvalidParams(format!("задача {} не найдена в списке", task_id)))
+}
+
+/// Останавливает задачу на сервере и отмечает её отменённой.
+/// Если сервер отвечает, что отменять нечего (404/405), статус берётся с сервера:
+/// задача могла уже завершиться. Пропавшая с сервера задача считается отменённой.
+pub async fn cancel_task(task_id: &str) -> Result<(), ApiError> {
+    let task = find_task(task_id)?;
+    let api = TrueApiClient::for_session(&task.session_key)?;
+    let (status, result) = match api.cancel_task(&task.id, task.product_group_code).await {
+        Ok(()) => {
+            debug_log(&format!("⛔ Задача {} отменена", task.id));
+            ("CANCELED".to_string(), Ok(()))
+        }
+        Err(e @ ApiError::Status { status: 404 | 405, .. }) => {
+            let not_found = matches!(e, ApiError::Status { status: 404, .. });
+            let finished = |status: &str| status == "COMPLETED" || is_final_status(status);
+            match api.task_status(&task.id, task.product_group_code).await {
+                // Задача уже завершилась — отменять нечего
+                Ok(current) if finished(&current.current_status) => (current.current_status, Ok(())),
+                // Задача ещё выполняется, а отменить её сервер не может
+                Ok(current) => (current.current_status, Err(e)),
+                // Задачи на сервере больше нет
+                Err(_) if not_found => ("CANCELED".to_string(), Ok(())),
+                Err(_) => return Err(e),
+            }
+        }
+        Err(e) => return Err(e),
+    };
+
+    with_tasks(|tasks| {
+        if let Some(stored) = tasks.iter_mut().find(|t| t.id == task.id) {
+            stored.set_status(&status);
+        }
+    });
+    result
+}
+
+/// Убирает задачу из списка; с `on_server` — сначала удаляет её на сервере.
+/// Если сервер удаление не поддерживает, задача убирается только из списка,
+/// а возвращённое предупреждение сообщает об этом.
+pub async fn delete_task(task_id: &str, on_server: bool) -> Result<Option<String>, ApiError> {
+    let task = find_task(task_id)?;
+    let mut warning = None;
+
+    if on_server {
+        let api = TrueApiClient::for_session(&task.session_key)?;
+        match api.delete_task(&task.id, task.product_group_code).await {
+            Ok(()) => debug_log(&format!("🗑 Задача {} удалена на сервере", task.id)),
+            // Задачи на сервере уже нет
+            Err(ApiError::Status { status: 404, .. }) => {}
+            // Метод не поддерживается — убираем только локально
+            Err(ApiError::Status { status: 405 | 501, .. }) => {
+                warning = Some(
+                    "сервер не удаляет задачи — она убрана только из списка и вернётся при синхронизации"
+                        .to_string(),
+                );
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    with_tasks(|tasks| tasks.retain(|t| t.id != task.id));
+    Ok(warning)
+}
+
+// --- Очистка реестра задач ---
+
+/// Убирает задачи сессии из реестра (после выхода из неё)
+pub fn forget_session_tasks(session_key: &str) {
+    with_tasks(|tasks| tasks.retain(|t| t.session_key != session_key));
+}
+
+/// Очищает реестр задач
+pub fn clear_tasks() {
+    with_tasks(|tasks| tasks.clear());
 }
 
 // --- Проверка всех задач ---
 pub async fn check_all_tasks() -> Vec<TaskStatusForUI> {
-    let tasks = TASKS.lock().unwrap();
+    let tasks = registry().tasks.clone();
     let mut results = Vec::new();
 
-    for task in &*tasks {
-        let status_for_ui = match check_task_status(&task.id, task.product_group_code).await {
-            Ok(status) => TaskStatusForUI {
-                id: status.id.clone(),
-                product_group_code: status.product_group_code,
-                status: status.current_status.clone(),
-                create_date: status.create_date.clone(),
-                is_completed: status.current_status == "COMPLETED",
+    for task in &tasks {
+        if task.is_final() {
+            results.push(TaskStatusForUI {
+                id: task.id.clone(),
+                org_inn: task.org_inn.clone(),
+                product_group_code: task.product_group_code,
+                status: task.status.clone(),
+                create_date: task.create_date.format("%Y-%m-%d").to_string(),
+                is_completed: task.status == "COMPLETED",
                 error: None,
-            },
+                downloaded: task.downloaded.clone(),
+                expires_on: task.expires_on,
+                download_error: task.download_error.clone(),
+            });
+            continue;
+        }
+
+        let status_for_ui = match check_task_status(&task.id, task.product_group_code, &task.session_key).await {
+            Ok(status) => {
+                let expires_on = expiry_date(&status.create_date, status.downloading_storage_days);
+                {
+                    // Реестр пишется на диск, только если что-то изменилось
+                    let mut registry = registry();
+                    if let Some(stored) = registry.tasks.iter_mut().find(|t| t.id == task.id) {
+                        let changed = stored.set_status(&status.current_status) || stored.expires_on != expires_on;
+                        stored.expires_on = expires_on;
+                        if changed {
+                            persist(&registry);
+                        }
+                    }
+                }
+                let task = auto_download(task, &status).await;
+                TaskStatusForUI {
+                    id: status.id.clone(),
+                    org_inn: status.org_inn.clone(),
+                    product_group_code: status.product_group_code,
+                    status: status.current_status.clone(),
+                    create_date: status.create_date.clone(),
+                    is_completed: status.current_status == "COMPLETED",
+                    error: None,
+                    downloaded: task.downloaded,
+                    expires_on,
+                    download_error: task.download_error,
+                }
+            }
             Err(e) => TaskStatusForUI {
                 id: task.id.clone(),
+                org_inn: task.org_inn.clone(),
                 product_group_code: task.product_group_code,
                 status: "ERROR".to_string(),
                 create_date: "—".to_string(),
                 is_completed: false,
-                error: Some(e),
+                error: Some(e.to_string()),
+                downloaded: task.downloaded.clone(),
+                expires_on: None,
+                download_error: task.download_error.clone(),
             },
         };
         results.push(status_for_ui);
@@ -427,3 +1163,95 @@ pub async fn check_all_tasks() -> Vec<TaskStatusForUI> {
 
     results
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(text: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap()
+    }
+
+    fn params(period: PeriodPreset) -> ExportParams {
+        ExportParams {
+            period,
+            ..ExportParams::default()
+        }
+    }
+
+    #[test]
+    fn preset_periods() {
+        // Среда, 13 марта 2024 года
+        let today = date("2024-03-13");
+        assert_eq!(
+            params(PeriodPreset::LastWeek).date_range(today),
+            Ok((date("2024-03-04"), date("2024-03-10")))
+        );
+        assert_eq!(
+            params(PeriodPreset::Yesterday).date_range(today),
+            Ok((date("2024-03-12"), date("2024-03-12")))
+        );
+        // Високосный февраль
+        assert_eq!(
+            params(PeriodPreset::LastMonth).date_range(today),
+            Ok((date("2024-02-01"), date("2024-02-29")))
+        );
+        // Январь → декабрь прошлого года
+        assert_eq!(
+            params(PeriodPreset::LastMonth).date_range(date("2024-01-01")),
+            Ok((date("2023-12-01"), date("2023-12-31")))
+        );
+    }
+
+    #[test]
+    fn custom_period_is_validated() {
+        let today = date("2024-03-13");
+        let custom = |start: &str, end: &str| ExportParams {
+            custom_start: start.to_string(),
+            custom_end: end.to_string(),
+            ..params(PeriodPreset::Custom)
+        };
+        assert_eq!(
+            custom(" 2024-01-01 ", "2024-01-31").date_range(today),
+            Ok((date("2024-01-01"), date("2024-01-31")))
+        );
+        assert!(custom("2024-02-01", "2024-01-31").date_range(today).is_err());
+        assert!(custom("2024-03-01", "2024-03-13").date_range(today).is_err());
+        assert!(custom("01.01.2024", "2024-01-31").date_range(today).is_err());
+    }
+
+    #[test]
+    fn parse_codes_ranges_and_lists() {
+        assert_eq!(parse_codes("1-5, 8;10 3"), Ok(vec![1, 2, 3, 4, 5, 8, 10]));
+        assert_eq!(parse_codes(" 7-9 "), Ok(vec![7, 8, 9]));
+        assert_eq!(parse_codes(""), Ok(vec![]));
+    }
+
+    #[test]
+    fn parse_codes_rejects_bad_input() {
+        assert!(parse_codes("5-1").is_err());
+        assert!(parse_codes("a").is_err());
+        assert!(parse_codes("0").is_err());
+        assert!(parse_codes("-3").is_err());
+        assert!(parse_codes("0-5").is_err());
+        assert!(parse_codes("1-99999999").is_err());
+        assert_eq!(parse_codes(&format!("1-{}", MAX_RANGE)).map(|c| c.len()), Ok(MAX_RANGE as usize));
+        assert!(parse_codes(&format!("1-{}", MAX_RANGE + 1)).is_err());
+    }
+
+    #[test]
+    fn format_codes_collapses_runs() {
+        assert_eq!(format_codes(&[10, 1, 2, 3, 5, 6, 3]), "1-3, 5-6, 10");
+        assert_eq!(format_codes(&[]), "");
+        let codes = vec![1, 2, 3, 8, 10, 11];
+        assert_eq!(parse_codes(&format_codes(&codes)), Ok(codes));
+    }
+
+    #[test]
+    fn retention_keeps_recent_tasks() {
+        let today = date("2024-03-13");
+        assert!(within_retention(date("2024-03-01"), 30, today));
+        assert!(!within_retention(date("2024-02-12"), 30, today));
+        assert!(within_retention(date("2000-01-01"), 0, today));
+    }
+}
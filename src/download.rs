@@ -0,0 +1,304 @@
+// src/download.rs
+
+//! Скачивание результатов выгрузки: имена файлов по организации, товарной
+//! группе и периоду, распаковка ZIP, открытие папки в файловом менеджере.
+
+use crate::api::TrueApiClient;
+use crate::error::DownloadError;
+use crate::storage::debug_log;
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::{Path, PathBuf};
+
+/// Сигнатура ZIP-архива
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// Папка для результатов: из настроек или `exports` в директории приложения
+pub fn downloads_dir() -> Result<PathBuf, String> {
+    let configured = crate::settings::load().download_dir;
+    if !configured.trim().is_empty() {
+        return Ok(PathBuf::from(configured.trim()));
+    }
+    let mut path = crate::storage::base_dir().map_err(|e| e.to_string())?;
+    path.push("exports");
+    Ok(path)
+}
+
+/// Что скачивается: организация, товарная группа и период выгрузки
+pub struct ResultName<'a> {
+    pub org_inn: &'a str,
+    pub product_group_code: i32,
+    pub data_start_date: &'a str,
+    pub data_end_date: &'a str,
+}
+
+impl ResultName<'_> {
+    /// «7701234567_pg12_2024-01-01_2024-01-07»
+    pub fn stem(&self) -> String {
+        let inn = if self.org_inn.trim().is_empty() {
+            "без-ИНН"
+        } else {
+            self.org_inn.trim()
+        };
+        sanitize(&format!(
+            "{}_pg{}_{}_{}",
+            inn, self.product_group_code, self.data_start_date, self.data_end_date
+        ))
+    }
+
+    /// Подпапка организации внутри папки загрузок
+    fn org_dir(&self) -> Result<PathBuf, String> {
+        let mut dir = downloads_dir()?;
+        if !self.org_inn.trim().is_empty() {
+            dir.push(sanitize(self.org_inn.trim()));
+        }
+        Ok(dir)
+    }
+}
+
+/// Убирает символы, недопустимые в именах файлов Windows
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Скачивает результат по ссылке и сохраняет его.
+/// Возвращает путь к файлу или к папке с распакованным архивом.
+pub async fn save_result(
+    api: &TrueApiClient,
+    url: &str,
+    name: &ResultName<'_>,
+) -> Result<PathBuf, DownloadError> {
+    let dir = name.org_dir().map_err(DownloadError::File)?;
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| DownloadError::File(format!("{}: {}", dir.display(), e)))?;
+
+    // Результат пишется во временный файл рядом с итоговым, а не в память
+    let stem = name.stem();
+    let partial = dir.join(format!("{}.partial", stem));
+    let result = match api.download(url, &partial).await {
+        Ok(headers) => store(&partial, &headers, &dir, &stem),
+        Err(e) => Err(e),
+    };
+    let _ = std::fs::remove_file(&partial);
+    let path = result?;
+
+    debug_log(&format!("💾 Результат выгрузки сохранён: {}", path.display()));
+    Ok(path)
+}
+
+/// Переносит скачанный файл на место: архив распаковывается, остальное переименовывается
+fn store(
+    partial: &Path,
+    headers: &reqwest::header::HeaderMap,
+    dir: &Path,
+    stem: &str,
+) -> Result<PathBuf, DownloadError> {
+    let open = || {
+        File::open(partial).map_err(|e| DownloadError::File(format!("{}: {}", partial.display(), e)))
+    };
+    let mut magic = [0u8; 4];
+    let is_zip = open()?.read_exact(&mut magic).is_ok() && magic == ZIP_MAGIC;
+    // XLSX тоже ZIP-архив, но распаковывать его не нужно
+    let is_xlsx = is_zip && is_office_document(open()?);
+
+    if is_zip && !is_xlsx && crate::settings::load().unzip_results {
+        let target = dir.join(stem);
+        extract_zip(open()?, &target)?;
+        return Ok(target);
+    }
+
+    let extension = if is_xlsx {
+        "xlsx"
+    } else if is_zip {
+        "zip"
+    } else {
+        extension_from_headers(headers).unwrap_or("csv")
+    };
+    let file = dir.join(format!("{}.{}", stem, extension));
+    std::fs::rename(partial, &file)
+        .map_err(|e| DownloadError::File(format!("{}: {}", file.display(), e)))?;
+    Ok(file)
+}
+
+fn is_office_document(reader: impl Read + Seek) -> bool {
+    zip::ZipArchive::new(reader)
+        .map(|mut archive| archive.by_name("[Content_Types].xml").is_ok())
+        .unwrap_or(false)
+}
+
+/// Расширение по Content-Type или имени файла из Content-Disposition
+fn extension_from_headers(headers: &reqwest::header::HeaderMap) -> Option<&'static str> {
+    let header = |name| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_ascii_lowercase())
+            .unwrap_or_default()
+    };
+    let disposition = header(reqwest::header::CONTENT_DISPOSITION);
+    let content_type = header(reqwest::header::CONTENT_TYPE);
+
+    if disposition.contains(".xlsx") || content_type.contains("spreadsheetml") {
+        Some("xlsx")
+    } else if disposition.contains(".csv") || content_type.contains("csv") {
+        Some("csv")
+    } else if content_type.contains("json") {
+        Some("json")
+    } else {
+        None
+    }
+}
+
+/// Распаковывает архив в `target`; пути вне папки (`../`) отклоняются
+fn extract_zip(reader: impl Read + Seek, target: &Path) -> Result<(), DownloadError> {
+    let mut archive =
+        zip::ZipArchive::new(reader).map_err(|e| DownloadError::Archive(e.to_string()))?;
+    std::fs::create_dir_all(target)
+        .map_err(|e| DownloadError::File(format!("{}: {}", target.display(), e)))?;
+
+    for i in 0..archive.len() {
+        let mut entry = archive
+            .by_index(i)
+            .map_err(|e| DownloadError::Archive(e.to_string()))?;
+        let Some(relative) = entry.enclosed_name() else {
+            return Err(DownloadError::Archive(format!(
+                "недопустимый путь в архиве: {}",
+                entry.name()
+            )));
+        };
+        let out = target.join(relative);
+
+        if entry.is_dir() {
+            std::fs::create_dir_all(&out)
+                .map_err(|e| DownloadError::File(format!("{}: {}", out.display(), e)))?;
+            continue;
+        }
+        if let Some(parent) = out.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| DownloadError::File(format!("{}: {}", parent.display(), e)))?;
+        }
+        let mut file = std::fs::File::create(&out)
+            .map_err(|e| DownloadError::File(format!("{}: {}", out.display(), e)))?;
+        std::io::copy(&mut entry, &mut file)
+            .map_err(|e| DownloadError::Archive(format!("{}: {}", out.display(), e)))?;
+    }
+    Ok(())
+}
+
+/// Открывает папку в файловом менеджере; для файла — папку, где он лежит
+pub fn open_folder(path: &Path) -> Result<(), String> {
+    let dir = if path.is_file() {
+        path.parent().unwrap_or(path)
+    } else {
+        path
+    };
+    std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+
+    #[cfg(windows)]
+    let program = "explorer";
+    #[cfg(target_os = "macos")]
+    let program = "open";
+    #[cfg(all(not(windows), not(target_os = "macos")))]
+    let program = "xdg-open";
+
+    std::process::Command::new(program)
+        .arg(dir)
+        .spawn()
+        .map(|_| ())
+        .map_err(|e| format!("Не удалось открыть папку {}: {}", dir.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+
+    fn zip_with(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in entries {
+            writer
+                .start_file(*name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(content).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("czn-download-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn sanitize_replaces_reserved_characters() {
+        assert_eq!(sanitize(r#"a<b>c:d"e/f\g|h?i*j"#), "a_b_c_d_e_f_g_h_i_j");
+        assert_eq!(sanitize("tab\there"), "tab_here");
+        assert_eq!(sanitize("Отчёт 2024-01-01"), "Отчёт 2024-01-01");
+    }
+
+    #[test]
+    fn result_name_stem() {
+        let name = ResultName {
+            org_inn: " 7700000000 ",
+            product_group_code: 12,
+            data_start_date: "2024-01-01",
+            data_end_date: "2024-01-07",
+        };
+        assert_eq!(name.stem(), "7700000000_pg12_2024-01-01_2024-01-07");
+        let name = ResultName { org_inn: "", ..name };
+        assert_eq!(name.stem(), "без-ИНН_pg12_2024-01-01_2024-01-07");
+    }
+
+    #[test]
+    fn extracts_nested_entries() {
+        let archive = zip_with(&[("report.csv", b"a;b\n1;2\n"), ("dir/more.csv", b"x")]);
+        let target = temp_dir("extract");
+        extract_zip(Cursor::new(archive), &target).unwrap();
+        assert_eq!(std::fs::read(target.join("report.csv")).unwrap(), b"a;b\n1;2\n");
+        assert_eq!(std::fs::read(target.join("dir").join("more.csv")).unwrap(), b"x");
+        std::fs::remove_dir_all(&target).unwrap();
+    }
+
+    #[test]
+    fn rejects_zip_slip_entry() {
+        let archive = zip_with(&[("../evil.txt", b"pwned")]);
+        let target = temp_dir("slip");
+        let error = extract_zip(Cursor::new(archive), &target).unwrap_err();
+        assert!(matches!(error, DownloadError::Archive(_)), "{}", error);
+        assert!(!target.parent().unwrap().join("evil.txt").exists());
+        let _ = std::fs::remove_dir_all(&target);
+    }
+
+    #[test]
+    fn rejects_non_zip() {
+        let target = temp_dir("garbage");
+        assert!(matches!(
+            extract_zip(Cursor::new(b"not a zip".to_vec()), &target),
+            Err(DownloadError::Archive(_))
+        ));
+    }
+
+    #[test]
+    fn detects_office_documents_and_extensions() {
+        assert!(is_office_document(Cursor::new(zip_with(&[("[Content_Types].xml", b"<Types/>")]))));
+        assert!(!is_office_document(Cursor::new(zip_with(&[("report.csv", b"")]))));
+
+        let mut headers = reqwest::header::HeaderMap::new();
+        assert_eq!(extension_from_headers(&headers), None);
+        headers.insert(
+            reqwest::header::CONTENT_DISPOSITION,
+            "attachment; filename=\"Report.CSV\"".parse().unwrap(),
+        );
+        assert_eq!(extension_from_headers(&headers), Some("csv"));
+        headers.clear();
+        headers.insert(reqwest::header::CONTENT_TYPE, "application/json".parse().unwrap());
+        assert_eq!(extension_from_headers(&headers), Some("json"));
+    }
+}
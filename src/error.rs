@@ -0,0 +1,268 @@
+// src/error.rs
+
+use crate::pin::PinFailure;
+use std::fmt;
+
+/// Ошибка запроса к True API / СУЗ
+#[derive(Clone, Debug, PartialEq)]
+pub enum ApiError {
+    /// Сервер недоступен: соединение, таймаут, TLS
+    Network(String),
+    /// Сервер отклонил токен (HTTP 401)
+    Unauthorized { body: String },
+    /// Прочие ответы с ошибкой; `retry_after` — из заголовка Retry-After
+    Status {
+        status: u16,
+        body: String,
+        retry_after: Option<std::time::Duration>,
+    },
+    /// Ответ в неожиданном формате
+    Parse(String),
+    /// Нет сохранённой сессии для запроса
+    NotSignedIn(String),
+    /// Не удалось подготовить клиент: прокси, сертификаты, адрес API
+    Config(String),
+    /// Некорректные параметры выгрузки (период, товарные группы)
+    InvalidParams(String),
+}
+
+impl ApiError {
+    /// Временная ошибка, после которой имеет смысл повторить запрос
+    pub fn is_transient(&self) -> bool {
+        match self {
+            ApiError::Network(_) => true,
+            ApiError::Status { status, .. } => *status >= 500 || *status == 429,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Network(e) => write!(f, "Ошибка сети: {}", e),
+            ApiError::Unauthorized { body } if body.is_empty() => {
+                write!(f, "Сессия недействительна или истекла (401)")
+            }
+            ApiError::Unauthorized { body } => {
+                write!(f, "Сессия недействительна или истекла (401): {}", body)
+            }
+            ApiError::Status { status, body, .. } => {
+                write!(f, "Ошибка сервера: {} — {}", status, body)
+            }
+            ApiError::Parse(e) => write!(f, "Ошибка парсинга JSON: {}", e),
+            ApiError::NotSignedIn(e) => write!(f, "Не авторизован: {}", e),
+            ApiError::Config(e) => write!(f, "Ошибка настроек подключения: {}", e),
+            ApiError::InvalidParams(e) => write!(f, "Некорректные параметры выгрузки: {}", e),
+        }
+    }
+}
+
+/// Ошибка хранилища токенов
+#[derive(Clone, Debug, PartialEq)]
+pub enum StorageError {
+    NotFound,
+    Empty,
+    /// Чтение, запись, создание директорий
+    Io(String),
+    /// Шифрование или расшифровка
+    Crypto(String),
+    /// Файл прочитан, но его содержимое не разобрать
+    Corrupted(String),
+    /// Нет ключа шифрования: нужна парольная фраза
+    PassphraseRequired,
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::NotFound => write!(f, "Токен не найден"),
+            StorageError::Empty => write!(f, "Токен пуст"),
+            StorageError::Io(e) => write!(f, "Ошибка файловой системы: {}", e),
+            StorageError::Crypto(e) => write!(f, "Ошибка шифрования токена: {}", e),
+            StorageError::Corrupted(e) => write!(f, "Повреждённый файл токена: {}", e),
+            StorageError::PassphraseRequired => write!(
+                f,
+                "Хранилище ключей недоступно: введите парольную фразу для шифрования токенов"
+            ),
+        }
+    }
+}
+
+/// Ошибка скачивания результата выгрузки
+#[derive(Clone, Debug, PartialEq)]
+pub enum DownloadError {
+    /// Задачи нет в реестре
+    UnknownTask(String),
+    /// Выгрузка ещё не готова или сервер не дал ссылку
+    NotReady,
+    Api(ApiError),
+    /// Папка загрузок или запись файла
+    File(String),
+    /// Архив не удалось распаковать
+    Archive(String),
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DownloadError::UnknownTask(id) => write!(f, "Задача {} не найдена", id),
+            DownloadError::NotReady => write!(f, "Выгрузка ещё не готова"),
+            DownloadError::Api(e) => write!(f, "{}", e),
+            DownloadError::File(e) => write!(f, "Не удалось сохранить файл: {}", e),
+            DownloadError::Archive(e) => write!(f, "Не удалось распаковать архив: {}", e),
+        }
+    }
+}
+
+impl From<ApiError> for DownloadError {
+    fn from(e: ApiError) -> Self {
+        DownloadError::Api(e)
+    }
+}
+
+/// Ошибка разбора или проверки подписи
+#[derive(Clone, Debug, PartialEq)]
+pub enum VerifyError {
+    /// Файл не является подписью CMS или повреждён
+    Format(String),
+    /// Подпись цела, но не та: другие данные, другой сертификат или вид подписи
+    Mismatch(String),
+    /// Подпись отсоединённая, а исходный файл не выбран
+    OriginalRequired,
+    /// Файл подписи или временные файлы проверки
+    File(String),
+    /// cryptcp.exe не найден, не запустился или не завершился вовремя
+    Csp(SignError),
+    /// Проверка прервана до завершения
+    Interrupted(String),
+    /// Кэш отзыва сертификатов не очищен
+    RevocationCache(String),
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::Format(e) => write!(f, "Подпись повреждена: {}", e),
+            VerifyError::Mismatch(e) => write!(f, "{}", e),
+            VerifyError::OriginalRequired => {
+                write!(f, "Подпись отсоединённая — выберите исходный файл")
+            }
+            VerifyError::File(e) => write!(f, "{}", e),
+            VerifyError::Csp(e) => write!(f, "{}", e),
+            VerifyError::Interrupted(e) => write!(f, "Проверка прервана: {}", e),
+            VerifyError::RevocationCache(e) => {
+                write!(f, "Не удалось очистить кэш отзыва сертификатов: {}", e)
+            }
+        }
+    }
+}
+
+/// Ошибка входа или подписи
+#[derive(Clone, Debug, PartialEq)]
+pub enum SignError {
+    /// КриптоПро CSP (cryptcp.exe) не найден или не запускается
+    CspNotFound(String),
+    /// Ошибка PIN-кода контейнера
+    Pin(PinFailure),
+    /// cryptcp.exe завершился с ошибкой
+    Csp(String),
+    /// cryptcp.exe не завершился вовремя и был остановлен
+    Timeout(std::time::Duration),
+    /// Подпись не прошла проверку перед отправкой
+    InvalidSignature(String),
+    /// Часы компьютера или срок действия сертификата
+    Clock(String),
+    /// Исходный или временный файл
+    File(String),
+    /// Подпись создана, но штамп времени не получен
+    Timestamp(String),
+    Api(ApiError),
+    Storage(StorageError),
+}
+
+/// Что предложить пользователю после ошибки
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorAction {
+    Relogin,
+    Retry,
+    OpenSettings,
+}
+
+impl ErrorAction {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ErrorAction::Relogin => "Войти заново",
+            ErrorAction::Retry => "Повторить",
+            ErrorAction::OpenSettings => "Открыть настройки",
+        }
+    }
+}
+
+impl SignError {
+    /// Нужно ли (повторно) запросить PIN
+    pub fn needs_pin(&self) -> bool {
+        matches!(self, SignError::Pin(PinFailure::Required | PinFailure::Wrong { .. }))
+    }
+
+    /// Токен не сохранён, потому что нечем его зашифровать
+    pub fn needs_passphrase(&self) -> bool {
+        matches!(self, SignError::Storage(StorageError::PassphraseRequired))
+    }
+
+    /// Действие, которое вероятнее всего исправит ошибку
+    pub fn action(&self) -> Option<ErrorAction> {
+        match self {
+            SignError::Api(ApiError::Unauthorized { .. } | ApiError::NotSignedIn(_)) => {
+                Some(ErrorAction::Relogin)
+            }
+            SignError::Api(ApiError::Config(_)) | SignError::Timestamp(_) => {
+                Some(ErrorAction::OpenSettings)
+            }
+            // КриптоПро не установлен или часы расходятся — повтор не поможет
+            SignError::CspNotFound(_) | SignError::Clock(_) => Some(ErrorAction::OpenSettings),
+            SignError::Api(e) if e.is_transient() => Some(ErrorAction::Retry),
+            SignError::Csp(_) | SignError::Timeout(_) => Some(ErrorAction::Retry),
+            SignError::Api(_)
+            | SignError::InvalidSignature(_)
+            | SignError::Storage(_)
+            | SignError::Pin(_)
+            | SignError::File(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for SignError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignError::CspNotFound(e) => write!(f, "Не найден cryptcp.exe (КриптоПро CSP): {}", e),
+            SignError::Pin(failure) => write!(f, "{}", failure),
+            SignError::Csp(e) => write!(f, "Ошибка подписи: {}", e),
+            SignError::Timeout(limit) => write!(
+                f,
+                "cryptcp не завершился за {} с и был остановлен: возможно, он ждал ввода PIN-кода или файл слишком большой",
+                limit.as_secs()
+            ),
+            SignError::InvalidSignature(e) => write!(f, "Подпись не прошла проверку: {}", e),
+            SignError::Clock(e) => write!(f, "{}", e),
+            SignError::File(e) => write!(f, "{}", e),
+            SignError::Timestamp(e) => {
+                write!(f, "Подпись создана, но штамп времени не получен: {}", e)
+            }
+            SignError::Api(e) => write!(f, "{}", e),
+            SignError::Storage(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<ApiError> for SignError {
+    fn from(e: ApiError) -> Self {
+        SignError::Api(e)
+    }
+}
+
+impl From<StorageError> for SignError {
+    fn from(e: StorageError) -> Self {
+        SignError::Storage(e)
+    }
+}
@@ -5,10 +5,32 @@ mod certificate;
 mod signing;
 mod dispenser;
 mod storage; // ← добавлено
+mod asn1;
+mod verify;
+mod batch;
+mod settings;
+mod tsa;
+mod pin;
+mod session;
+mod secure;
+mod net;
+mod clock;
+mod api;
+mod error;
+mod retry;
+mod ratelimit;
+mod demo;
+mod inspector;
+mod download;
 
 use certificate::{CertificateInfo, find_certificates};
-use signing::{sign_file_with_certificate, extract_attr};
+use signing::{sign_file_with_certificate, sign_user_file, prepare_signature_message, extract_attr, SignatureKind};
+use std::path::PathBuf;
 use dispenser::{TaskStatusForUI};
+use verify::VerificationReport;
+use batch::{BatchOptions, ExistingSignature, FileState};
+use storage::StoredToken;
+use error::{ErrorAction, SignError, VerifyError};
 
 const FAVICON: Asset = asset!("/assets/favicon.ico");
 const MAIN_CSS: Asset = asset!("/assets/main.css");
@@ -42,84 +64,2344 @@ fn App() -> Element {
     let mut tasks = use_signal(|| Vec::<TaskStatusForUI>::new());
     let mut loading_status = use_signal(|| false);
 
-    use_future(move || async move {
+    let task_polling = use_future(move || async move {
         tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
 
-        loop {
-            loading_status.set(true);
-            let statuses = dispenser::check_all_tasks().await;
-            tasks.set(statuses);
-            loading_status.set(false);
+        loop {
+            loading_status.set(true);
+            let statuses = dispenser::check_all_tasks().await;
+            tasks.set(statuses);
+            loading_status.set(false);
+
+            tokio::time::sleep(tokio::time::Duration::from_secs(30)).await;
+        }
+    });
+
+    let mut token = use_signal(|| storage::load_stored_token().ok());
+    let mut sessions = use_signal(storage::list_sessions);
+    let mut relogin_reasons = use_signal(Vec::<String>::new);
+
+    // Продление сеансов всех организаций: незадолго до истечения токена и после ответа 401
+    let session_refresh = use_future(move || async move {
+        // Сессии, которые не удалось продлить: ключ → (токен, причина).
+        // Повторная попытка — только после того, как токен сменится (ручной вход).
+        let mut failed = std::collections::HashMap::<String, (String, String)>::new();
+
+        loop {
+            let all = storage::list_sessions();
+            failed.retain(|key, (failed_token, _)| {
+                all.iter().any(|s| &s.key() == key && &s.token == failed_token)
+            });
+
+            for stored in &all {
+                let key = stored.key();
+                let needs_refresh = session::take_unauthorized(&key) || session::expires_soon(stored);
+                if !needs_refresh || failed.contains_key(&key) {
+                    continue;
+                }
+
+                // Пробуем войти тем же сертификатом; при неудаче просим пользователя
+                let cert = stored.thumbprint.as_ref().and_then(|thumb| {
+                    certificates()
+                        .and_then(|certs| certs.into_iter().find(|c| &c.thumbprint == thumb))
+                });
+                let reason = match cert {
+                    Some(cert) => match session::oms_of(stored) {
+                        Some(oms) => signing::sign_in_oms(&cert, None, &oms).await,
+                        None => signing::sign_in(&cert, None).await,
+                    }
+                    .err()
+                    .map(|e| {
+                        format!(
+                            "{}: не удалось продлить сессию автоматически: {}. Выберите сертификат для входа.",
+                            session::label(stored),
+                            e
+                        )
+                    }),
+                    None => Some(format!(
+                        "{}: сессия истекает. Выберите сертификат для повторного входа.",
+                        session::label(stored)
+                    )),
+                };
+                if let Some(reason) = reason {
+                    failed.insert(key, (stored.token.clone(), reason));
+                }
+            }
+
+            relogin_reasons.set(failed.values().map(|(_, reason)| reason.clone()).collect());
+            sessions.set(storage::list_sessions());
+            token.set(storage::load_stored_token().ok());
+
+            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+        }
+    });
+
+    // Последний повтор запроса к API; скрывается через 15 с
+    let mut retry_notice = use_signal(|| Option::<String>::None);
+    use_future(move || async move {
+        let mut events = retry::subscribe();
+        loop {
+            match tokio::time::timeout(std::time::Duration::from_secs(15), events.recv()).await {
+                Ok(Ok(event)) => retry_notice.set(Some(event.describe())),
+                Ok(Err(tokio::sync::broadcast::error::RecvError::Lagged(_))) => {}
+                Ok(Err(tokio::sync::broadcast::error::RecvError::Closed)) => break,
+                Err(_) => retry_notice.set(None),
+            }
+        }
+    });
+
+    let polling = use_context_provider(|| Polling {
+        tasks: task_polling,
+        sessions: session_refresh,
+        stopped: Signal::new(false),
+    });
+
+    // После полной очистки фоновые циклы остановлены до следующего входа
+    let wipe = move |_: ()| {
+        polling.stop();
+        tasks.set(Vec::new());
+        relogin_reasons.set(Vec::new());
+        sessions.set(Vec::new());
+        token.set(None);
+    };
+
+    rsx! {
+        document::Link { rel: "icon", href: FAVICON }
+        document::Link { rel: "stylesheet", href: MAIN_CSS }
+        document::Link { rel: "stylesheet", href: TAILWIND_CSS }
+
+        div { class: "min-h-screen bg-gray-900 text-white p-4",
+            h1 { class: "text-2xl font-bold mb-2 text-center",
+                "Электронные подписи в системе"
+            }
+            SessionHeader { token }
+            SessionSwitcher { sessions, token, tasks }
+            ForgetEverything { on_wiped: wipe }
+
+            if demo::is_active() {
+                div { class: "mb-6 p-3 bg-indigo-900/40 border border-indigo-600 rounded-xl text-indigo-200 text-sm",
+                    "🎓 Демо-режим: запросы обрабатывает встроенная имитация True API, сертификаты и подписи учебные. Выгрузки готовы примерно через 20 секунд."
+                }
+            }
+
+            if let Some(notice) = retry_notice() {
+                div { class: "mb-6 p-3 bg-gray-800 border border-gray-600 rounded-xl text-gray-300 text-xs",
+                    "🔁 {notice}"
+                }
+            }
+
+            for reason in relogin_reasons() {
+                div { class: "mb-6 p-4 bg-yellow-900/30 border border-yellow-700 rounded-xl text-yellow-100 text-sm",
+                    "⚠️ {reason}"
+                }
+            }
+
+            {
+                let today = chrono::Local::now().date_naive();
+                let warn_days = settings::load().expiry_warning_days;
+                let expiring: Vec<TaskStatusForUI> = tasks()
+                    .into_iter()
+                    .filter(|t| t.expires_soon(today, warn_days))
+                    .collect();
+                rsx! {
+                    for task in expiring {
+                        div { key: "{task.id}", class: "mb-6 p-4 bg-orange-900/30 border border-orange-700 rounded-xl text-orange-100 text-sm",
+                            {
+                                let until = task.expires_on.map(|d| d.format("%d.%m.%Y").to_string()).unwrap_or_default();
+                                format!(
+                                    "⚠️ Результат «{}» (ИНН {}) хранится на сервере до {} и ещё не скачан",
+                                    task.display_name(),
+                                    task.org_inn,
+                                    until
+                                )
+                            }
+                            if let Some(error) = task.download_error.clone() {
+                                ": {error}"
+                            }
+                        }
+                    }
+                }
+            }
+
+            TaskSync { tasks }
+
+            if tasks().len() > 0 {
+                div { class: "mb-6 p-4 bg-blue-900/30 border border-blue-700 rounded-xl",
+                    h2 { class: "text-lg font-semibold mb-3 flex items-center gap-2",
+                        svg {
+                            class: "w-5 h-5",
+                            xmlns: "http://www.w3.org/2000/svg",
+                            view_box: "0 0 24 24",
+                            fill: "none",
+                            stroke: "currentColor",
+                            stroke_width: "2",
+                            d: "M13 16h-1v-4h-1m1-4h.01M21 12a9 9 0 11-18 0 9 9 0 0118 0z",
+                        }
+                        "Статус выгрузок"
+                        button {
+                            class: "ml-auto px-3 py-1 rounded bg-gray-700 hover:bg-gray-600 text-sm font-normal",
+                            onclick: move |_| {
+                                if let Err(e) = download::downloads_dir().and_then(|dir| download::open_folder(&dir)) {
+                                    eprintln!("⚠️ {}", e);
+                                }
+                            },
+                            "📂 Папка загрузок"
+                        }
+                    }
+                    ul { class: "space-y-2 text-sm",
+                        for task in tasks().iter() {
+                            li { key: "{task.id}", class: "flex items-center gap-3",
+                                if task.is_completed {
+                                    span { class: "text-green-400", "✅" }
+                                    span { class: "font-medium text-green-100",
+                                        "Готово: {task.display_name()}"
+                                    }
+                                    TaskDownload { task: task.clone(), tasks }
+                                    if task.downloaded.is_none() {
+                                        if let Some(until) = task.expires_on {
+                                            span { class: "text-gray-400 text-xs",
+                                                {format!("хранится до {}", until.format("%d.%m.%Y"))}
+                                            }
+                                        }
+                                    }
+                                } else if task.error.is_some() {
+                                    span { class: "text-red-400", "❌" }
+                                    {
+                                        let error_msg = task.error.as_deref().unwrap_or("-");
+                                        rsx! {
+                                            span { class: "text-red-100", "Ошибка {task.display_name()}: {error_msg}" }
+                                        }
+                                    }
+                                } else if task.is_in_progress() {
+                                    span { class: "text-yellow-400", "⏳" }
+                                    span { class: "text-yellow-100",
+                                        "В обработке: {task.display_name()}"
+                                    }
+                                } else {
+                                    span { class: "text-gray-400", "⛔" }
+                                    span { class: "text-gray-300",
+                                        "{task.status_label()}: {task.display_name()}"
+                                    }
+                                }
+                                if !task.org_inn.is_empty() {
+                                    span { class: "text-gray-400", "ИНН {task.org_inn}" }
+                                }
+                                TaskActions { task: task.clone(), tasks }
+                            }
+                        }
+                    }
+                }
+            }
+
+            if loading_status() && tasks().is_empty() {
+                div { class: "mb-6 p-4 bg-gray-800 border border-gray-600 rounded-xl text-center",
+                    "Проверка статуса выгрузок..."
+                }
+            }
+
+            match certificates() {
+                Some(certs) => rsx! {
+                    CertificateSection { certificates: certs.clone() }
+                    FileSigningSection { certificates: certs.clone() }
+                    BatchSigningSection { certificates: certs.clone() }
+                },
+                None => rsx! {
+                    div { class: "text-center py-8", "Загрузка сертификатов..." }
+                },
+            }
+
+            ExportParamsSection {}
+            VerifySection {}
+            SettingsSection {}
+            HttpInspector {}
+        }
+    }
+}
+
+/// Загрузка задач, созданных в личном кабинете или на другом рабочем месте
+#[component]
+fn TaskSync(tasks: Signal<Vec<TaskStatusForUI>>) -> Element {
+    let mut status_filter = use_signal(String::new);
+    let mut group_filter = use_signal(String::new);
+    let mut date_from = use_signal(String::new);
+    let mut date_to = use_signal(String::new);
+    let mut syncing = use_signal(|| false);
+    let mut result = use_signal(|| Option::<String>::None);
+
+    let start_sync = move |_: MouseEvent| {
+        let parse_date = |text: String| chrono::NaiveDate::parse_from_str(&text, "%Y-%m-%d").ok();
+        let filter = dispenser::TaskFilter {
+            status: Some(status_filter()).filter(|s| !s.is_empty()),
+            product_group: group_filter().parse().ok(),
+            date_from: parse_date(date_from()),
+            date_to: parse_date(date_to()),
+        };
+        syncing.set(true);
+        result.set(None);
+        spawn(async move {
+            match dispenser::sync_tasks(&filter).await {
+                Ok(summary) => {
+                    result.set(Some(summary.describe()));
+                    tasks.set(dispenser::check_all_tasks().await);
+                }
+                Err(e) => result.set(Some(format!("❌ {}", e))),
+            }
+            syncing.set(false);
+        });
+    };
+
+    rsx! {
+        div { class: "mb-6 p-3 bg-gray-800/60 border border-gray-700 rounded-xl text-sm space-y-2",
+            div { class: "flex flex-wrap items-center gap-2",
+                "Задачи на сервере:"
+                select {
+                    class: "p-1 rounded bg-gray-800 border border-gray-700",
+                    value: "{status_filter}",
+                    onchange: move |e| status_filter.set(e.value()),
+                    option { value: "", "Все статусы" }
+                    for (status, label) in dispenser::TASK_STATUSES.iter().copied() {
+                        option { value: "{status}", "{label}" }
+                    }
+                }
+                select {
+                    class: "p-1 rounded bg-gray-800 border border-gray-700",
+                    value: "{group_filter}",
+                    onchange: move |e| group_filter.set(e.value()),
+                    option { value: "", "Все товарные группы" }
+                    for (code, name) in dispenser::PRODUCT_GROUPS.iter().copied() {
+                        option { value: "{code}", "{code} — {name}" }
+                    }
+                }
+                "с"
+                input {
+                    class: "p-1 rounded bg-gray-800 border border-gray-700",
+                    r#type: "date",
+                    value: "{date_from}",
+                    oninput: move |e| date_from.set(e.value()),
+                }
+                "по"
+                input {
+                    class: "p-1 rounded bg-gray-800 border border-gray-700",
+                    r#type: "date",
+                    value: "{date_to}",
+                    oninput: move |e| date_to.set(e.value()),
+                }
+                button {
+                    class: "px-3 py-1 rounded bg-blue-700 hover:bg-blue-600 disabled:opacity-50",
+                    disabled: syncing(),
+                    onclick: start_sync,
+                    if syncing() { "Синхронизация..." } else { "🔄 Синхронизировать с сервером" }
+                }
+            }
+            if let Some(text) = result() {
+                div { class: "whitespace-pre-line text-gray-300", "{text}" }
+            }
+        }
+    }
+}
+
+/// Кнопки скачивания готового результата и открытия папки с ним
+#[component]
+fn TaskDownload(task: TaskStatusForUI, tasks: Signal<Vec<TaskStatusForUI>>) -> Element {
+    let mut downloading = use_signal(|| false);
+    let mut error = use_signal(|| Option::<String>::None);
+
+    let start_download = {
+        let id = task.id.clone();
+        move |_: MouseEvent| {
+            let id = id.clone();
+            downloading.set(true);
+            error.set(None);
+            spawn(async move {
+                match dispenser::download_task(&id).await {
+                    Ok(path) => {
+                        if let Some(task) = tasks.write().iter_mut().find(|t| t.id == id) {
+                            task.downloaded = Some(path);
+                        }
+                    }
+                    Err(e) => error.set(Some(e.to_string())),
+                }
+                downloading.set(false);
+            });
+        }
+    };
+
+    rsx! {
+        if let Some(path) = task.downloaded.clone() {
+            button {
+                class: "px-2 py-1 rounded bg-gray-700 hover:bg-gray-600 text-xs",
+                title: "{path.display()}",
+                onclick: move |_| {
+                    if let Err(e) = download::open_folder(&path) {
+                        error.set(Some(e));
+                    }
+                },
+                "📂 Открыть папку"
+            }
+        }
+        button {
+            class: "px-2 py-1 rounded bg-blue-700 hover:bg-blue-600 text-xs disabled:opacity-50",
+            disabled: downloading(),
+            onclick: start_download,
+            if downloading() {
+                "Скачивание..."
+            } else if task.downloaded.is_some() {
+                "Скачать снова"
+            } else {
+                "⬇ Скачать"
+            }
+        }
+        if let Some(e) = error() {
+            span { class: "text-red-300 text-xs", "{e}" }
+        }
+    }
+}
+
+/// Что пользователь собирается сделать с задачей
+#[derive(Clone, Copy, PartialEq)]
+enum TaskAction {
+    Cancel,
+    Delete,
+}
+
+/// Отмена задачи в очереди и удаление из списка — после подтверждения
+#[component]
+fn TaskActions(task: TaskStatusForUI, tasks: Signal<Vec<TaskStatusForUI>>) -> Element {
+    let mut confirming = use_signal(|| Option::<TaskAction>::None);
+    let mut on_server = use_signal(|| true);
+    let mut busy = use_signal(|| false);
+    let mut message = use_signal(|| Option::<String>::None);
+
+    let run = {
+        let id = task.id.clone();
+        move |_: MouseEvent| {
+            let Some(action) = confirming() else {
+                return;
+            };
+            let id = id.clone();
+            confirming.set(None);
+            busy.set(true);
+            message.set(None);
+            spawn(async move {
+                match action {
+                    TaskAction::Cancel => match dispenser::cancel_task(&id).await {
+                        Ok(()) => {
+                            if let Some(task) = tasks.write().iter_mut().find(|t| t.id == id) {
+                                task.status = "CANCELED".to_string();
+                            }
+                        }
+                        Err(e) => message.set(Some(format!("❌ {}", e))),
+                    },
+                    TaskAction::Delete => match dispenser::delete_task(&id, on_server()).await {
+                        Ok(warning) => {
+                            // Предупреждение показывается, пока строка ещё на месте
+                            if let Some(warning) = warning {
+                                message.set(Some(format!("⚠️ {}", warning)));
+                                tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+                            }
+                            tasks.write().retain(|t| t.id != id);
+                        }
+                        Err(e) => message.set(Some(format!("❌ {}", e))),
+                    },
+                }
+                busy.set(false);
+            });
+        }
+    };
+
+    rsx! {
+        match confirming() {
+            Some(action) => rsx! {
+                span { class: "text-yellow-300 text-xs",
+                    if action == TaskAction::Cancel {
+                        "Остановить выгрузку {task.display_name()}?"
+                    } else {
+                        "Удалить задачу {task.display_name()} из списка?"
+                    }
+                }
+                if action == TaskAction::Delete {
+                    label { class: "flex items-center gap-1 text-xs text-gray-300",
+                        input {
+                            r#type: "checkbox",
+                            checked: on_server(),
+                            onchange: move |e| on_server.set(e.checked()),
+                        }
+                        "и на сервере"
+                    }
+                }
+                button {
+                    class: "px-2 py-1 rounded bg-red-700 hover:bg-red-600 text-xs",
+                    onclick: run,
+                    "Да"
+                }
+                button {
+                    class: "px-2 py-1 rounded bg-gray-700 hover:bg-gray-600 text-xs",
+                    onclick: move |_| confirming.set(None),
+                    "Нет"
+                }
+            },
+            None => rsx! {
+                if task.is_in_progress() {
+                    button {
+                        class: "px-2 py-1 rounded bg-gray-700 hover:bg-gray-600 text-xs disabled:opacity-50",
+                        disabled: busy(),
+                        onclick: move |_| confirming.set(Some(TaskAction::Cancel)),
+                        "⛔ Отменить"
+                    }
+                }
+                button {
+                    class: "px-2 py-1 rounded bg-gray-700 hover:bg-gray-600 text-xs disabled:opacity-50",
+                    disabled: busy(),
+                    title: "Убрать задачу из списка",
+                    onclick: move |_| confirming.set(Some(TaskAction::Delete)),
+                    "🗑"
+                }
+            },
+        }
+        if let Some(msg) = message() {
+            span { class: "text-orange-300 text-xs", "{msg}" }
+        }
+    }
+}
+
+/// Добавляет файлы в список без повторов
+fn merge_paths(mut files: Signal<Vec<PathBuf>>, paths: Vec<PathBuf>) {
+    let mut current = files.write();
+    for path in paths {
+        if !current.contains(&path) {
+            current.push(path);
+        }
+    }
+}
+
+/// Оставшееся время сеанса; обновляется раз в секунду
+#[component]
+fn SessionHeader(token: Signal<Option<StoredToken>>) -> Element {
+    let mut now = use_signal(chrono::Utc::now);
+
+    use_future(move || async move {
+        loop {
+            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+            now.set(chrono::Utc::now());
+        }
+    });
+
+    // Подписываемся на тик, чтобы перерисовывать обратный отсчёт
+    let _tick = now();
+
+    rsx! {
+        p { class: "text-center text-sm text-gray-400 mb-6",
+            match token() {
+                Some(stored) => rsx! { "Сессия: {session::status_text(&stored)}" },
+                None => rsx! { "Вход не выполнен" },
+            }
+        }
+    }
+}
+
+/// Фоновые циклы приложения: опрос статусов выгрузок и продление сессий
+#[derive(Clone, Copy)]
+struct Polling {
+    tasks: UseFuture,
+    sessions: UseFuture,
+    stopped: Signal<bool>,
+}
+
+impl Polling {
+    /// Останавливает циклы (после «Забыть всё»)
+    fn stop(mut self) {
+        self.tasks.cancel();
+        self.sessions.cancel();
+        self.stopped.set(true);
+    }
+
+    /// Возобновляет циклы после нового входа, если они были остановлены
+    fn resume(mut self) {
+        if *self.stopped.peek() {
+            self.tasks.restart();
+            self.sessions.restart();
+            self.stopped.set(false);
+        }
+    }
+}
+
+/// «Забыть всё»: удаляет токены, кэш PIN, временные файлы и реестр задач.
+/// Для смены оператора на общем рабочем месте; требует подтверждения.
+#[component]
+fn ForgetEverything(on_wiped: EventHandler<()>) -> Element {
+    let mut confirming = use_signal(|| false);
+    let mut status = use_signal(|| Option::<String>::None);
+
+    let wipe = move |_: MouseEvent| {
+        confirming.set(false);
+        pin::forget_all();
+        dispenser::clear_tasks();
+        let message = match storage::forget_everything() {
+            Ok(()) => "Все учётные данные и временные файлы удалены".to_string(),
+            Err(e) => format!("Удалено не всё: {}", e),
+        };
+        status.set(Some(message));
+        on_wiped.call(());
+    };
+
+    rsx! {
+        div { class: "mb-6 flex flex-wrap items-center justify-end gap-3 text-sm",
+            if let Some(msg) = status() {
+                span { class: "text-gray-300", "{msg}" }
+            }
+            if confirming() {
+                span { class: "text-yellow-300",
+                    "Удалить все токены, PIN-коды, ключ шифрования, пароль прокси, временные файлы и список выгрузок? В Windows также будет очищен кэш списков отзыва сертификатов (CRL) — он общий для всех программ этой учётной записи."
+                }
+                button {
+                    class: "px-3 py-1 rounded bg-red-700 hover:bg-red-600",
+                    onclick: wipe,
+                    "Да, забыть всё"
+                }
+                button {
+                    class: "px-3 py-1 rounded bg-gray-700 hover:bg-gray-600",
+                    onclick: move |_| confirming.set(false),
+                    "Отмена"
+                }
+            } else {
+                button {
+                    class: "px-3 py-1 rounded bg-gray-800 border border-gray-700 hover:border-red-600",
+                    onclick: move |_| {
+                        status.set(None);
+                        confirming.set(true);
+                    },
+                    "Забыть всё"
+                }
+            }
+        }
+    }
+}
+
+/// Переключатель сессий организаций и запуск выгрузки по одной или всем
+#[component]
+fn SessionSwitcher(
+    sessions: Signal<Vec<StoredToken>>,
+    token: Signal<Option<StoredToken>>,
+    tasks: Signal<Vec<TaskStatusForUI>>,
+) -> Element {
+    let mut export_all = use_signal(|| false);
+    let mut export_status = use_signal(|| Option::<String>::None);
+    let mut exporting = use_signal(|| false);
+    let mut pin_request = use_signal(|| Option::<PinRequest>::None);
+
+    if sessions().is_empty() {
+        return rsx! {};
+    }
+
+    let active_key = token().map(|t| t.key()).unwrap_or_default();
+
+    // Подключение СУЗ для организации выбранной сессии True API
+    let oms = token()
+        .filter(|t| !t.is_oms())
+        .and_then(|t| t.inn)
+        .and_then(|inn| settings::oms_for_inn(&inn));
+
+    let mut start_oms_sign_in = move |pin: Option<String>, remember: bool| {
+        let Some(stored) = token() else {
+            return;
+        };
+        let Some(oms) = stored.inn.as_deref().and_then(settings::oms_for_inn) else {
+            return;
+        };
+        let cert = stored.thumbprint.as_ref().and_then(|thumb| {
+            find_certificates().into_iter().find(|c| &c.thumbprint == thumb)
+        });
+        let Some(cert) = cert else {
+            export_status.set(Some("Ошибка: сертификат сессии не найден в хранилище".to_string()));
+            return;
+        };
+
+        exporting.set(true);
+        export_status.set(None);
+        spawn(async move {
+            let pin = pin.map(zeroize::Zeroizing::new);
+            let pin_str = pin.as_deref().map(|p| p.as_str());
+            match signing::sign_in_oms(&cert, pin_str, &oms).await {
+                Ok(fresh) => {
+                    if let (true, Some(p)) = (remember, pin_str) {
+                        pin::remember(&cert.thumbprint, p);
+                    }
+                    sessions.set(storage::list_sessions());
+                    export_status.set(Some(format!(
+                        "Получен токен СУЗ {} ({})",
+                        oms.oms_id,
+                        session::status_text(&fresh)
+                    )));
+                }
+                Err(error) if error.needs_pin() => {
+                    pin_request.set(Some(PinRequest { cert: cert.clone(), reason: error.to_string() }));
+                }
+                Err(error) => export_status.set(Some(format!("Ошибка: {}", error))),
+            }
+            exporting.set(false);
+        });
+    };
+
+    let start_export = move |_: MouseEvent| {
+        let scope = if export_all() {
+            dispenser::ExportScope::All
+        } else {
+            match token() {
+                Some(stored) => dispenser::ExportScope::Session(stored.key()),
+                None => return,
+            }
+        };
+        exporting.set(true);
+        export_status.set(None);
+        spawn(async move {
+            let params = settings::load().export_params;
+            let message = match dispenser::fetch_violation_tasks(scope, &params).await {
+                Ok(results) => results.join("\n"),
+                Err(e) => format!("Ошибка: {}", e),
+            };
+            export_status.set(Some(message));
+            exporting.set(false);
+        });
+    };
+
+    rsx! {
+        div { class: "mb-6 p-4 bg-gray-800 border border-gray-700 rounded-xl space-y-3",
+            div { class: "flex flex-wrap items-center gap-3",
+                span { class: "text-sm text-gray-400", "Организация:" }
+                select {
+                    class: "flex-1 p-2 rounded bg-gray-900 text-white border border-gray-700",
+                    value: "{active_key}",
+                    onchange: move |e| {
+                        let key = e.value();
+                        if let Err(err) = storage::set_active_session(&key) {
+                            export_status.set(Some(format!("Ошибка: {}", err)));
+                        }
+                        token.set(sessions().into_iter().find(|s| s.key() == key));
+                    },
+                    for stored in sessions() {
+                        option { value: "{stored.key()}", "{session::label(&stored)}" }
+                    }
+                }
+            }
+            div { class: "flex flex-wrap items-center gap-3",
+                label { class: "flex items-center gap-2 text-sm",
+                    input {
+                        r#type: "checkbox",
+                        checked: export_all(),
+                        onchange: move |e| export_all.set(e.checked()),
+                    }
+                    "Все организации ({sessions().len()})"
+                }
+                button {
+                    class: "px-4 py-2 rounded bg-blue-700 hover:bg-blue-600 disabled:opacity-50",
+                    disabled: exporting(),
+                    onclick: start_export,
+                    if exporting() { "Запрос..." } else { "Запросить выгрузку" }
+                }
+                button {
+                    class: "px-4 py-2 rounded bg-gray-700 hover:bg-red-700",
+                    onclick: move |_| {
+                        let Some(stored) = token() else {
+                            return;
+                        };
+                        let key = stored.key();
+                        if let Err(e) = storage::delete_session(&key) {
+                            export_status.set(Some(format!("Ошибка: {}", e)));
+                            return;
+                        }
+                        dispenser::forget_session_tasks(&key);
+                        let org_inn = stored.inn.clone().unwrap_or_default();
+                        if !storage::list_sessions().iter().any(|s| s.inn.as_deref() == Some(org_inn.as_str())) {
+                            tasks.write().retain(|t| t.org_inn != org_inn);
+                        }
+                        sessions.set(storage::list_sessions());
+                        token.set(storage::load_stored_token().ok());
+                        export_status.set(Some(format!("Выполнен выход: {}", session::label(&stored))));
+                    },
+                    "Выйти"
+                }
+                if let Some(oms) = oms {
+                    button {
+                        class: "px-4 py-2 rounded bg-gray-700 hover:bg-gray-600 disabled:opacity-50",
+                        disabled: exporting(),
+                        onclick: move |_| start_oms_sign_in(None, false),
+                        "Получить токен СУЗ ({oms.oms_id})"
+                    }
+                }
+            }
+            div { class: "flex flex-wrap items-center gap-2 text-xs text-gray-400",
+                "Параметры выгрузки: {settings::load().export_params.summary()}"
+                button {
+                    class: "underline hover:text-gray-200",
+                    onclick: move |_| {
+                        document::eval("document.getElementById('export')?.scrollIntoView({ behavior: 'smooth' })");
+                    },
+                    "изменить"
+                }
+            }
+            if let Some(status) = export_status() {
+                pre { class: "text-xs text-gray-300 whitespace-pre-wrap", "{status}" }
+            }
+            if let Some(request) = pin_request() {
+                PinDialog {
+                    request,
+                    on_submit: move |(pin, remember): (String, bool)| {
+                        pin_request.set(None);
+                        start_oms_sign_in(Some(pin), remember);
+                    },
+                    on_cancel: move |_| pin_request.set(None),
+                }
+            }
+        }
+    }
+}
+
+/// Запрос PIN-кода: для какого сертификата и по какой причине
+#[derive(Clone, PartialEq)]
+struct PinRequest {
+    cert: CertificateInfo,
+    reason: String,
+}
+
+#[component]
+fn PinDialog(
+    request: PinRequest,
+    on_submit: EventHandler<(String, bool)>,
+    on_cancel: EventHandler<()>,
+) -> Element {
+    let mut pin_input = use_signal(String::new);
+    let mut remember = use_signal(|| false);
+    let minutes = settings::load().pin_cache_minutes;
+    let name = extract_attr(&request.cert.subject_name, "CN=")
+        .unwrap_or(request.cert.subject_name.clone());
+
+    // PIN забирается из поля целиком, чтобы не оставлять копию в состоянии компонента
+    let mut submit = move || {
+        let pin = std::mem::take(&mut *pin_input.write());
+        on_submit.call((pin, remember() && minutes > 0));
+    };
+
+    rsx! {
+        div { class: "fixed inset-0 bg-black bg-opacity-50 flex items-center justify-center z-50",
+            div { class: "bg-gray-800 rounded-lg p-6 w-96 space-y-4",
+                h3 { class: "text-lg font-semibold", "PIN-код контейнера" }
+                p { class: "text-sm text-gray-300", "{name}" }
+                p { class: "text-sm text-yellow-300", "{request.reason}" }
+                input {
+                    class: "w-full p-2 rounded bg-gray-900 border border-gray-700",
+                    r#type: "password",
+                    autofocus: true,
+                    value: pin_input(),
+                    oninput: move |e| pin_input.set(e.value()),
+                    onkeydown: move |e: KeyboardEvent| {
+                        if e.key() == Key::Enter {
+                            submit();
+                        }
+                    },
+                }
+                if minutes > 0 {
+                    label { class: "flex items-center gap-2 text-sm",
+                        input {
+                            r#type: "checkbox",
+                            checked: remember(),
+                            onchange: move |e| remember.set(e.checked()),
+                        }
+                        "Запомнить на {minutes} мин"
+                    }
+                }
+                div { class: "flex justify-end gap-3",
+                    button {
+                        class: "px-4 py-2 rounded bg-gray-700 hover:bg-gray-600",
+                        onclick: move |_| {
+                            pin_input.set(String::new());
+                            on_cancel.call(());
+                        },
+                        "Отмена"
+                    }
+                    button {
+                        class: "px-4 py-2 rounded bg-blue-700 hover:bg-blue-600",
+                        onclick: move |_| submit(),
+                        "OK"
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Состояние входа в разделе сертификатов, разделяемое с фоновой задачей
+#[derive(Clone, Copy)]
+struct SignInState {
+    status: Signal<Option<Result<String, SignError>>>,
+    loading: Signal<bool>,
+    pin_request: Signal<Option<PinRequest>>,
+    polling: Polling,
+}
+
+/// Запускает вход по сертификату; при ошибке PIN открывает диалог ввода
+fn start_sign_in(cert: CertificateInfo, pin: Option<String>, remember: bool, mut state: SignInState) {
+    state.loading.set(true);
+    spawn(async move {
+        let pin = pin.map(zeroize::Zeroizing::new);
+        let pin_str = pin.as_deref().map(|p| p.as_str());
+        match sign_file_with_certificate(&cert, pin_str).await {
+            Ok(message) => {
+                if let (true, Some(p)) = (remember, pin_str) {
+                    pin::remember(&cert.thumbprint, p);
+                }
+                state.polling.resume();
+                state.status.set(Some(Ok(message)));
+            }
+            Err(error) if error.needs_pin() => {
+                state.pin_request.set(Some(PinRequest { cert: cert.clone(), reason: error.to_string() }));
+            }
+            Err(error) => {
+                state.status.set(Some(Err(error)));
+            }
+        }
+        state.loading.set(false);
+    });
+}
+
+/// Ввод парольной фразы, когда токены нечем зашифровать (нет Secret Service).
+/// Фраза хранится только в памяти; без неё сохранённые токены не прочитать.
+#[component]
+fn PassphrasePrompt(message: String, on_submit: EventHandler<String>) -> Element {
+    let mut passphrase = use_signal(String::new);
+    let mut confirmation = use_signal(String::new);
+    let mismatch = !confirmation().is_empty() && passphrase() != confirmation();
+    let ready = !passphrase().is_empty() && passphrase() == confirmation();
+
+    rsx! {
+        div { class: "rounded-xl border border-yellow-700/50 bg-yellow-900/20 text-yellow-100 px-4 py-3 text-sm space-y-2",
+            p { "{message}" }
+            div { class: "flex flex-wrap items-center gap-2",
+                input {
+                    class: "p-2 rounded bg-gray-800 border border-gray-700",
+                    r#type: "password",
+                    placeholder: "Парольная фраза",
+                    value: "{passphrase}",
+                    oninput: move |e| passphrase.set(e.value()),
+                }
+                input {
+                    class: "p-2 rounded bg-gray-800 border border-gray-700",
+                    r#type: "password",
+                    placeholder: "Повторите",
+                    value: "{confirmation}",
+                    oninput: move |e| confirmation.set(e.value()),
+                }
+                button {
+                    class: "px-3 py-2 rounded bg-blue-700 hover:bg-blue-600 disabled:opacity-50",
+                    disabled: !ready,
+                    onclick: move |_| {
+                        on_submit.call(passphrase());
+                        passphrase.set(String::new());
+                        confirmation.set(String::new());
+                    },
+                    "Сохранить и войти"
+                }
+            }
+            if mismatch {
+                p { class: "text-red-300 text-xs", "Фразы не совпадают" }
+            }
+            p { class: "text-gray-400 text-xs",
+                "Фраза не сохраняется на диск: после перезапуска её нужно будет ввести снова, чтобы прочитать сохранённые токены."
+            }
+        }
+    }
+}
+
+/// Прокручивает страницу к разделу настроек
+fn open_settings() {
+    document::eval("document.getElementById('settings')?.scrollIntoView({ behavior: 'smooth' })");
+}
+
+/// Выпадающий список сертификатов; в `selected` хранится отпечаток
+#[component]
+fn CertificateSelect(certificates: Vec<CertificateInfo>, selected: Signal<String>) -> Element {
+    rsx! {
+        select {
+            class: "w-full p-2 rounded bg-gray-800 text-white border border-gray-700",
+            value: selected(),
+            onchange: move |e| selected.set(e.value()),
+            for cert in certificates.iter() {
+                option { value: "{cert.thumbprint}",
+                    {extract_attr(&cert.subject_name, "CN=").unwrap_or(cert.subject_name.clone())}
+                }
+            }
+        }
+    }
+}
+
+/// Состояние раздела подписи файлов, разделяемое с фоновой задачей
+#[derive(Clone, Copy)]
+struct FileSigningState {
+    files: Signal<Vec<PathBuf>>,
+    results: Signal<Vec<(PathBuf, Result<PathBuf, String>)>>,
+    signing: Signal<bool>,
+    pin_request: Signal<Option<PinRequest>>,
+}
+
+/// Подписывает файлы из списка по очереди. Подписанные и завершившиеся ошибкой
+/// убираются из списка; при ошибке PIN обработка останавливается и открывается диалог.
+fn start_file_signing(
+    cert: CertificateInfo,
+    kind: SignatureKind,
+    pin: Option<String>,
+    remember: bool,
+    mut state: FileSigningState,
+) {
+    let paths = state.files.peek().clone();
+    state.signing.set(true);
+    spawn(async move {
+        let pin = pin.map(zeroize::Zeroizing::new);
+        let pin_str = pin.as_deref().map(|p| p.as_str());
+        let mut signed_any = false;
+
+        for path in paths {
+            match sign_user_file(&cert, &path, kind, pin_str).await {
+                Err(error) if error.needs_pin() => {
+                    state.pin_request.set(Some(PinRequest { cert: cert.clone(), reason: error.to_string() }));
+                    break;
+                }
+                outcome => {
+                    signed_any |= outcome.is_ok();
+                    state.files.write().retain(|p| p != &path);
+                    state.results.write().push((path, outcome.map_err(|e| e.to_string())));
+                }
+            }
+        }
+
+        if let (true, true, Some(p)) = (remember, signed_any, pin_str) {
+            pin::remember(&cert.thumbprint, p);
+        }
+        state.signing.set(false);
+    });
+}
+
+#[component]
+fn FileSigningSection(certificates: Vec<CertificateInfo>) -> Element {
+    let selected_thumb = use_signal(|| {
+        certificates
+            .first()
+            .map(|c| c.thumbprint.clone())
+            .unwrap_or_default()
+    });
+    let mut kind = use_signal(|| SignatureKind::Detached);
+    let mut files = use_signal(Vec::<PathBuf>::new);
+    let mut results = use_signal(Vec::<(PathBuf, Result<PathBuf, String>)>::new);
+    let signing = use_signal(|| false);
+    let mut pin_request = use_signal(|| Option::<PinRequest>::None);
+
+    let selected = certificates
+        .iter()
+        .find(|c| c.thumbprint == selected_thumb())
+        .cloned();
+
+    let pick_files = move |_: MouseEvent| {
+        spawn(async move {
+            if let Some(handles) = rfd::AsyncFileDialog::new().pick_files().await {
+                merge_paths(files, handles.iter().map(|h| h.path().to_path_buf()).collect());
+                results.set(Vec::new());
+            }
+        });
+    };
+
+    let state = FileSigningState {
+        files,
+        results,
+        signing,
+        pin_request,
+    };
+
+    let selected_for_sign = selected.clone();
+    let sign_all = move |_: MouseEvent| {
+        let Some(cert) = selected_for_sign.clone() else {
+            return;
+        };
+        results.set(Vec::new());
+        start_file_signing(cert, kind(), None, false, state);
+    };
+
+    rsx! {
+        div { class: "mt-8 p-4 bg-gray-800/60 border border-gray-700 rounded-xl space-y-3",
+            h2 { class: "text-lg font-semibold", "Подпись файлов" }
+
+            CertificateSelect { certificates: certificates.clone(), selected: selected_thumb }
+            if let Some(cert) = selected.as_ref() {
+                p { class: "text-sm text-gray-400", "{prepare_signature_message(cert)}" }
+            }
+
+            div { class: "flex flex-wrap gap-3",
+                for option_kind in [SignatureKind::Detached, SignatureKind::Attached] {
+                    button {
+                        class: if kind() == option_kind { "px-4 py-2 rounded bg-blue-700" } else { "px-4 py-2 rounded bg-gray-700 hover:bg-gray-600" },
+                        onclick: move |_| kind.set(option_kind),
+                        "{option_kind.label()}"
+                    }
+                }
+            }
+
+            div {
+                class: "p-6 border-2 border-dashed border-gray-600 rounded-xl text-center text-gray-400",
+                ondragover: move |evt: DragEvent| evt.prevent_default(),
+                ondrop: move |evt: DragEvent| {
+                    evt.prevent_default();
+                    merge_paths(files, evt.files().iter().map(|f| f.path()).collect());
+                    results.set(Vec::new());
+                },
+                "Перетащите файлы сюда или "
+                button {
+                    class: "underline text-blue-300",
+                    onclick: pick_files,
+                    "выберите их"
+                }
+            }
+
+            if !files().is_empty() {
+                ul { class: "text-sm space-y-1",
+                    for path in files().iter() {
+                        li { class: "text-gray-300", "📄 {path.display()}" }
+                    }
+                }
+                div { class: "flex gap-3",
+                    button {
+                        class: "px-4 py-2 rounded bg-blue-700 hover:bg-blue-600 disabled:opacity-50",
+                        disabled: signing() || selected.is_none(),
+                        onclick: sign_all,
+                        if signing() { "Подпись..." } else { "Подписать" }
+                    }
+                    button {
+                        class: "px-4 py-2 rounded bg-gray-700 hover:bg-gray-600",
+                        disabled: signing(),
+                        onclick: move |_| files.set(Vec::new()),
+                        "Очистить"
+                    }
+                }
+            }
+
+            if let Some(request) = pin_request() {
+                {
+                    let cert = request.cert.clone();
+                    let signature_kind = kind();
+                    rsx! {
+                        PinDialog {
+                            request,
+                            on_submit: move |(pin, remember): (String, bool)| {
+                                pin_request.set(None);
+                                start_file_signing(cert.clone(), signature_kind, Some(pin), remember, state);
+                            },
+                            on_cancel: move |_| pin_request.set(None),
+                        }
+                    }
+                }
+            }
+
+            if !results().is_empty() {
+                ul { class: "text-sm space-y-1",
+                    for (path, outcome) in results().iter() {
+                        match outcome {
+                            Ok(sig) => rsx! {
+                                li { class: "text-green-200", "✅ {sig.display()}" }
+                            },
+                            Err(error) => rsx! {
+                                li { class: "text-red-200", "❌ {path.display()}: {error}" }
+                            },
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Состояние пакетной подписи, разделяемое с фоновой задачей
+#[derive(Clone, Copy)]
+struct BatchState {
+    files: Signal<Vec<PathBuf>>,
+    states: Signal<Vec<FileState>>,
+    running: Signal<bool>,
+    summary: Signal<Option<Result<String, String>>>,
+    pin_request: Signal<Option<PinRequest>>,
+}
+
+/// Подписывает все файлы папки и пишет итоговый отчёт.
+/// Если не подошёл PIN, отчёт не пишется — открывается диалог ввода.
+fn start_batch(
+    cert: CertificateInfo,
+    dir: PathBuf,
+    opts: BatchOptions,
+    pin: Option<String>,
+    remember: bool,
+    mut state: BatchState,
+) {
+    let list = match batch::collect_files(&dir, opts.recursive) {
+        Ok(list) => list,
+        Err(e) => {
+            state.summary.set(Some(Err(e)));
+            return;
+        }
+    };
+
+    state.files.set(list.clone());
+    state.states.set(vec![FileState::Pending; list.len()]);
+    state.summary.set(None);
+    state.running.set(true);
+
+    spawn(async move {
+        let pin = pin.map(zeroize::Zeroizing::new);
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let worker = tokio::spawn(batch::sign_files(
+            cert.clone(),
+            list.clone(),
+            opts,
+            pin.as_ref().map(|p| p.to_string()),
+            tx,
+        ));
+
+        while let Some((idx, file_state)) = rx.recv().await {
+            if let Some(slot) = state.states.write().get_mut(idx) {
+                *slot = file_state;
+            }
+        }
+
+        let final_states = worker
+            .await
+            .unwrap_or_else(|e| vec![FileState::Failed(batch::interrupted(e)); list.len()]);
+
+        let pin_error = final_states.iter().find_map(|s| match s {
+            FileState::Failed(e) if e.needs_pin() => Some(e.to_string()),
+            _ => None,
+        });
+        if let Some(reason) = pin_error {
+            state.states.set(final_states);
+            state.pin_request.set(Some(PinRequest { cert, reason }));
+            state.running.set(false);
+            return;
+        }
+
+        let signed = final_states.iter().filter(|s| matches!(s, FileState::Signed(_))).count();
+        let skipped = final_states.iter().filter(|s| matches!(s, FileState::Skipped)).count();
+        let failed = final_states.iter().filter(|s| matches!(s, FileState::Failed(_))).count();
+
+        if let (true, Some(p)) = (remember && signed > 0, pin.as_deref()) {
+            pin::remember(&cert.thumbprint, p);
+        }
+
+        let result = batch::write_report(&dir, &list, &final_states).map(|(csv, json)| {
+            format!(
+                "Подписано: {}, пропущено: {}, ошибок: {}. Отчёт: {} / {}",
+                signed,
+                skipped,
+                failed,
+                csv.display(),
+                json.display()
+            )
+        });
+        state.states.set(final_states);
+        state.summary.set(Some(result));
+        state.running.set(false);
+    });
+}
+
+#[component]
+fn BatchSigningSection(certificates: Vec<CertificateInfo>) -> Element {
+    let selected_thumb = use_signal(|| {
+        certificates
+            .first()
+            .map(|c| c.thumbprint.clone())
+            .unwrap_or_default()
+    });
+    let mut folder = use_signal(|| Option::<PathBuf>::None);
+    let mut options = use_signal(|| BatchOptions {
+        kind: SignatureKind::Detached,
+        existing: ExistingSignature::Skip,
+        concurrency: 4,
+        recursive: false,
+    });
+    let mut files = use_signal(Vec::<PathBuf>::new);
+    let mut states = use_signal(Vec::<FileState>::new);
+    let running = use_signal(|| false);
+    let mut summary = use_signal(|| Option::<Result<String, String>>::None);
+    let mut pin_request = use_signal(|| Option::<PinRequest>::None);
+
+    let selected = certificates
+        .iter()
+        .find(|c| c.thumbprint == selected_thumb())
+        .cloned();
+
+    let pick_folder = move |_: MouseEvent| {
+        spawn(async move {
+            if let Some(handle) = rfd::AsyncFileDialog::new().pick_folder().await {
+                folder.set(Some(handle.path().to_path_buf()));
+                files.set(Vec::new());
+                states.set(Vec::new());
+                summary.set(None);
+            }
+        });
+    };
+
+    let state = BatchState {
+        files,
+        states,
+        running,
+        summary,
+        pin_request,
+    };
+
+    let selected_for_start = selected.clone();
+    let start = move |_: MouseEvent| {
+        let (Some(cert), Some(dir)) = (selected_for_start.clone(), folder()) else {
+            return;
+        };
+        start_batch(cert, dir, options(), None, false, state);
+    };
+
+    let done = states().iter().filter(|s| s.is_finished()).count();
+    let total = states().len();
+    let percent = if total == 0 { 0 } else { done * 100 / total };
+
+    rsx! {
+        div { class: "mt-8 p-4 bg-gray-800/60 border border-gray-700 rounded-xl space-y-3",
+            h2 { class: "text-lg font-semibold", "Пакетная подпись папки" }
+
+            CertificateSelect { certificates: certificates.clone(), selected: selected_thumb }
+
+            div { class: "flex flex-wrap items-center gap-3 text-sm",
+                button {
+                    class: "px-4 py-2 rounded bg-gray-700 hover:bg-gray-600",
+                    disabled: running(),
+                    onclick: pick_folder,
+                    "Выбрать папку..."
+                }
+                for kind in [SignatureKind::Detached, SignatureKind::Attached] {
+                    button {
+                        class: if options().kind == kind { "px-3 py-2 rounded bg-blue-700" } else { "px-3 py-2 rounded bg-gray-700 hover:bg-gray-600" },
+                        onclick: move |_| options.write().kind = kind,
+                        "{kind.label()}"
+                    }
+                }
+                for existing in [ExistingSignature::Skip, ExistingSignature::Overwrite] {
+                    button {
+                        class: if options().existing == existing { "px-3 py-2 rounded bg-blue-700" } else { "px-3 py-2 rounded bg-gray-700 hover:bg-gray-600" },
+                        onclick: move |_| options.write().existing = existing,
+                        "{existing.label()}"
+                    }
+                }
+                label { class: "flex items-center gap-2",
+                    "Потоков:"
+                    input {
+                        class: "w-16 p-1 rounded bg-gray-800 border border-gray-700",
+                        r#type: "number",
+                        min: "1",
+                        max: "16",
+                        value: "{options().concurrency}",
+                        oninput: move |e| {
+                            if let Ok(n) = e.value().parse::<usize>() {
+                                options.write().concurrency = n.clamp(1, 16);
+                            }
+                        },
+                    }
+                }
+                label { class: "flex items-center gap-2",
+                    input {
+                        r#type: "checkbox",
+                        checked: options().recursive,
+                        onchange: move |e| options.write().recursive = e.checked(),
+                    }
+                    "Включая подпапки"
+                }
+            }
+
+            if let Some(dir) = folder() {
+                p { class: "text-sm text-gray-400", "Папка: {dir.display()}" }
+                button {
+                    class: "px-4 py-2 rounded bg-blue-700 hover:bg-blue-600 disabled:opacity-50",
+                    disabled: running() || selected.is_none(),
+                    onclick: start,
+                    if running() { "Подпись..." } else { "Подписать все файлы" }
+                }
+            }
+
+            if total > 0 {
+                div { class: "w-full h-2 bg-gray-700 rounded",
+                    div { class: "h-2 bg-blue-500 rounded", style: "width: {percent}%" }
+                }
+                p { class: "text-sm text-gray-400", "{done} из {total}" }
+                ul { class: "text-sm space-y-1 max-h-64 overflow-y-auto",
+                    for (path, state) in files().iter().zip(states().iter()) {
+                        li {
+                            {
+                                let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+                                match state {
+                                    FileState::Pending => rsx! { span { class: "text-gray-400", "• {name}" } },
+                                    FileState::Signing => rsx! { span { class: "text-yellow-200", "⏳ {name}" } },
+                                    FileState::Signed(_) => rsx! { span { class: "text-green-200", "✅ {name}" } },
+                                    FileState::Skipped => rsx! { span { class: "text-gray-300", "⏭ {name} — подпись уже есть" } },
+                                    FileState::Failed(error) => rsx! { span { class: "text-red-200", "❌ {name}: {error}" } },
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+
+            if let (Some(request), Some(dir)) = (pin_request(), folder()) {
+                {
+                    let cert = request.cert.clone();
+                    let opts = options();
+                    rsx! {
+                        PinDialog {
+                            request,
+                            on_submit: move |(pin, remember): (String, bool)| {
+                                pin_request.set(None);
+                                start_batch(cert.clone(), dir.clone(), opts, Some(pin), remember, state);
+                            },
+                            on_cancel: move |_| pin_request.set(None),
+                        }
+                    }
+                }
+            }
+
+            match summary() {
+                Some(Ok(text)) => rsx! {
+                    div { class: "rounded-xl border border-blue-700/50 bg-blue-900/20 text-blue-100 px-4 py-3 text-sm", "{text}" }
+                },
+                Some(Err(error)) => rsx! {
+                    div { class: "rounded-xl border border-red-700/50 bg-red-900/20 text-red-100 px-4 py-3 text-sm", "Ошибка: {error}" }
+                },
+                None => rsx! {},
+            }
+        }
+    }
+}
+
+/// Консоль разработчика: последние HTTP-обмены с True API
+#[component]
+fn HttpInspector() -> Element {
+    let mut open = use_signal(|| false);
+    let mut exchanges = use_signal(Vec::<inspector::Exchange>::new);
+    let mut expanded = use_signal(|| Option::<u64>::None);
+    let mut copied = use_signal(|| Option::<u64>::None);
+
+    // Журнал пополняется из фоновых запросов — перечитываем его, пока панель открыта
+    use_future(move || async move {
+        let mut seen = u64::MAX;
+        loop {
+            if open() && inspector::version() != seen {
+                seen = inspector::version();
+                exchanges.set(inspector::snapshot());
+            }
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        }
+    });
+
+    let copy_curl = move |exchange: inspector::Exchange| {
+        let curl = serde_json::to_string(&exchange.to_curl()).unwrap_or_default();
+        document::eval(&format!("navigator.clipboard.writeText({})", curl));
+        copied.set(Some(exchange.id));
+    };
+
+    rsx! {
+        div { class: "mt-8 p-4 bg-gray-800/60 border border-gray-700 rounded-xl space-y-3",
+            div { class: "flex items-center justify-between",
+                h2 { class: "text-lg font-semibold", "Консоль разработчика" }
+                div { class: "flex gap-2",
+                    if open() {
+                        button {
+                            class: "px-3 py-1 rounded bg-gray-700 hover:bg-gray-600 text-sm",
+                            onclick: move |_| {
+                                inspector::clear();
+                                exchanges.set(Vec::new());
+                            },
+                            "Очистить"
+                        }
+                    }
+                    button {
+                        class: "px-3 py-1 rounded bg-gray-700 hover:bg-gray-600 text-sm",
+                        onclick: move |_| {
+                            if !open() {
+                                exchanges.set(inspector::snapshot());
+                            }
+                            open.toggle();
+                        },
+                        if open() { "Скрыть" } else { "Показать запросы" }
+                    }
+                }
+            }
+
+            if open() {
+                p { class: "text-xs text-gray-400",
+                    "Последние запросы к True API. Токены, подписи и пароли скрыты (***)."
+                }
+                if exchanges().is_empty() {
+                    p { class: "text-sm text-gray-500", "Запросов пока не было" }
+                }
+                for exchange in exchanges() {
+                    div { key: "{exchange.id}", class: "border border-gray-700 rounded text-sm",
+                        div {
+                            class: "flex flex-wrap items-center gap-3 p-2 cursor-pointer hover:bg-gray-700/50",
+                            onclick: {
+                                let id = exchange.id;
+                                move |_| expanded.set(if expanded() == Some(id) { None } else { Some(id) })
+                            },
+                            span { class: "text-gray-500 font-mono", {exchange.started.format("%H:%M:%S").to_string()} }
+                            span { class: "font-mono font-semibold", "{exchange.method}" }
+                            span {
+                                class: match exchange.status {
+                                    Some(s) if s < 400 => "font-mono text-green-400",
+                                    Some(_) => "font-mono text-red-400",
+                                    None => "font-mono text-yellow-400",
+                                },
+                                {exchange.status.map(|s| s.to_string()).unwrap_or_else(|| "—".to_string())}
+                            }
+                            span { class: "text-gray-400", {format!("{} мс", exchange.duration.as_millis())} }
+                            span { class: "font-mono break-all flex-1", "{exchange.url}" }
+                        }
+                        if expanded() == Some(exchange.id) {
+                            div { class: "p-3 border-t border-gray-700 space-y-2 text-xs",
+                                if let Some(error) = exchange.error.clone() {
+                                    p { class: "text-red-400", "Ошибка: {error}" }
+                                }
+                                InspectorPart {
+                                    title: "Запрос",
+                                    headers: exchange.request_headers.clone(),
+                                    body: exchange.request_body.clone(),
+                                }
+                                InspectorPart {
+                                    title: "Ответ",
+                                    headers: exchange.response_headers.clone(),
+                                    body: exchange.response_body.clone(),
+                                }
+                                button {
+                                    class: "px-3 py-1 rounded bg-gray-700 hover:bg-gray-600",
+                                    onclick: {
+                                        let exchange = exchange.clone();
+                                        move |_| copy_curl(exchange.clone())
+                                    },
+                                    if copied() == Some(exchange.id) { "Скопировано ✓" } else { "Копировать как curl" }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Заголовки и тело одной стороны обмена
+#[component]
+fn InspectorPart(title: String, headers: Vec<(String, String)>, body: Option<String>) -> Element {
+    rsx! {
+        div { class: "space-y-1",
+            p { class: "font-semibold text-gray-300", "{title}" }
+            div { class: "font-mono text-gray-400",
+                for (name, value) in headers {
+                    div { class: "break-all", "{name}: {value}" }
+                }
+            }
+            if let Some(body) = body.filter(|b| !b.is_empty()) {
+                pre { class: "p-2 bg-gray-900 rounded whitespace-pre-wrap break-all max-h-64 overflow-auto", "{body}" }
+            }
+        }
+    }
+}
+
+/// Параметры выгрузки нарушений и сохранённые профили
+#[component]
+fn ExportParamsSection() -> Element {
+    let mut params = use_signal(|| settings::load().export_params);
+    let mut profiles = use_signal(|| settings::load().export_profiles);
+    let mut selected_profile = use_signal(String::new);
+    let mut profile_name = use_signal(String::new);
+    // Категории и виды редактируются текстом: «1-20» или «1, 3, 5»
+    let mut categories_text = use_signal(|| dispenser::format_codes(&params().violation_categories));
+    let mut kinds_text = use_signal(|| dispenser::format_codes(&params().violation_kinds));
+    let mut status = use_signal(|| Option::<String>::None);
+
+    // Собирает параметры из формы; коды разбираются из текстовых полей
+    let collect = move || -> Result<dispenser::ExportParams, String> {
+        let mut current = params();
+        current.violation_categories = dispenser::parse_codes(&categories_text())?;
+        current.violation_kinds = dispenser::parse_codes(&kinds_text())?;
+        current.validate()?;
+        Ok(current)
+    };
 
-            tokio::time::sleep(tokio::time::Duration::from_secs(30)).await;
+    let mut show = move |current: dispenser::ExportParams| {
+        categories_text.set(dispenser::format_codes(&current.violation_categories));
+        kinds_text.set(dispenser::format_codes(&current.violation_kinds));
+        params.set(current);
+    };
+
+    let save = move |_: MouseEvent| {
+        let message = match collect() {
+            Ok(current) => {
+                let mut all = settings::load();
+                all.export_params = current.clone();
+                match settings::save(&all) {
+                    Ok(()) => {
+                        params.set(current);
+                        "Параметры выгрузки сохранены".to_string()
+                    }
+                    Err(e) => format!("Ошибка: {}", e),
+                }
+            }
+            Err(e) => format!("Ошибка: {}", e),
+        };
+        status.set(Some(message));
+    };
+
+    let save_profile = move |_: MouseEvent| {
+        let name = profile_name().trim().to_string();
+        if name.is_empty() {
+            status.set(Some("Ошибка: укажите название профиля".to_string()));
+            return;
         }
-    });
+        let current = match collect() {
+            Ok(current) => current,
+            Err(e) => {
+                status.set(Some(format!("Ошибка: {}", e)));
+                return;
+            }
+        };
+        let mut all = settings::load();
+        all.export_profiles.retain(|p| p.name != name);
+        all.export_profiles.push(dispenser::ExportProfile { name: name.clone(), params: current });
+        all.export_profiles.sort_by(|a, b| a.name.cmp(&b.name));
+        let message = match settings::save(&all) {
+            Ok(()) => {
+                profiles.set(all.export_profiles);
+                selected_profile.set(name.clone());
+                format!("Профиль «{}» сохранён", name)
+            }
+            Err(e) => format!("Ошибка: {}", e),
+        };
+        status.set(Some(message));
+    };
+
+    let load_profile = move |_: MouseEvent| {
+        let name = selected_profile();
+        if let Some(profile) = profiles().into_iter().find(|p| p.name == name) {
+            show(profile.params);
+            profile_name.set(profile.name);
+            status.set(Some(format!("Загружен профиль «{}» — сохраните параметры, чтобы применить их", name)));
+        }
+    };
+
+    let delete_profile = move |_: MouseEvent| {
+        let name = selected_profile();
+        if name.is_empty() {
+            return;
+        }
+        let mut all = settings::load();
+        all.export_profiles.retain(|p| p.name != name);
+        let message = match settings::save(&all) {
+            Ok(()) => {
+                profiles.set(all.export_profiles);
+                selected_profile.set(String::new());
+                format!("Профиль «{}» удалён", name)
+            }
+            Err(e) => format!("Ошибка: {}", e),
+        };
+        status.set(Some(message));
+    };
 
     rsx! {
-        document::Link { rel: "icon", href: FAVICON }
-        document::Link { rel: "stylesheet", href: MAIN_CSS }
-        document::Link { rel: "stylesheet", href: TAILWIND_CSS }
+        div { id: "export", class: "mt-8 p-4 bg-gray-800/60 border border-gray-700 rounded-xl space-y-4",
+            h2 { class: "text-lg font-semibold", "Параметры выгрузки" }
 
-        div { class: "min-h-screen bg-gray-900 text-white p-4",
-            h1 { class: "text-2xl font-bold mb-6 text-center",
-                "Электронные подписи в системе"
+            div { class: "flex flex-wrap items-center gap-3",
+                "Профиль:"
+                select {
+                    class: "p-2 rounded bg-gray-800 border border-gray-700",
+                    value: "{selected_profile}",
+                    onchange: move |e| selected_profile.set(e.value()),
+                    option { value: "", "— не выбран —" }
+                    for profile in profiles() {
+                        option { value: "{profile.name}", "{profile.name}" }
+                    }
+                }
+                button {
+                    class: "px-3 py-2 rounded bg-gray-700 hover:bg-gray-600 disabled:opacity-50",
+                    disabled: selected_profile().is_empty(),
+                    onclick: load_profile,
+                    "Загрузить"
+                }
+                button {
+                    class: "px-3 py-2 rounded bg-gray-700 hover:bg-red-700 disabled:opacity-50",
+                    disabled: selected_profile().is_empty(),
+                    onclick: delete_profile,
+                    "Удалить"
+                }
+                input {
+                    class: "flex-1 min-w-40 p-2 rounded bg-gray-800 border border-gray-700",
+                    placeholder: "Название нового профиля",
+                    value: "{profile_name}",
+                    oninput: move |e| profile_name.set(e.value()),
+                }
+                button {
+                    class: "px-3 py-2 rounded bg-gray-700 hover:bg-gray-600",
+                    onclick: save_profile,
+                    "Сохранить как профиль"
+                }
             }
 
-            if tasks().len() > 0 {
-                div { class: "mb-6 p-4 bg-blue-900/30 border border-blue-700 rounded-xl",
-                    h2 { class: "text-lg font-semibold mb-3 flex items-center gap-2",
-                        svg {
-                            class: "w-5 h-5",
-                            xmlns: "http://www.w3.org/2000/svg",
-                            view_box: "0 0 24 24",
-                            fill: "none",
-                            stroke: "currentColor",
-                            stroke_width: "2",
-                            d: "M13 16h-1v-4h-1m1-4h.01M21 12a9 9 0 11-18 0 9 9 0 0118 0z",
+            div { class: "flex flex-wrap items-center gap-3",
+                "Период:"
+                select {
+                    class: "p-2 rounded bg-gray-800 border border-gray-700",
+                    value: "{params().period.slug()}",
+                    onchange: move |e| {
+                        if let Some(period) = dispenser::PeriodPreset::from_slug(&e.value()) {
+                            params.write().period = period;
                         }
-                        "Статус выгрузок"
+                    },
+                    for period in dispenser::PeriodPreset::ALL {
+                        option { value: "{period.slug()}", "{period.label()}" }
                     }
-                    ul { class: "space-y-2 text-sm",
-                        for task in tasks().iter() {
-                            li { class: "flex items-center gap-3",
-                                if task.is_completed {
-                                    span { class: "text-green-400", "✅" }
-                                    span { class: "font-medium text-green-100",
-                                        "Готово: {task.display_name()}"
+                }
+                if params().period == dispenser::PeriodPreset::Custom {
+                    input {
+                        class: "p-2 rounded bg-gray-800 border border-gray-700",
+                        r#type: "date",
+                        value: "{params().custom_start}",
+                        oninput: move |e| params.write().custom_start = e.value(),
+                    }
+                    "—"
+                    input {
+                        class: "p-2 rounded bg-gray-800 border border-gray-700",
+                        r#type: "date",
+                        value: "{params().custom_end}",
+                        oninput: move |e| params.write().custom_end = e.value(),
+                    }
+                }
+            }
+
+            div { class: "flex flex-wrap items-center gap-3",
+                "Формат:"
+                select {
+                    class: "p-2 rounded bg-gray-800 border border-gray-700",
+                    value: "{params().format.api_value()}",
+                    onchange: move |e| {
+                        if let Some(format) = dispenser::ExportFormat::from_api_value(&e.value()) {
+                            params.write().format = format;
+                        }
+                    },
+                    for format in dispenser::ExportFormat::ALL {
+                        option { value: "{format.api_value()}", "{format.api_value()}" }
+                    }
+                }
+                "Периодичность:"
+                select {
+                    class: "p-2 rounded bg-gray-800 border border-gray-700",
+                    value: "{params().periodicity.api_value()}",
+                    onchange: move |e| {
+                        if let Some(periodicity) = dispenser::Periodicity::from_api_value(&e.value()) {
+                            params.write().periodicity = periodicity;
+                        }
+                    },
+                    for periodicity in dispenser::Periodicity::ALL {
+                        option { value: "{periodicity.api_value()}", "{periodicity.label()}" }
+                    }
+                }
+            }
+
+            div { class: "space-y-2",
+                "Товарные группы ({params().product_groups.len()}):"
+                div { class: "grid grid-cols-2 md:grid-cols-3 gap-1 max-h-64 overflow-auto p-2 bg-gray-900 rounded text-sm",
+                    for (code, name) in dispenser::PRODUCT_GROUPS.iter().copied() {
+                        label { class: "flex items-center gap-2",
+                            input {
+                                r#type: "checkbox",
+                                checked: params().product_groups.contains(&code),
+                                onchange: move |e| {
+                                    let mut current = params.write();
+                                    current.product_groups.retain(|c| *c != code);
+                                    if e.checked() {
+                                        current.product_groups.push(code);
+                                        current.product_groups.sort_unstable();
                                     }
-                                } else if task.error.is_some() {
-                                    span { class: "text-red-400", "❌" }
-                                    {
-                                        let error_msg = task.error.as_deref().unwrap_or("-");
-                                        rsx! {
-                                            span { class: "text-red-100", "Ошибка {task.display_name()}: {error_msg}" }
-                                        }
+                                },
+                            }
+                            "{code} — {name}"
+                        }
+                    }
+                }
+            }
+
+            label { class: "block",
+                "Категории нарушений (например, 1-20 или 1, 3, 5):"
+                input {
+                    class: "w-full mt-1 p-2 rounded bg-gray-800 border border-gray-700",
+                    value: "{categories_text}",
+                    oninput: move |e| categories_text.set(e.value()),
+                }
+            }
+            label { class: "block",
+                "Виды нарушений:"
+                input {
+                    class: "w-full mt-1 p-2 rounded bg-gray-800 border border-gray-700",
+                    value: "{kinds_text}",
+                    oninput: move |e| kinds_text.set(e.value()),
+                }
+            }
+
+            p { class: "text-sm text-gray-400", "{params().summary()}" }
+
+            div { class: "flex items-center gap-3",
+                button {
+                    class: "px-4 py-2 rounded bg-blue-700 hover:bg-blue-600",
+                    onclick: save,
+                    "Сохранить параметры"
+                }
+                if let Some(message) = status() {
+                    span { class: "text-sm text-gray-300", "{message}" }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn SettingsSection() -> Element {
+    let mut draft = use_signal(settings::load);
+    let mut status = use_signal(|| Option::<String>::None);
+    // Новый пароль прокси; сохранённый хранится только в зашифрованном виде
+    let mut proxy_password = use_signal(String::new);
+
+    let save = move |_: MouseEvent| {
+        let password = std::mem::take(&mut *proxy_password.write());
+        if !password.is_empty() {
+            if let Err(e) = draft.write().proxy.set_password(&password) {
+                status.set(Some(format!("Ошибка: {}", e)));
+                return;
+            }
+        }
+        if let Err(e) = draft().validate() {
+            status.set(Some(format!("Ошибка: {}", e)));
+            return;
+        }
+        let message = match settings::save(&draft()) {
+            Ok(()) => "Настройки сохранены".to_string(),
+            Err(e) => format!("Ошибка: {}", e),
+        };
+        status.set(Some(message));
+    };
+
+    let pick_download_dir = move |_: MouseEvent| {
+        spawn(async move {
+            if let Some(folder) = rfd::AsyncFileDialog::new().pick_folder().await {
+                draft.write().download_dir = folder.path().display().to_string();
+            }
+        });
+    };
+
+    let add_ca = move |_: MouseEvent| {
+        spawn(async move {
+            if let Some(file) = rfd::AsyncFileDialog::new()
+                .add_filter("Сертификаты", &["pem", "crt", "cer", "der"])
+                .pick_file()
+                .await
+            {
+                draft
+                    .write()
+                    .extra_ca_paths
+                    .push(file.path().display().to_string());
+            }
+        });
+    };
+
+    rsx! {
+        div {
+            id: "settings",
+            class: "mt-8 p-4 bg-gray-800/60 border border-gray-700 rounded-xl space-y-3 text-sm",
+            h2 { class: "text-lg font-semibold", "Настройки" }
+
+            label { class: "block",
+                "Служба штампов времени (TSA, RFC 3161):"
+                input {
+                    class: "w-full mt-1 p-2 rounded bg-gray-800 border border-gray-700",
+                    placeholder: "http://tsa.example.ru/tsp/tsp.srf",
+                    value: draft().tsa_url,
+                    oninput: move |e| draft.write().tsa_url = e.value(),
+                }
+                p { class: "text-xs text-gray-400 mt-1",
+                    "Запрос отправляется с хэшем SHA-256; службы, принимающие только ГОСТ Р 34.11-2012, не подойдут"
+                }
+            }
+            label { class: "flex items-center gap-2",
+                input {
+                    r#type: "checkbox",
+                    checked: draft().cades_t,
+                    onchange: move |e| draft.write().cades_t = e.checked(),
+                }
+                "Добавлять штамп времени к подписям файлов (CAdES-T)"
+            }
+
+            label { class: "block",
+                "Контур True API:"
+                select {
+                    class: "w-full mt-1 p-2 rounded bg-gray-800 border border-gray-700",
+                    value: draft().environment.slug(),
+                    onchange: move |e| {
+                        if let Some(env) = settings::Environment::from_slug(&e.value()) {
+                            draft.write().environment = env;
+                        }
+                    },
+                    for env in settings::Environment::ALL {
+                        option { value: env.slug(), {env.label()} }
+                    }
+                }
+            }
+            if draft().environment == settings::Environment::Custom {
+                label { class: "block",
+                    "Адрес API:"
+                    input {
+                        class: "w-full mt-1 p-2 rounded bg-gray-800 border border-gray-700",
+                        placeholder: "http://127.0.0.1:8080/api/v3/true-api",
+                        value: draft().custom_base_url,
+                        oninput: move |e| draft.write().custom_base_url = e.value(),
+                    }
+                }
+            }
+            div { class: "space-y-2",
+                p { "Подключения к СУЗ (ИНН, omsConnection, omsId):" }
+                for (idx, oms) in draft().oms_connections.into_iter().enumerate() {
+                    div { class: "flex gap-2",
+                        input {
+                            class: "w-40 p-2 rounded bg-gray-800 border border-gray-700",
+                            placeholder: "ИНН",
+                            value: oms.inn,
+                            oninput: move |e| draft.write().oms_connections[idx].inn = e.value(),
+                        }
+                        input {
+                            class: "flex-1 p-2 rounded bg-gray-800 border border-gray-700",
+                            placeholder: "omsConnection",
+                            value: oms.oms_connection,
+                            oninput: move |e| draft.write().oms_connections[idx].oms_connection = e.value(),
+                        }
+                        input {
+                            class: "flex-1 p-2 rounded bg-gray-800 border border-gray-700",
+                            placeholder: "omsId",
+                            value: oms.oms_id,
+                            oninput: move |e| draft.write().oms_connections[idx].oms_id = e.value(),
+                        }
+                        button {
+                            class: "px-3 rounded bg-gray-700 hover:bg-red-700",
+                            onclick: move |_| {
+                                draft.write().oms_connections.remove(idx);
+                            },
+                            "✕"
+                        }
+                    }
+                }
+                button {
+                    class: "px-3 py-1 rounded bg-gray-700 hover:bg-gray-600",
+                    onclick: move |_| draft.write().oms_connections.push(settings::OmsConnection::default()),
+                    "Добавить подключение СУЗ"
+                }
+            }
+
+            label { class: "block",
+                "Допустимое расхождение часов с сервером, секунд (0 — не проверять):"
+                input {
+                    class: "w-32 mt-1 ml-2 p-2 rounded bg-gray-800 border border-gray-700",
+                    r#type: "number",
+                    min: "0",
+                    value: "{draft().clock_skew_threshold_secs}",
+                    oninput: move |e| {
+                        if let Ok(secs) = e.value().parse() {
+                            draft.write().clock_skew_threshold_secs = secs;
+                        }
+                    },
+                }
+            }
+
+            div { class: "flex flex-wrap items-center gap-3",
+                "Повторы запросов к API:"
+                input {
+                    class: "w-20 p-2 rounded bg-gray-800 border border-gray-700",
+                    r#type: "number",
+                    min: "0",
+                    title: "Число повторов",
+                    value: "{draft().retry.max_retries}",
+                    oninput: move |e| {
+                        if let Ok(n) = e.value().parse() {
+                            draft.write().retry.max_retries = n;
+                        }
+                    },
+                }
+                "первая задержка, мс:"
+                input {
+                    class: "w-24 p-2 rounded bg-gray-800 border border-gray-700",
+                    r#type: "number",
+                    min: "0",
+                    value: "{draft().retry.base_delay_ms}",
+                    oninput: move |e| {
+                        if let Ok(ms) = e.value().parse() {
+                            draft.write().retry.base_delay_ms = ms;
+                        }
+                    },
+                }
+                "не более, мс:"
+                input {
+                    class: "w-24 p-2 rounded bg-gray-800 border border-gray-700",
+                    r#type: "number",
+                    min: "0",
+                    value: "{draft().retry.max_delay_ms}",
+                    oninput: move |e| {
+                        if let Ok(ms) = e.value().parse() {
+                            draft.write().retry.max_delay_ms = ms;
+                        }
+                    },
+                }
+                label { class: "flex items-center gap-2",
+                    input {
+                        r#type: "checkbox",
+                        checked: draft().retry.jitter,
+                        onchange: move |e| draft.write().retry.jitter = e.checked(),
+                    }
+                    "случайный разброс"
+                }
+            }
+
+            div { class: "space-y-2",
+                "Папка для результатов выгрузки:"
+                div { class: "flex flex-wrap items-center gap-3",
+                    input {
+                        class: "flex-1 p-2 rounded bg-gray-800 border border-gray-700",
+                        placeholder: "по умолчанию — exports в папке приложения",
+                        value: "{draft().download_dir}",
+                        oninput: move |e| draft.write().download_dir = e.value(),
+                    }
+                    button {
+                        class: "px-3 py-2 rounded bg-gray-700 hover:bg-gray-600",
+                        onclick: pick_download_dir,
+                        "Выбрать..."
+                    }
+                }
+                label { class: "flex items-center gap-2",
+                    input {
+                        r#type: "checkbox",
+                        checked: draft().unzip_results,
+                        onchange: move |e| draft.write().unzip_results = e.checked(),
+                    }
+                    "Распаковывать ZIP-архивы с результатами"
+                }
+                label { class: "flex items-center gap-2",
+                    input {
+                        r#type: "checkbox",
+                        checked: draft().auto_download,
+                        onchange: move |e| draft.write().auto_download = e.checked(),
+                    }
+                    "Скачивать готовые результаты созданных здесь задач автоматически"
+                }
+                div { class: "flex flex-wrap items-center gap-3",
+                    "Предупреждать о нескачанном результате за"
+                    input {
+                        class: "w-20 p-2 rounded bg-gray-800 border border-gray-700",
+                        r#type: "number",
+                        min: "0",
+                        value: "{draft().expiry_warning_days}",
+                        oninput: move |e| {
+                            if let Ok(days) = e.value().parse() {
+                                draft.write().expiry_warning_days = days;
+                            }
+                        },
+                    }
+                    "дн. до удаления с сервера"
+                }
+                div { class: "flex flex-wrap items-center gap-3",
+                    "Хранить задачи в реестре"
+                    input {
+                        class: "w-20 p-2 rounded bg-gray-800 border border-gray-700",
+                        r#type: "number",
+                        min: "0",
+                        value: "{draft().task_retention_days}",
+                        oninput: move |e| {
+                            if let Ok(days) = e.value().parse() {
+                                draft.write().task_retention_days = days;
+                            }
+                        },
+                    }
+                    "дн. (0 — бессрочно)"
+                }
+            }
+
+            div { class: "space-y-2",
+                "Лимиты запросов к API (0 — без ограничения):"
+                for endpoint in ratelimit::Endpoint::ALL {
+                    div { class: "flex items-center gap-3 text-sm",
+                        span { class: "w-40", "{endpoint.label()}" }
+                        input {
+                            class: "w-20 p-2 rounded bg-gray-800 border border-gray-700",
+                            r#type: "number",
+                            min: "0",
+                            value: "{draft().rate_limits.configured(endpoint).requests}",
+                            oninput: move |e| {
+                                if let Ok(n) = e.value().parse() {
+                                    if let Some(limit) = draft.write().rate_limits.get_mut(endpoint) {
+                                        limit.requests = n;
                                     }
-                                } else {
-                                    span { class: "text-yellow-400", "⏳" }
-                                    span { class: "text-yellow-100",
-                                        "В обработке: {task.display_name()}"
+                                }
+                            },
+                        }
+                        "запросов за"
+                        input {
+                            class: "w-20 p-2 rounded bg-gray-800 border border-gray-700",
+                            r#type: "number",
+                            min: "0",
+                            value: "{draft().rate_limits.configured(endpoint).per_secs}",
+                            oninput: move |e| {
+                                if let Ok(n) = e.value().parse() {
+                                    if let Some(limit) = draft.write().rate_limits.get_mut(endpoint) {
+                                        limit.per_secs = n;
                                     }
                                 }
-                            }
+                            },
                         }
+                        "сек"
                     }
                 }
             }
 
-            if loading_status() && tasks().is_empty() {
-                div { class: "mb-6 p-4 bg-gray-800 border border-gray-600 rounded-xl text-center",
-                    "Проверка статуса выгрузок..."
+            label { class: "block",
+                "Прокси-сервер:"
+                select {
+                    class: "w-full mt-1 p-2 rounded bg-gray-800 border border-gray-700",
+                    value: draft().proxy.mode.slug(),
+                    onchange: move |e| {
+                        if let Some(mode) = settings::ProxyMode::from_slug(&e.value()) {
+                            draft.write().proxy.mode = mode;
+                        }
+                    },
+                    for mode in settings::ProxyMode::ALL {
+                        option { value: mode.slug(), {mode.label()} }
+                    }
+                }
+            }
+            if draft().proxy.mode == settings::ProxyMode::Manual {
+                div { class: "space-y-2 pl-4 border-l border-gray-700",
+                    input {
+                        class: "w-full p-2 rounded bg-gray-800 border border-gray-700",
+                        placeholder: "http://proxy.corp.local:3128 или socks5://10.0.0.1:1080",
+                        value: draft().proxy.url,
+                        oninput: move |e| draft.write().proxy.url = e.value(),
+                    }
+                    div { class: "flex gap-2",
+                        input {
+                            class: "flex-1 p-2 rounded bg-gray-800 border border-gray-700",
+                            placeholder: "Пользователь",
+                            value: draft().proxy.username,
+                            oninput: move |e| draft.write().proxy.username = e.value(),
+                        }
+                        input {
+                            class: "flex-1 p-2 rounded bg-gray-800 border border-gray-700",
+                            r#type: "password",
+                            placeholder: if draft().proxy.password_protected.is_empty() { "Пароль" } else { "Пароль сохранён" },
+                            value: proxy_password(),
+                            oninput: move |e| proxy_password.set(e.value()),
+                        }
+                    }
+                    input {
+                        class: "w-full p-2 rounded bg-gray-800 border border-gray-700",
+                        placeholder: "Без прокси: localhost,127.0.0.1,.corp.local",
+                        value: draft().proxy.no_proxy,
+                        oninput: move |e| draft.write().proxy.no_proxy = e.value(),
+                    }
                 }
             }
 
-            match certificates() {
-                Some(certs) => rsx! {
-                    CertificateSection { certificates: certs.clone() }
+            div { class: "space-y-2",
+                p { "Дополнительные корневые сертификаты (TLS-инспекция):" }
+                for (idx, path) in draft().extra_ca_paths.into_iter().enumerate() {
+                    div { class: "flex items-center gap-2",
+                        span { class: "flex-1 truncate text-gray-300", "{path}" }
+                        button {
+                            class: "px-3 rounded bg-gray-700 hover:bg-red-700",
+                            onclick: move |_| {
+                                draft.write().extra_ca_paths.remove(idx);
+                            },
+                            "✕"
+                        }
+                    }
+                }
+                button {
+                    class: "px-3 py-1 rounded bg-gray-700 hover:bg-gray-600",
+                    onclick: add_ca,
+                    "Добавить сертификат"
+                }
+            }
+
+            if settings::environment_overridden() {
+                p { class: "text-yellow-300",
+                    "Контур задан переменными окружения {settings::ENV_VAR} / {settings::BASE_URL_VAR}: сейчас используется {settings::api_base_url()}"
+                }
+            }
+
+            button {
+                class: "px-4 py-2 rounded bg-blue-700 hover:bg-blue-600",
+                onclick: save,
+                "Сохранить"
+            }
+            if let Some(msg) = status() {
+                p { class: "text-gray-300", "{msg}" }
+            }
+        }
+    }
+}
+
+#[component]
+fn VerifySection() -> Element {
+    let mut signature_path = use_signal(|| Option::<std::path::PathBuf>::None);
+    let mut original_path = use_signal(|| Option::<std::path::PathBuf>::None);
+    let mut report = use_signal(|| Option::<Result<VerificationReport, VerifyError>>::None);
+    let mut verifying = use_signal(|| false);
+
+    let pick_signature = move |_: MouseEvent| {
+        spawn(async move {
+            if let Some(file) = rfd::AsyncFileDialog::new()
+                .add_filter("Подписи", &["sig", "p7s", "p7m", "sgn"])
+                .add_filter("Все файлы", &["*"])
+                .pick_file()
+                .await
+            {
+                signature_path.set(Some(file.path().to_path_buf()));
+                report.set(None);
+            }
+        });
+    };
+
+    let pick_original = move |_: MouseEvent| {
+        spawn(async move {
+            if let Some(file) = rfd::AsyncFileDialog::new().pick_file().await {
+                original_path.set(Some(file.path().to_path_buf()));
+                report.set(None);
+            }
+        });
+    };
+
+    let run_verify = move |_: MouseEvent| {
+        let Some(sig) = signature_path() else {
+            return;
+        };
+        verifying.set(true);
+        spawn(async move {
+            let result = verify::verify_signature(sig, original_path()).await;
+            report.set(Some(result));
+            verifying.set(false);
+        });
+    };
+
+    let file_label = |path: Option<std::path::PathBuf>| {
+        path.map(|p| p.display().to_string())
+            .unwrap_or_else(|| "не выбран".to_string())
+    };
+
+    rsx! {
+        div { class: "mt-8 p-4 bg-gray-800/60 border border-gray-700 rounded-xl space-y-3",
+            h2 { class: "text-lg font-semibold", "Проверка подписи" }
+            div { class: "flex flex-wrap gap-3",
+                button {
+                    class: "px-4 py-2 rounded bg-gray-700 hover:bg-gray-600",
+                    onclick: pick_signature,
+                    "Выбрать подпись..."
+                }
+                button {
+                    class: "px-4 py-2 rounded bg-gray-700 hover:bg-gray-600",
+                    onclick: pick_original,
+                    "Исходный файл (для отсоединённой)..."
+                }
+                button {
+                    class: "px-4 py-2 rounded bg-blue-700 hover:bg-blue-600 disabled:opacity-50",
+                    disabled: signature_path().is_none() || verifying(),
+                    onclick: run_verify,
+                    if verifying() { "Проверка..." } else { "Проверить" }
+                }
+            }
+            p { class: "text-sm text-gray-400", "Подпись: {file_label(signature_path())}" }
+            p { class: "text-sm text-gray-400", "Исходный файл: {file_label(original_path())}" }
+
+            match report() {
+                Some(Ok(report)) => rsx! {
+                    VerificationResult { report }
                 },
-                None => rsx! {
-                    div { class: "text-center py-8", "Загрузка сертификатов..." }
+                Some(Err(error)) => rsx! {
+                    div { class: "rounded-xl border border-red-700/50 bg-red-900/20 text-red-100 px-4 py-3 text-sm",
+                        "Ошибка: {error}"
+                    }
                 },
+                None => rsx! {},
+            }
+        }
+    }
+}
+
+#[component]
+fn VerificationResult(report: VerificationReport) -> Element {
+    let (border, verdict) = if report.valid {
+        ("border-green-700/50 bg-green-900/20 text-green-100", "✅ Подпись действительна")
+    } else {
+        ("border-red-700/50 bg-red-900/20 text-red-100", "❌ Подпись недействительна")
+    };
+    let kind = if report.info.detached { "отсоединённая" } else { "присоединённая" };
+
+    rsx! {
+        div { class: "rounded-xl border px-4 py-3 text-sm space-y-2 {border}",
+            p { class: "font-semibold", "{verdict} ({kind})" }
+            if !report.message.is_empty() {
+                p { class: "text-gray-300", "{report.message}" }
+            }
+            for signer in report.info.signers.iter() {
+                div { class: "border-t border-gray-700 pt-2",
+                    match signer.certificate.as_ref() {
+                        Some(cert) => {
+                            let subject = cert.subject_name.clone();
+                            let cn = extract_attr(&subject, "CN=").unwrap_or(subject.clone());
+                            rsx! {
+                                p { class: "font-medium", "{cn}" }
+                                if let Some(inn) = extract_attr(&subject, "INN=") {
+                                    p { class: "text-blue-300", "ИНН: {inn}" }
+                                }
+                            }
+                        }
+                        // Издатель — это УЦ, а не подписант: не выдаём его за владельца подписи
+                        None => rsx! {
+                            p { class: "font-medium text-yellow-300", "Сертификат подписанта не найден в подписи" }
+                            p { class: "text-gray-400", "Издатель: {signer.issuer_name}" }
+                        },
+                    }
+                    p { "Серийный номер: {signer.serial_number}" }
+                    p { "Алгоритм хэширования: {signer.digest_algorithm}" }
+                    if let Some(time) = signer.signing_time.as_ref() {
+                        p { "Время подписи: {time}" }
+                    }
+                    if let Some(cert) = signer.certificate.as_ref() {
+                        p { "Сертификат действителен с {cert.valid_from} по {cert.valid_to}" }
+                    }
+                    for stamp in signer.timestamps.iter() {
+                        {
+                            let ok = stamp.imprint_ok != Some(false) && stamp.token_valid != Some(false);
+                            let icon = if ok { "🕒" } else { "❌" };
+                            let tsa = extract_attr(&stamp.tsa_name, "CN=").unwrap_or(stamp.tsa_name.clone());
+                            let imprint = match stamp.imprint_ok {
+                                Some(true) => "отпечаток совпадает",
+                                Some(false) => "отпечаток НЕ совпадает",
+                                None => "алгоритм отпечатка не поддерживается",
+                            };
+                            let token = match stamp.token_valid {
+                                Some(true) => "подпись TSA действительна",
+                                Some(false) => "подпись TSA недействительна",
+                                None => "подпись TSA не проверялась",
+                            };
+                            rsx! {
+                                p { "{icon} Штамп времени: {stamp.time} ({tsa}; {imprint}; {token})" }
+                            }
+                        }
+                    }
+                }
             }
         }
     }
@@ -129,8 +2411,15 @@ fn App() -> Element {
 fn CertificateSection(certificates: Vec<CertificateInfo>) -> Element {
     let mut search_query = use_signal(|| String::new());
     let mut selected_cert = use_signal(|| Option::<CertificateInfo>::None);
-    let mut sign_status = use_signal(|| Option::<String>::None);
-    let mut loading = use_signal(|| false);
+    let mut sign_status = use_signal(|| Option::<Result<String, SignError>>::None);
+    let loading = use_signal(|| false);
+    let mut pin_request = use_signal(|| Option::<PinRequest>::None);
+    let sign_in = SignInState {
+        status: sign_status,
+        loading,
+        pin_request,
+        polling: use_context::<Polling>(),
+    };
 
     let filtered_certs = use_memo(move || {
         if search_query().is_empty() {
@@ -170,19 +2459,7 @@ fn CertificateSection(certificates: Vec<CertificateInfo>) -> Element {
                             }
                             selected_cert.set(Some(cert.clone()));
                             sign_status.set(None);
-                            loading.set(true);
-                            let cert_clone = cert.clone();
-                            spawn(async move {
-                                match sign_file_with_certificate(&cert_clone).await {
-                                    Ok(message) => {
-                                        sign_status.set(Some(message));
-                                    }
-                                    Err(error) => {
-                                        sign_status.set(Some(format!("Ошибка: {}", error)));
-                                    }
-                                }
-                                loading.set(false);
-                            });
+                            start_sign_in(cert.clone(), None, false, sign_in);
                         },
                         div { class: "space-y-1",
                             {
@@ -239,9 +2516,60 @@ fn CertificateSection(certificates: Vec<CertificateInfo>) -> Element {
                 }
             }
 
-            if let Some(msg) = sign_status() {
-                div { class: "rounded-xl border border-blue-700/50 bg-blue-900/20 text-blue-100 px-4 py-3 text-sm shadow-inner",
-                    "{msg}"
+            match sign_status() {
+                Some(Ok(msg)) => rsx! {
+                    div { class: "rounded-xl border border-blue-700/50 bg-blue-900/20 text-blue-100 px-4 py-3 text-sm shadow-inner",
+                        "{msg}"
+                    }
+                },
+                Some(Err(error)) if error.needs_passphrase() => rsx! {
+                    PassphrasePrompt {
+                        message: error.to_string(),
+                        on_submit: move |passphrase: String| {
+                            secure::set_passphrase(&passphrase);
+                            if let Some(cert) = selected_cert() {
+                                sign_status.set(None);
+                                start_sign_in(cert, None, false, sign_in);
+                            }
+                        },
+                    }
+                },
+                Some(Err(error)) => rsx! {
+                    div { class: "rounded-xl border border-red-700/50 bg-red-900/20 text-red-100 px-4 py-3 text-sm shadow-inner flex items-center justify-between gap-4",
+                        span { "Ошибка: {error}" }
+                        if let Some(action) = error.action() {
+                            button {
+                                class: "shrink-0 px-3 py-1 rounded bg-red-800 hover:bg-red-700",
+                                onclick: move |_| match action {
+                                    ErrorAction::OpenSettings => open_settings(),
+                                    ErrorAction::Relogin | ErrorAction::Retry => {
+                                        if let Some(cert) = selected_cert() {
+                                            sign_status.set(None);
+                                            start_sign_in(cert, None, false, sign_in);
+                                        }
+                                    }
+                                },
+                                "{action.label()}"
+                            }
+                        }
+                    }
+                },
+                None => rsx! {},
+            }
+
+            if let Some(request) = pin_request() {
+                {
+                    let cert = request.cert.clone();
+                    rsx! {
+                        PinDialog {
+                            request,
+                            on_submit: move |(pin, remember): (String, bool)| {
+                                pin_request.set(None);
+                                start_sign_in(cert.clone(), Some(pin), remember, sign_in);
+                            },
+                            on_cancel: move |_| pin_request.set(None),
+                        }
+                    }
                 }
             }
 
@@ -0,0 +1,89 @@
+// src/net.rs
+
+use crate::error::ApiError;
+use crate::settings::{self, ProxyMode, Settings};
+use once_cell::sync::Lazy;
+use reqwest::{Certificate, Client, NoProxy, Proxy};
+use std::sync::Mutex;
+use std::time::Duration;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+// --- Общий клиент (пул соединений) и настройки, с которыми он создан ---
+static SHARED: Lazy<Mutex<Option<(String, Client)>>> = Lazy::new(|| Mutex::new(None));
+
+/// Общий HTTP-клиент для всех запросов: True API, СУЗ, TSA.
+/// Пересоздаётся, только если изменились настройки прокси или сертификатов.
+/// В демо-режиме прокси не используется: сервер работает на 127.0.0.1.
+pub fn shared_client() -> Result<Client, ApiError> {
+    let settings = settings::load();
+    let demo = crate::demo::is_active();
+    let fingerprint = serde_json::to_string(&(&settings.proxy, &settings.extra_ca_paths, demo))
+        .unwrap_or_default();
+
+    let mut shared = SHARED.lock().unwrap();
+    if let Some((current, client)) = shared.as_ref() {
+        if *current == fingerprint {
+            return Ok(client.clone());
+        }
+    }
+
+    let client = build_client(&settings, demo)?;
+    *shared = Some((fingerprint, client.clone()));
+    Ok(client)
+}
+
+/// Создаёт HTTP-клиент с таймаутами, настройками прокси и дополнительными
+/// корневыми сертификатами; `direct` — без прокси (демо-режим)
+fn build_client(settings: &Settings, direct: bool) -> Result<Client, ApiError> {
+    let proxy = &settings.proxy;
+    let mut builder = Client::builder()
+        .user_agent("czn-dioxus/1.0")
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT);
+
+    match proxy.mode {
+        _ if direct => builder = builder.no_proxy(),
+        // reqwest сам берёт HTTP(S)_PROXY / NO_PROXY, а в Windows — настройки системы
+        ProxyMode::System => {}
+        ProxyMode::Disabled => builder = builder.no_proxy(),
+        ProxyMode::Manual => {
+            let url = proxy.url.trim();
+            if url.is_empty() {
+                return Err(ApiError::Config("не указан адрес прокси-сервера".to_string()));
+            }
+            let mut manual = Proxy::all(url)
+                .map_err(|e| ApiError::Config(format!("неверный адрес прокси-сервера {}: {}", url, e)))?;
+            if !proxy.username.trim().is_empty() {
+                let password = proxy.password().map_err(ApiError::Config)?;
+                manual = manual.basic_auth(proxy.username.trim(), &password);
+            }
+            manual = manual.no_proxy(NoProxy::from_string(&proxy.no_proxy));
+            builder = builder.proxy(manual);
+        }
+    }
+
+    for path in settings.extra_ca_paths.iter().filter(|p| !p.trim().is_empty()) {
+        for cert in load_certificates(path.trim())? {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    builder
+        .build()
+        .map_err(|e| ApiError::Config(format!("не удалось создать HTTP-клиент: {}", e)))
+}
+
+/// Читает корневые сертификаты из файла: PEM (в том числе цепочку) или DER
+fn load_certificates(path: &str) -> Result<Vec<Certificate>, ApiError> {
+    let bytes = std::fs::read(path)
+        .map_err(|e| ApiError::Config(format!("не удалось прочитать сертификат {}: {}", path, e)))?;
+
+    let parsed = if bytes.starts_with(b"-----BEGIN") {
+        Certificate::from_pem_bundle(&bytes)
+    } else {
+        Certificate::from_der(&bytes).map(|cert| vec![cert])
+    };
+    parsed.map_err(|e| ApiError::Config(format!("неверный сертификат {}: {}", path, e)))
+}
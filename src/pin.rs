@@ -0,0 +1,174 @@
+// src/pin.rs
+
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use zeroize::Zeroizing;
+
+// --- Кэш PIN-кодов на время сеанса (только в памяти) ---
+struct CachedPin {
+    pin: Zeroizing<String>,
+    expires_at: Instant,
+}
+
+static CACHE: Lazy<Mutex<HashMap<String, CachedPin>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn normalize(thumbprint: &str) -> String {
+    thumbprint.replace([':', ' '], "").to_uppercase()
+}
+
+/// Запоминает PIN для сертификата на время, заданное в настройках.
+/// При нулевом таймауте ничего не сохраняет.
+pub fn remember(thumbprint: &str, pin: &str) {
+    let minutes = crate::settings::load().pin_cache_minutes;
+    if minutes == 0 {
+        return;
+    }
+
+    let entry = CachedPin {
+        pin: Zeroizing::new(pin.to_string()),
+        expires_at: Instant::now() + Duration::from_secs(minutes as u64 * 60),
+    };
+    // Старое значение затирается при удалении (Zeroizing)
+    CACHE.lock().unwrap().insert(normalize(thumbprint), entry);
+}
+
+/// Возвращает сохранённый PIN, если срок хранения не истёк
+pub fn cached(thumbprint: &str) -> Option<Zeroizing<String>> {
+    let mut cache = CACHE.lock().unwrap();
+    let now = Instant::now();
+    cache.retain(|_, entry| entry.expires_at > now);
+    cache
+        .get(&normalize(thumbprint))
+        .map(|entry| entry.pin.clone())
+}
+
+/// Забывает PIN сертификата (например, после ошибки ввода)
+pub fn forget(thumbprint: &str) {
+    CACHE.lock().unwrap().remove(&normalize(thumbprint));
+}
+
+/// Забывает все сохранённые PIN-коды
+pub fn forget_all() {
+    CACHE.lock().unwrap().clear();
+}
+
+/// Ошибки, связанные с PIN-кодом носителя
+#[derive(Clone, Debug, PartialEq)]
+pub enum PinFailure {
+    /// Контейнер защищён PIN-кодом, а он не передан
+    Required,
+    /// Введён неверный PIN; число оставшихся попыток, если носитель его сообщает
+    Wrong { attempts_left: Option<u32> },
+    /// PIN заблокирован после исчерпания попыток
+    Locked,
+}
+
+const REQUIRED_TEXT: &str = "Требуется PIN-код контейнера";
+const WRONG_TEXT: &str = "Неверный PIN-код";
+
+impl fmt::Display for PinFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PinFailure::Required => write!(f, "{}", REQUIRED_TEXT),
+            PinFailure::Wrong { attempts_left: Some(n) } => {
+                write!(f, "{} (осталось попыток: {})", WRONG_TEXT, n)
+            }
+            PinFailure::Wrong { attempts_left: None } => write!(f, "{}", WRONG_TEXT),
+            PinFailure::Locked => write!(
+                f,
+                "PIN-код заблокирован: исчерпано число попыток ввода. Обратитесь к администратору носителя"
+            ),
+        }
+    }
+}
+
+/// Ищет первое число после слова-маркера
+fn number_after(text: &str, marker: &str) -> Option<u32> {
+    let start = text.find(marker)? + marker.len();
+    text[start..]
+        .chars()
+        .skip_while(|c| !c.is_ascii_digit())
+        .take_while(|c| c.is_ascii_digit())
+        .collect::<String>()
+        .parse()
+        .ok()
+}
+
+/// Распознаёт ошибки PIN в выводе cryptcp
+pub fn classify(output: &str) -> Option<PinFailure> {
+    let text = output.to_lowercase();
+
+    // SCARD_W_CHV_BLOCKED. Слова «заблокирован»/«blocked» не годятся: так же
+    // cryptcp сообщает о заблокированном сертификате или контейнере
+    if text.contains("0x8010006c") || text.contains("scard_w_chv_blocked") {
+        return Some(PinFailure::Locked);
+    }
+
+    // SCARD_W_WRONG_CHV
+    if text.contains("0x8010006b")
+        || text.contains("scard_w_wrong_chv")
+        || text.contains("неправильный пароль")
+        || text.contains("неверный пин")
+        || text.contains("wrong pin")
+    {
+        let attempts_left = number_after(&text, "осталось").or_else(|| number_after(&text, "remaining"));
+        return Some(PinFailure::Wrong { attempts_left });
+    }
+
+    // SCARD_W_CANCELLED_BY_USER / SCARD_W_CARD_NOT_AUTHENTICATED
+    if text.contains("0x8010006e") || text.contains("0x8010006f") {
+        return Some(PinFailure::Required);
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrong_pin_with_attempts_left() {
+        assert_eq!(
+            classify("Ошибка: 0x8010006b. Неправильный пароль. Осталось попыток: 2"),
+            Some(PinFailure::Wrong { attempts_left: Some(2) })
+        );
+        assert_eq!(
+            classify("Error: SCARD_W_WRONG_CHV (wrong PIN), remaining attempts: 5"),
+            Some(PinFailure::Wrong { attempts_left: Some(5) })
+        );
+        assert_eq!(
+            classify("[ErrorCode: 0x8010006b]"),
+            Some(PinFailure::Wrong { attempts_left: None })
+        );
+    }
+
+    #[test]
+    fn locked_only_by_error_code() {
+        assert_eq!(classify("[ErrorCode: 0x8010006c]"), Some(PinFailure::Locked));
+        assert_eq!(classify("SCARD_W_CHV_BLOCKED"), Some(PinFailure::Locked));
+        // Заблокированный сертификат или контейнер — не блокировка PIN
+        assert_eq!(classify("Сертификат заблокирован"), None);
+        assert_eq!(classify("Container is blocked"), None);
+    }
+
+    #[test]
+    fn pin_required() {
+        assert_eq!(classify("[ErrorCode: 0x8010006e]"), Some(PinFailure::Required));
+        assert_eq!(classify("[ErrorCode: 0x8010006F]"), Some(PinFailure::Required));
+    }
+
+    #[test]
+    fn unrelated_errors() {
+        assert_eq!(classify(""), None);
+        assert_eq!(classify("[ErrorCode: 0x80092004] Cannot find object or property"), None);
+    }
+
+    #[test]
+    fn thumbprint_is_normalized_for_cache() {
+        assert_eq!(normalize("ab:cd ef"), "ABCDEF");
+    }
+}
@@ -0,0 +1,230 @@
+// src/ratelimit.rs
+
+//! Ограничение частоты запросов к True API (token bucket по каждому методу).
+//! Лимитер общий для всех клиентов: создание задач выгрузки и опрос
+//! их статусов расходуют одни и те же корзины.
+
+use once_cell::sync::Lazy;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Группа методов API со своей квотой
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    /// /auth/key и /auth/simpleSignIn
+    Auth,
+    /// POST /dispenser/tasks
+    CreateTask,
+    /// GET /dispenser/tasks/{id} и список задач
+    TaskStatus,
+    /// Остальные методы — без ограничения
+    Other,
+}
+
+impl Endpoint {
+    pub const ALL: [Endpoint; 3] = [Endpoint::Auth, Endpoint::CreateTask, Endpoint::TaskStatus];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Endpoint::Auth => "Вход",
+            Endpoint::CreateTask => "Создание задач",
+            Endpoint::TaskStatus => "Статус задач",
+            Endpoint::Other => "Прочие",
+        }
+    }
+
+    /// Определяет группу по методу и пути запроса
+    pub fn classify(method: &Method, path: &str) -> Self {
+        let path = path.trim_start_matches('/');
+        let path = path.split('?').next().unwrap_or(path).trim_end_matches('/');
+        if path.starts_with("auth/") {
+            Endpoint::Auth
+        } else if path == "dispenser/tasks" && *method == Method::POST {
+            Endpoint::CreateTask
+        } else if path.starts_with("dispenser/tasks") && *method == Method::GET {
+            Endpoint::TaskStatus
+        } else {
+            Endpoint::Other
+        }
+    }
+}
+
+/// Квота: не более `requests` запросов за `per_secs` секунд (0 — без ограничения)
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    pub requests: u32,
+    pub per_secs: u32,
+}
+
+impl RateLimit {
+    fn is_unlimited(&self) -> bool {
+        self.requests == 0 || self.per_secs == 0
+    }
+
+    /// Скорость пополнения корзины, запросов в секунду
+    fn rate(&self) -> f64 {
+        self.requests as f64 / self.per_secs as f64
+    }
+}
+
+/// Квоты по группам методов (настраиваются в settings.json)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct RateLimits {
+    pub auth: RateLimit,
+    pub create_task: RateLimit,
+    pub task_status: RateLimit,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            auth: RateLimit { requests: 10, per_secs: 60 },
+            create_task: RateLimit { requests: 5, per_secs: 60 },
+            task_status: RateLimit { requests: 50, per_secs: 60 },
+        }
+    }
+}
+
+impl RateLimits {
+    /// Квота группы как задана в настройках
+    pub fn configured(&self, endpoint: Endpoint) -> RateLimit {
+        match endpoint {
+            Endpoint::Auth => self.auth,
+            Endpoint::CreateTask => self.create_task,
+            Endpoint::TaskStatus => self.task_status,
+            Endpoint::Other => RateLimit { requests: 0, per_secs: 0 },
+        }
+    }
+
+    /// Действующая квота группы (None — без ограничения)
+    pub fn get(&self, endpoint: Endpoint) -> Option<RateLimit> {
+        let limit = self.configured(endpoint);
+        (!limit.is_unlimited()).then_some(limit)
+    }
+
+    pub fn get_mut(&mut self, endpoint: Endpoint) -> Option<&mut RateLimit> {
+        match endpoint {
+            Endpoint::Auth => Some(&mut self.auth),
+            Endpoint::CreateTask => Some(&mut self.create_task),
+            Endpoint::TaskStatus => Some(&mut self.task_status),
+            Endpoint::Other => None,
+        }
+    }
+}
+
+struct Bucket {
+    limit: RateLimit,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            tokens: limit.requests as f64,
+            updated: Instant::now(),
+        }
+    }
+
+    /// Забирает токен или возвращает время ожидания до следующего
+    fn take(&mut self) -> Result<(), Duration> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.rate()).min(self.limit.requests as f64);
+        self.updated = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / self.limit.rate()))
+        }
+    }
+}
+
+static BUCKETS: Lazy<Mutex<HashMap<Endpoint, Bucket>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Ждёт, пока квота группы позволит отправить запрос
+pub async fn acquire(endpoint: Endpoint, limits: &RateLimits) {
+    let Some(limit) = limits.get(endpoint) else {
+        return;
+    };
+
+    let mut logged = false;
+    loop {
+        let wait = {
+            let mut buckets = BUCKETS.lock().unwrap();
+            let bucket = buckets.entry(endpoint).or_insert_with(|| Bucket::new(limit));
+            // Квоту поменяли в настройках — начинаем с полной корзины
+            if bucket.limit != limit {
+                *bucket = Bucket::new(limit);
+            }
+            match bucket.take() {
+                Ok(()) => return,
+                Err(wait) => wait,
+            }
+        };
+
+        if !logged {
+            crate::storage::debug_log(&format!(
+                "⏳ Лимит запросов «{}»: ожидание {:.1} сек",
+                endpoint.label(),
+                wait.as_secs_f64()
+            ));
+            logged = true;
+        }
+        tokio::time::sleep(wait).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_allows_burst_then_asks_to_wait() {
+        let mut bucket = Bucket::new(RateLimit { requests: 3, per_secs: 60 });
+        for _ in 0..3 {
+            assert_eq!(bucket.take(), Ok(()));
+        }
+        // Один токен пополняется за 20 с
+        let wait = bucket.take().unwrap_err();
+        assert!(wait > Duration::from_secs(19) && wait <= Duration::from_secs(20), "{:?}", wait);
+    }
+
+    #[test]
+    fn bucket_refills_over_time() {
+        let mut bucket = Bucket::new(RateLimit { requests: 2, per_secs: 1 });
+        assert!(bucket.take().is_ok());
+        assert!(bucket.take().is_ok());
+        assert!(bucket.take().is_err());
+
+        // Корзина опустела 0,6 с назад — за это время пополнился один токен
+        bucket.tokens = 0.0;
+        bucket.updated = Instant::now() - Duration::from_millis(600);
+        assert!(bucket.take().is_ok());
+        assert!(bucket.take().is_err());
+
+        // Пополнение не превышает размер корзины
+        bucket.updated = Instant::now() - Duration::from_secs(3600);
+        assert!(bucket.take().is_ok());
+        assert!(bucket.take().is_ok());
+        assert!(bucket.take().is_err());
+    }
+
+    #[test]
+    fn zero_limit_means_unlimited() {
+        let limits = RateLimits {
+            auth: RateLimit { requests: 0, per_secs: 60 },
+            ..RateLimits::default()
+        };
+        assert_eq!(limits.get(Endpoint::Auth), None);
+        assert_eq!(limits.get(Endpoint::Other), None);
+        assert!(limits.get(Endpoint::CreateTask).is_some());
+    }
+}
@@ -0,0 +1,195 @@
+// src/retry.rs
+
+use crate::error::ApiError;
+use once_cell::sync::Lazy;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+/// Политика повторов запросов к API (настраивается в settings.json)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Сколько раз повторять запрос после временной ошибки (0 — не повторять)
+    pub max_retries: u32,
+    /// Задержка перед первым повтором, мс; далее удваивается
+    pub base_delay_ms: u64,
+    /// Верхняя граница задержки, мс (в том числе для Retry-After)
+    pub max_delay_ms: u64,
+    /// Случайно сокращать задержку до половины, чтобы клиенты не повторяли разом
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay_ms: 1000,
+            max_delay_ms: 60_000,
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Задержка перед повтором номер `retry` (с 1). `Retry-After` сервера
+    /// имеет приоритет над экспоненциальной задержкой.
+    pub fn delay(&self, retry: u32, retry_after: Option<Duration>) -> Duration {
+        let max = Duration::from_millis(self.max_delay_ms);
+        if let Some(after) = retry_after {
+            return after.min(max);
+        }
+
+        let exponential = self
+            .base_delay_ms
+            .saturating_mul(1u64 << retry.saturating_sub(1).min(20))
+            .min(self.max_delay_ms);
+        let millis = if self.jitter {
+            exponential / 2 + (random_fraction() * (exponential / 2) as f64) as u64
+        } else {
+            exponential
+        };
+        Duration::from_millis(millis)
+    }
+}
+
+/// Нужно ли повторять запрос после этой ошибки: сеть, таймаут, 5xx и 429
+pub fn should_retry(error: &ApiError) -> bool {
+    error.is_transient()
+}
+
+/// Разбирает заголовок Retry-After: число секунд или HTTP-дата
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (at.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().ok()
+}
+
+/// Псевдослучайное число в [0, 1) для разброса задержек
+fn random_fraction() -> f64 {
+    use std::hash::{BuildHasher, Hasher};
+
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    hasher.write_u128(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default(),
+    );
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Событие повтора запроса для отображения в интерфейсе
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryEvent {
+    /// Путь запроса относительно базового адреса API
+    pub path: String,
+    /// Номер повтора (с 1)
+    pub retry: u32,
+    pub max_retries: u32,
+    pub delay: Duration,
+    pub error: String,
+}
+
+impl RetryEvent {
+    pub fn describe(&self) -> String {
+        format!(
+            "Повтор {} из {} запроса {} через {:.1} с: {}",
+            self.retry,
+            self.max_retries,
+            self.path,
+            self.delay.as_secs_f64(),
+            self.error
+        )
+    }
+}
+
+static EVENTS: Lazy<broadcast::Sender<RetryEvent>> = Lazy::new(|| broadcast::channel(64).0);
+
+/// Сообщает подписчикам о повторе запроса
+pub fn emit(event: RetryEvent) {
+    // Подписчиков может не быть — это не ошибка
+    let _ = EVENTS.send(event);
+}
+
+/// Подписка на события повторов
+pub fn subscribe() -> broadcast::Receiver<RetryEvent> {
+    EVENTS.subscribe()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(jitter: bool) -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 5,
+            base_delay_ms: 100,
+            max_delay_ms: 1000,
+            jitter,
+        }
+    }
+
+    #[test]
+    fn delay_doubles_up_to_the_limit() {
+        let policy = policy(false);
+        let delays: Vec<u64> = (1..=6)
+            .map(|retry| policy.delay(retry, None).as_millis() as u64)
+            .collect();
+        assert_eq!(delays, vec![100, 200, 400, 800, 1000, 1000]);
+        // Большой номер повтора не переполняет сдвиг
+        assert_eq!(policy.delay(u32::MAX, None), Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn jitter_keeps_delay_between_half_and_full() {
+        let policy = policy(true);
+        for _ in 0..50 {
+            let delay = policy.delay(3, None).as_millis();
+            assert!((200..=400).contains(&delay), "{}", delay);
+        }
+    }
+
+    #[test]
+    fn retry_after_takes_priority_but_is_capped() {
+        let policy = policy(true);
+        assert_eq!(policy.delay(1, Some(Duration::from_millis(300))), Duration::from_millis(300));
+        assert_eq!(policy.delay(1, Some(Duration::from_secs(60))), Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn retry_after_header_seconds_and_date() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+
+        headers.insert(RETRY_AFTER, "7".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(7)));
+
+        let at = chrono::Utc::now() + chrono::Duration::seconds(120);
+        headers.insert(RETRY_AFTER, at.to_rfc2822().parse().unwrap());
+        let delay = retry_after(&headers).unwrap();
+        assert!(delay > Duration::from_secs(100) && delay <= Duration::from_secs(120));
+
+        // Дата в прошлом и мусор не дают задержки
+        headers.insert(RETRY_AFTER, "Mon, 01 Jan 2001 00:00:00 GMT".parse().unwrap());
+        assert_eq!(retry_after(&headers), None);
+        headers.insert(RETRY_AFTER, "soon".parse().unwrap());
+        assert_eq!(retry_after(&headers), None);
+    }
+
+    #[test]
+    fn only_transient_errors_are_retried() {
+        let status = |status| ApiError::Status {
+            status,
+            body: String::new(),
+            retry_after: None,
+        };
+        assert!(should_retry(&status(503)));
+        assert!(should_retry(&ApiError::Network("timeout".into())));
+        assert!(!should_retry(&status(400)));
+    }
+}
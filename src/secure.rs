@@ -0,0 +1,362 @@
+// src/secure.rs
+
+//! Шифрование секретов на диске:
+//! - Windows: DPAPI (ключ привязан к учётной записи пользователя)
+//! - Linux/macOS: AES-256-GCM, ключ — из Secret Service, иначе из парольной фразы
+
+/// Заголовок зашифрованного файла
+const MAGIC: &[u8] = b"CZN1";
+
+const SCHEME_DPAPI: u8 = 1;
+#[cfg(not(windows))]
+const SCHEME_KEYRING: u8 = 2;
+#[cfg(not(windows))]
+const SCHEME_PASSPHRASE: u8 = 3;
+
+/// Переменная окружения с парольной фразой (если Secret Service недоступен)
+#[cfg(not(windows))]
+pub const PASSPHRASE_ENV: &str = "CZN_TOKEN_PASSPHRASE";
+
+/// Парольная фраза, введённая в приложении; хранится только в памяти
+#[cfg(not(windows))]
+static SESSION_PASSPHRASE: once_cell::sync::Lazy<std::sync::Mutex<Option<zeroize::Zeroizing<String>>>> =
+    once_cell::sync::Lazy::new(|| std::sync::Mutex::new(None));
+
+/// Запоминает парольную фразу до закрытия приложения
+#[cfg(not(windows))]
+pub fn set_passphrase(passphrase: &str) {
+    *SESSION_PASSPHRASE.lock().unwrap() = Some(zeroize::Zeroizing::new(passphrase.to_string()));
+    // Токены, которые раньше не удалось расшифровать, теперь могут прочитаться
+    crate::storage::invalidate_sessions();
+}
+
+#[cfg(windows)]
+pub fn set_passphrase(_passphrase: &str) {}
+
+/// Нечем зашифровать токен: Secret Service недоступен, а парольная фраза
+/// не задана ни в приложении, ни в переменной окружения
+#[cfg(not(windows))]
+pub fn passphrase_required() -> bool {
+    SESSION_PASSPHRASE.lock().unwrap().is_none()
+        && std::env::var(PASSPHRASE_ENV).is_err()
+        && !platform::keyring_available()
+}
+
+#[cfg(windows)]
+pub fn passphrase_required() -> bool {
+    false
+}
+
+/// Забывает ключи шифрования: парольную фразу в памяти и ключ в Secret Service.
+/// Всё, что было ими зашифровано, после этого не прочитать.
+#[cfg(not(windows))]
+pub fn forget_keys() -> Result<(), String> {
+    SESSION_PASSPHRASE.lock().unwrap().take();
+    platform::delete_keyring_key()
+}
+
+/// Ключ DPAPI принадлежит учётной записи Windows и приложением не хранится
+#[cfg(windows)]
+pub fn forget_keys() -> Result<(), String> {
+    Ok(())
+}
+
+/// Зашифрован ли файл этим модулем (иначе — старый открытый формат)
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Шифрует данные для записи на диск
+pub fn encrypt(plain: &[u8]) -> Result<Vec<u8>, String> {
+    let (scheme, payload) = platform::encrypt(plain)?;
+    let mut out = MAGIC.to_vec();
+    out.push(scheme);
+    out.extend(payload);
+    Ok(out)
+}
+
+/// Расшифровывает данные, записанные `encrypt`
+pub fn decrypt(data: &[u8]) -> Result<Vec<u8>, String> {
+    if !is_encrypted(data) || data.len() < MAGIC.len() + 1 {
+        return Err("Неизвестный формат зашифрованного файла".to_string());
+    }
+    let scheme = data[MAGIC.len()];
+    platform::decrypt(scheme, &data[MAGIC.len() + 1..])
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::SCHEME_DPAPI;
+    use windows::core::PCWSTR;
+    use windows::Win32::Foundation::{LocalFree, HLOCAL};
+    use windows::Win32::Security::Cryptography::{
+        CryptProtectData, CryptUnprotectData, CRYPTPROTECT_UI_FORBIDDEN, CRYPT_INTEGER_BLOB,
+    };
+
+    /// Копирует результат DPAPI и освобождает буфер, выделенный системой
+    fn take_blob(blob: &CRYPT_INTEGER_BLOB) -> Vec<u8> {
+        unsafe {
+            let data = std::slice::from_raw_parts(blob.pbData, blob.cbData as usize).to_vec();
+            let _ = LocalFree(HLOCAL(blob.pbData as _));
+            data
+        }
+    }
+
+    pub fn encrypt(plain: &[u8]) -> Result<(u8, Vec<u8>), String> {
+        let input = CRYPT_INTEGER_BLOB {
+            cbData: plain.len() as u32,
+            pbData: plain.as_ptr() as *mut u8,
+        };
+        let mut output = CRYPT_INTEGER_BLOB::default();
+        unsafe {
+            CryptProtectData(
+                &input,
+                PCWSTR::null(),
+                None,
+                None,
+                None,
+                CRYPTPROTECT_UI_FORBIDDEN,
+                &mut output,
+            )
+        }
+        .map_err(|e| format!("DPAPI: не удалось зашифровать данные: {}", e))?;
+        Ok((SCHEME_DPAPI, take_blob(&output)))
+    }
+
+    pub fn decrypt(scheme: u8, payload: &[u8]) -> Result<Vec<u8>, String> {
+        if scheme != SCHEME_DPAPI {
+            return Err("Файл зашифрован на другой платформе".to_string());
+        }
+        let input = CRYPT_INTEGER_BLOB {
+            cbData: payload.len() as u32,
+            pbData: payload.as_ptr() as *mut u8,
+        };
+        let mut output = CRYPT_INTEGER_BLOB::default();
+        unsafe {
+            CryptUnprotectData(
+                &input,
+                None,
+                None,
+                None,
+                None,
+                CRYPTPROTECT_UI_FORBIDDEN,
+                &mut output,
+            )
+        }
+        .map_err(|e| format!("DPAPI: не удалось расшифровать данные: {}", e))?;
+        Ok(take_blob(&output))
+    }
+}
+
+#[cfg(not(windows))]
+mod platform {
+    use super::{PASSPHRASE_ENV, SCHEME_DPAPI, SCHEME_KEYRING, SCHEME_PASSPHRASE, SESSION_PASSPHRASE};
+    use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+    use aes_gcm::{Aes256Gcm, Nonce};
+    use zeroize::Zeroizing;
+
+    const KEYRING_SERVICE: &str = "czn-dioxus";
+    const KEYRING_USER: &str = "token-encryption-key";
+    const NONCE_LEN: usize = 12;
+    const SALT_LEN: usize = 16;
+    const PBKDF2_ROUNDS: u32 = 200_000;
+
+    /// Что лежит в Secret Service под нашим именем
+    enum Stored {
+        Key(Zeroizing<Vec<u8>>),
+        Missing,
+        Unavailable,
+    }
+
+    /// Запись Secret Service с ключом шифрования.
+    /// Тесты настоящий Secret Service пользователя не трогают — только парольная фраза.
+    fn keyring_entry() -> Option<keyring::Entry> {
+        if cfg!(test) {
+            return None;
+        }
+        keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER).ok()
+    }
+
+    /// Читает ключ, ничего не создавая и не перезаписывая
+    fn stored_key(entry: &keyring::Entry) -> Stored {
+        match entry.get_secret() {
+            Ok(secret) if secret.len() == 32 => Stored::Key(Zeroizing::new(secret)),
+            // Секрет другой длины не наш или повреждён: затирать его нельзя,
+            // иначе пропадёт то, что им зашифровано
+            Ok(secret) => {
+                drop(Zeroizing::new(secret));
+                Stored::Unavailable
+            }
+            Err(keyring::Error::NoEntry) => Stored::Missing,
+            Err(_) => Stored::Unavailable,
+        }
+    }
+
+    /// Существующий ключ из Secret Service (для расшифровки)
+    fn keyring_key() -> Option<Zeroizing<Vec<u8>>> {
+        match stored_key(&keyring_entry()?) {
+            Stored::Key(key) => Some(key),
+            Stored::Missing | Stored::Unavailable => None,
+        }
+    }
+
+    /// Ключ из Secret Service для шифрования; если его ещё нет — генерируется и сохраняется
+    fn keyring_key_or_create() -> Option<Zeroizing<Vec<u8>>> {
+        let entry = keyring_entry()?;
+        match stored_key(&entry) {
+            Stored::Key(key) => Some(key),
+            Stored::Missing => {
+                let key = Aes256Gcm::generate_key(&mut OsRng);
+                entry.set_secret(key.as_slice()).ok()?;
+                Some(Zeroizing::new(key.to_vec()))
+            }
+            Stored::Unavailable => None,
+        }
+    }
+
+    pub fn delete_keyring_key() -> Result<(), String> {
+        let Some(entry) = keyring_entry() else {
+            return Ok(());
+        };
+        match entry.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            // Без Secret Service ключа там и не было
+            Err(keyring::Error::PlatformFailure(_) | keyring::Error::NoStorageAccess(_)) => Ok(()),
+            Err(e) => Err(format!("Не удалось удалить ключ шифрования из Secret Service: {}", e)),
+        }
+    }
+
+    /// Можно ли шифровать ключом из Secret Service; сам ключ при проверке не создаётся
+    pub fn keyring_available() -> bool {
+        keyring_entry().is_some_and(|entry| !matches!(stored_key(&entry), Stored::Unavailable))
+    }
+
+    /// Ключ из парольной фразы (PBKDF2-HMAC-SHA256): введённой в приложении
+    /// или из переменной окружения
+    fn passphrase_key(salt: &[u8]) -> Result<Zeroizing<Vec<u8>>, String> {
+        let session = SESSION_PASSPHRASE.lock().unwrap().clone();
+        let passphrase = match session {
+            Some(passphrase) => passphrase,
+            None => Zeroizing::new(std::env::var(PASSPHRASE_ENV).map_err(|_| {
+                format!(
+                    "Secret Service недоступен; введите парольную фразу или задайте её в переменной {}",
+                    PASSPHRASE_ENV
+                )
+            })?),
+        };
+        let mut key = Zeroizing::new(vec![0u8; 32]);
+        pbkdf2::pbkdf2_hmac::<sha2::Sha256>(passphrase.as_bytes(), salt, PBKDF2_ROUNDS, &mut key);
+        Ok(key)
+    }
+
+    fn seal(key: &[u8], plain: &[u8]) -> Result<Vec<u8>, String> {
+        let cipher = Aes256Gcm::new_from_slice(key).map_err(|e| e.to_string())?;
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, plain)
+            .map_err(|_| "Не удалось зашифровать данные".to_string())?;
+        let mut out = nonce.to_vec();
+        out.extend(ciphertext);
+        Ok(out)
+    }
+
+    fn open(key: &[u8], sealed: &[u8]) -> Result<Vec<u8>, String> {
+        if sealed.len() < NONCE_LEN {
+            return Err("Зашифрованный файл повреждён".to_string());
+        }
+        let cipher = Aes256Gcm::new_from_slice(key).map_err(|e| e.to_string())?;
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| "Не удалось расшифровать данные: неверный ключ или файл повреждён".to_string())
+    }
+
+    pub fn encrypt(plain: &[u8]) -> Result<(u8, Vec<u8>), String> {
+        if let Some(key) = keyring_key_or_create() {
+            return Ok((SCHEME_KEYRING, seal(&key, plain)?));
+        }
+
+        let salt: [u8; SALT_LEN] = rand_salt();
+        let key = passphrase_key(&salt)?;
+        let mut out = salt.to_vec();
+        out.extend(seal(&key, plain)?);
+        Ok((SCHEME_PASSPHRASE, out))
+    }
+
+    pub fn decrypt(scheme: u8, payload: &[u8]) -> Result<Vec<u8>, String> {
+        match scheme {
+            SCHEME_KEYRING => {
+                let key = keyring_key().ok_or("Ключ шифрования в Secret Service недоступен")?;
+                open(&key, payload)
+            }
+            SCHEME_PASSPHRASE => {
+                if payload.len() < SALT_LEN {
+                    return Err("Зашифрованный файл повреждён".to_string());
+                }
+                let (salt, sealed) = payload.split_at(SALT_LEN);
+                open(&passphrase_key(salt)?, sealed)
+            }
+            SCHEME_DPAPI => Err("Файл зашифрован DPAPI в Windows и здесь недоступен".to_string()),
+            _ => Err("Неизвестная схема шифрования".to_string()),
+        }
+    }
+
+    fn rand_salt() -> [u8; SALT_LEN] {
+        use aes_gcm::aead::rand_core::RngCore;
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        salt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// В тестах Secret Service не используется: ключ только из парольной фразы
+    fn with_key() {
+        set_passphrase("тестовая парольная фраза");
+    }
+
+    #[test]
+    fn encrypt_decrypt_round_trip() {
+        with_key();
+        let plain = "eyJhbGciOi.токен.подпись".as_bytes();
+        let encrypted = encrypt(plain).unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert!(!encrypted.windows(plain.len()).any(|w| w == plain));
+        assert_eq!(decrypt(&encrypted).unwrap(), plain);
+
+        // Каждое шифрование со своим nonce (и солью)
+        assert_ne!(encrypt(plain).unwrap(), encrypted);
+    }
+
+    #[test]
+    fn tampered_data_is_rejected() {
+        with_key();
+        let mut encrypted = encrypt(b"secret").unwrap();
+        let last = encrypted.len() - 1;
+        encrypted[last] ^= 0x01;
+        assert!(decrypt(&encrypted).is_err());
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn tests_do_not_touch_keyring() {
+        with_key();
+        assert!(!platform::keyring_available());
+        assert!(!passphrase_required());
+        let encrypted = encrypt(b"secret").unwrap();
+        assert_eq!(encrypted[MAGIC.len()], SCHEME_PASSPHRASE);
+    }
+
+    #[test]
+    fn unknown_format_is_rejected() {
+        assert!(!is_encrypted(b"plain token"));
+        assert!(decrypt(b"plain token").is_err());
+        assert!(decrypt(MAGIC).is_err());
+        let mut unknown_scheme = MAGIC.to_vec();
+        unknown_scheme.extend([0x7F, 0, 0, 0]);
+        assert!(decrypt(&unknown_scheme).is_err());
+    }
+}
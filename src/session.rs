@@ -0,0 +1,213 @@
+// src/session.rs
+
+use crate::settings::OmsConnection;
+use crate::storage::StoredToken;
+use base64::Engine;
+use chrono::{Duration, Utc};
+use once_cell::sync::Lazy;
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+/// Срок жизни токена СУЗ: он не является JWT, поэтому срок берём из документации
+pub const OMS_TOKEN_LIFETIME: Duration = Duration::hours(10);
+
+/// За сколько до истечения токена выполнять повторный вход
+pub const REFRESH_MARGIN: Duration = Duration::minutes(5);
+
+// --- Сессии, получившие ответ 401 от API: токен отозван или истёк раньше срока ---
+static UNAUTHORIZED: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// Отмечает, что сервер отклонил токен сессии (HTTP 401)
+pub fn mark_unauthorized(session_key: &str) {
+    UNAUTHORIZED.lock().unwrap().insert(session_key.to_string());
+}
+
+/// Сбрасывает признак 401 для сессии и сообщает, был ли он установлен
+pub fn take_unauthorized(session_key: &str) -> bool {
+    UNAUTHORIZED.lock().unwrap().remove(session_key)
+}
+
+/// Поля полезной нагрузки JWT, которые нас интересуют
+#[derive(serde::Deserialize, Default)]
+struct Claims {
+    iat: Option<i64>,
+    exp: Option<i64>,
+    inn: Option<String>,
+    org_inn: Option<String>,
+}
+
+/// Декодирует полезную нагрузку JWT без проверки подписи
+fn decode_claims(token: &str) -> Option<Claims> {
+    let payload = token.split('.').nth(1)?;
+    let bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// Собирает сохраняемую запись: токен, время выдачи и истечения, ИНН организации
+pub fn describe_token(token: &str, thumbprint: Option<&str>) -> StoredToken {
+    let claims = decode_claims(token).unwrap_or_default();
+    StoredToken {
+        token: token.trim().to_string(),
+        issued_at: claims.iat,
+        expires_at: claims.exp,
+        inn: claims.inn.or(claims.org_inn),
+        thumbprint: thumbprint.map(|t| t.to_string()),
+        oms_connection: None,
+        oms_id: None,
+    }
+}
+
+/// Дополняет запись токена данными подключения СУЗ
+pub fn attach_oms(stored: &mut StoredToken, oms: &OmsConnection) {
+    if stored.inn.is_none() {
+        stored.inn = Some(oms.inn.trim().to_string());
+    }
+    if stored.expires_at.is_none() {
+        let now = Utc::now();
+        stored.issued_at = Some(now.timestamp());
+        stored.expires_at = Some((now + OMS_TOKEN_LIFETIME).timestamp());
+    }
+    stored.oms_connection = Some(oms.oms_connection.trim().to_string());
+    stored.oms_id = Some(oms.oms_id.trim().to_string());
+}
+
+/// Подключение СУЗ, к которому относится токен (для повторного входа)
+pub fn oms_of(stored: &StoredToken) -> Option<OmsConnection> {
+    Some(OmsConnection {
+        inn: stored.inn.clone().unwrap_or_default(),
+        oms_connection: stored.oms_connection.clone()?,
+        oms_id: stored.oms_id.clone().unwrap_or_default(),
+    })
+}
+
+/// Оставшееся время жизни токена (None — срок неизвестен)
+pub fn remaining(stored: &StoredToken) -> Option<Duration> {
+    stored
+        .expires_at
+        .map(|exp| Duration::seconds(exp - Utc::now().timestamp()))
+}
+
+/// Нужно ли обновить токен: истекает в пределах `REFRESH_MARGIN` или уже истёк
+pub fn expires_soon(stored: &StoredToken) -> bool {
+    remaining(stored).is_some_and(|left| left <= REFRESH_MARGIN)
+}
+
+/// Короткое имя сессии для списка: "ИНН 7700000000 · AB12CD34"
+pub fn label(stored: &StoredToken) -> String {
+    let inn = stored
+        .inn
+        .as_deref()
+        .map(|inn| format!("ИНН {}", inn))
+        .unwrap_or_else(|| "ИНН неизвестен".to_string());
+    let thumbprint = stored
+        .thumbprint
+        .as_deref()
+        .map(|t| t.chars().take(8).collect::<String>())
+        .unwrap_or_default();
+    if stored.is_oms() {
+        format!("{} · {} · СУЗ", inn, thumbprint)
+    } else {
+        format!("{} · {}", inn, thumbprint)
+    }
+}
+
+/// Строка для заголовка: "ИНН 7700000000 · осталось 9:41:05"
+pub fn status_text(stored: &StoredToken) -> String {
+    let inn = stored
+        .inn
+        .as_deref()
+        .map(|inn| format!("ИНН {} · ", inn))
+        .unwrap_or_default();
+
+    match remaining(stored) {
+        Some(left) if left > Duration::zero() => format!(
+            "{}осталось {}:{:02}:{:02}",
+            inn,
+            left.num_hours(),
+            left.num_minutes() % 60,
+            left.num_seconds() % 60
+        ),
+        Some(_) => format!("{}сессия истекла", inn),
+        None => format!("{}срок действия неизвестен", inn),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jwt(payload: &serde_json::Value) -> String {
+        let encode = |bytes: &[u8]| base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes);
+        format!(
+            "{}.{}.{}",
+            encode(br#"{"alg":"GOST3410","typ":"JWT"}"#),
+            encode(payload.to_string().as_bytes()),
+            encode(b"signature")
+        )
+    }
+
+    #[test]
+    fn decodes_jwt_claims() {
+        let token = jwt(&serde_json::json!({ "iat": 1_700_000_000, "exp": 1_700_036_000, "inn": "7700000000" }));
+        let stored = describe_token(&format!(" {}\n", token), Some("AB12CD34EF"));
+        assert_eq!(stored.token, token);
+        assert_eq!(stored.issued_at, Some(1_700_000_000));
+        assert_eq!(stored.expires_at, Some(1_700_036_000));
+        assert_eq!(stored.inn.as_deref(), Some("7700000000"));
+        assert_eq!(stored.thumbprint.as_deref(), Some("AB12CD34EF"));
+        assert_eq!(label(&stored), "ИНН 7700000000 · AB12CD34");
+    }
+
+    #[test]
+    fn org_inn_is_used_without_inn() {
+        let token = jwt(&serde_json::json!({ "org_inn": "7800000000" }));
+        assert_eq!(describe_token(&token, None).inn.as_deref(), Some("7800000000"));
+    }
+
+    #[test]
+    fn opaque_token_has_no_claims() {
+        let stored = describe_token("not-a-jwt", None);
+        assert_eq!(stored.token, "not-a-jwt");
+        assert_eq!(stored.expires_at, None);
+        assert_eq!(stored.inn, None);
+        assert_eq!(remaining(&stored), None);
+        assert!(!expires_soon(&stored));
+        assert_eq!(status_text(&stored), "срок действия неизвестен");
+    }
+
+    #[test]
+    fn expiry_and_refresh_margin() {
+        let now = Utc::now().timestamp();
+        let token = jwt(&serde_json::json!({ "exp": now + 3600, "inn": "7700000000" }));
+        let stored = describe_token(&token, None);
+        assert!(!expires_soon(&stored));
+        let left = remaining(&stored).unwrap();
+        assert!(left > Duration::minutes(59) && left <= Duration::hours(1));
+        assert!(status_text(&stored).starts_with("ИНН 7700000000 · осталось "));
+
+        let token = jwt(&serde_json::json!({ "exp": now + 60 }));
+        assert!(expires_soon(&describe_token(&token, None)));
+
+        let token = jwt(&serde_json::json!({ "exp": now - 60 }));
+        let expired = describe_token(&token, None);
+        assert!(expires_soon(&expired));
+        assert_eq!(status_text(&expired), "сессия истекла");
+    }
+
+    #[test]
+    fn oms_connection_sets_lifetime_and_inn() {
+        let mut stored = describe_token("oms-token", Some("AB"));
+        let oms = OmsConnection {
+            inn: " 7700000000 ".to_string(),
+            oms_connection: "conn".to_string(),
+            oms_id: "oms".to_string(),
+        };
+        attach_oms(&mut stored, &oms);
+        assert!(stored.is_oms());
+        assert_eq!(stored.inn.as_deref(), Some("7700000000"));
+        assert_eq!(stored.expires_at.unwrap() - stored.issued_at.unwrap(), OMS_TOKEN_LIFETIME.num_seconds());
+        assert_eq!(oms_of(&stored).unwrap().oms_connection, "conn");
+    }
+}
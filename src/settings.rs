@@ -0,0 +1,304 @@
+// src/settings.rs
+
+use serde::{Deserialize, Serialize};
+
+/// Адрес True API промышленного контура
+pub const PRODUCTION_URL: &str = "https://markirovka.crpt.ru/api/v3/true-api";
+/// Адрес True API тестового контура (песочницы) ЦРПТ
+pub const SANDBOX_URL: &str = "https://markirovka.sandbox.crptech.ru/api/v3/true-api";
+
+/// Переменная окружения с контуром: production, sandbox, custom или demo
+pub const ENV_VAR: &str = "CZN_ENV";
+/// Переменная окружения с адресом API (выбирает собственный контур)
+pub const BASE_URL_VAR: &str = "CZN_API_BASE_URL";
+
+/// Контур True API, с которым работает приложение
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Environment {
+    #[default]
+    Production,
+    Sandbox,
+    /// Собственный адрес (например, локальный mock-сервер)
+    Custom,
+    /// Встроенная имитация True API с учебными сертификатами
+    Demo,
+}
+
+impl Environment {
+    pub const ALL: [Environment; 4] = [
+        Environment::Production,
+        Environment::Sandbox,
+        Environment::Custom,
+        Environment::Demo,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Environment::Production => "Промышленный контур",
+            Environment::Sandbox => "Песочница ЦРПТ",
+            Environment::Custom => "Собственный адрес",
+            Environment::Demo => "Демо-режим (без сети и КриптоПро)",
+        }
+    }
+
+    /// Короткое имя для переменной окружения и путей на диске
+    pub fn slug(&self) -> &'static str {
+        match self {
+            Environment::Production => "production",
+            Environment::Sandbox => "sandbox",
+            Environment::Custom => "custom",
+            Environment::Demo => "demo",
+        }
+    }
+
+    pub fn from_slug(slug: &str) -> Option<Environment> {
+        Environment::ALL
+            .into_iter()
+            .find(|env| env.slug().eq_ignore_ascii_case(slug.trim()))
+    }
+}
+
+/// Подключение к СУЗ (станции управления заказами) организации
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(default)]
+pub struct OmsConnection {
+    /// ИНН организации
+    pub inn: String,
+    /// Идентификатор подключения (omsConnection) из личного кабинета
+    pub oms_connection: String,
+    /// Идентификатор СУЗ (omsId)
+    pub oms_id: String,
+}
+
+/// Как выбирать прокси-сервер
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ProxyMode {
+    /// Системные настройки и переменные HTTP(S)_PROXY / NO_PROXY
+    #[default]
+    System,
+    /// Без прокси
+    Disabled,
+    /// Прокси из настроек
+    Manual,
+}
+
+impl ProxyMode {
+    pub const ALL: [ProxyMode; 3] = [ProxyMode::System, ProxyMode::Disabled, ProxyMode::Manual];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ProxyMode::System => "Системный",
+            ProxyMode::Disabled => "Без прокси",
+            ProxyMode::Manual => "Указать вручную",
+        }
+    }
+
+    pub fn slug(&self) -> &'static str {
+        match self {
+            ProxyMode::System => "system",
+            ProxyMode::Disabled => "disabled",
+            ProxyMode::Manual => "manual",
+        }
+    }
+
+    pub fn from_slug(slug: &str) -> Option<ProxyMode> {
+        ProxyMode::ALL.into_iter().find(|mode| mode.slug() == slug)
+    }
+}
+
+/// Настройки прокси-сервера
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(default)]
+pub struct ProxySettings {
+    pub mode: ProxyMode,
+    /// Адрес: http://, https://, socks5:// или socks5h://
+    pub url: String,
+    pub username: String,
+    /// Пароль, зашифрованный `secure` (Base64)
+    pub password_protected: String,
+    /// Узлы без прокси через запятую (например, "localhost,.corp.local")
+    pub no_proxy: String,
+}
+
+impl ProxySettings {
+    /// Шифрует и запоминает пароль прокси (пустая строка — удалить)
+    pub fn set_password(&mut self, password: &str) -> Result<(), String> {
+        use base64::Engine;
+
+        self.password_protected = if password.is_empty() {
+            String::new()
+        } else {
+            let encrypted = crate::secure::encrypt(password.as_bytes())?;
+            base64::engine::general_purpose::STANDARD.encode(encrypted)
+        };
+        Ok(())
+    }
+
+    /// Расшифровывает пароль прокси
+    pub fn password(&self) -> Result<zeroize::Zeroizing<String>, String> {
+        use base64::Engine;
+
+        if self.password_protected.is_empty() {
+            return Ok(zeroize::Zeroizing::new(String::new()));
+        }
+        let encrypted = base64::engine::general_purpose::STANDARD
+            .decode(&self.password_protected)
+            .map_err(|e| format!("Повреждён пароль прокси: {}", e))?;
+        let plain = crate::secure::decrypt(&encrypted)
+            .map_err(|e| format!("Не удалось расшифровать пароль прокси: {}", e))?;
+        String::from_utf8(plain)
+            .map(zeroize::Zeroizing::new)
+            .map_err(|_| "Повреждён пароль прокси".to_string())
+    }
+}
+
+/// Пользовательские настройки приложения (settings.json в директории приложения)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    /// Адрес службы штампов времени (RFC 3161).
+    /// Хэш в запросе — только SHA-256: службы, принимающие лишь ГОСТ Р 34.11-2012, не поддерживаются
+    pub tsa_url: String,
+    /// Добавлять штамп времени к подписям файлов (CAdES-T)
+    pub cades_t: bool,
+    /// Сколько минут хранить введённый PIN в памяти (0 — не хранить)
+    pub pin_cache_minutes: u32,
+    /// Контур True API
+    pub environment: Environment,
+    /// Адрес API для собственного контура
+    pub custom_base_url: String,
+    /// Подключения к СУЗ по организациям
+    pub oms_connections: Vec<OmsConnection>,
+    /// Прокси-сервер для всех HTTP-запросов
+    pub proxy: ProxySettings,
+    /// Дополнительные корневые сертификаты (PEM или DER), например для TLS-инспекции
+    pub extra_ca_paths: Vec<String>,
+    /// Допустимое расхождение часов с сервером при входе, секунд (0 — не проверять)
+    pub clock_skew_threshold_secs: u32,
+    /// Повторы запросов к API после временных ошибок
+    pub retry: crate::retry::RetryPolicy,
+    /// Квоты запросов к API по группам методов
+    pub rate_limits: crate::ratelimit::RateLimits,
+    /// Текущие параметры выгрузки нарушений
+    pub export_params: crate::dispenser::ExportParams,
+    /// Сохранённые наборы параметров выгрузки
+    pub export_profiles: Vec<crate::dispenser::ExportProfile>,
+    /// Папка для результатов выгрузки (пусто — exports в директории приложения)
+    pub download_dir: String,
+    /// Распаковывать ZIP-архивы с результатами
+    pub unzip_results: bool,
+    /// Скачивать готовые результаты автоматически при опросе статусов
+    /// (только для задач, созданных в приложении)
+    pub auto_download: bool,
+    /// За сколько дней до удаления с сервера предупреждать о нескачанном результате
+    pub expiry_warning_days: u32,
+    /// Сколько дней хранить задачи в реестре (0 — бессрочно)
+    pub task_retention_days: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            tsa_url: String::new(),
+            cades_t: false,
+            pin_cache_minutes: 15,
+            environment: Environment::Production,
+            custom_base_url: String::new(),
+            oms_connections: Vec::new(),
+            proxy: ProxySettings::default(),
+            extra_ca_paths: Vec::new(),
+            clock_skew_threshold_secs: 300,
+            retry: crate::retry::RetryPolicy::default(),
+            rate_limits: crate::ratelimit::RateLimits::default(),
+            export_params: crate::dispenser::ExportParams::default(),
+            export_profiles: Vec::new(),
+            download_dir: String::new(),
+            unzip_results: true,
+            auto_download: true,
+            expiry_warning_days: 1,
+            task_retention_days: 30,
+        }
+    }
+}
+
+impl Settings {
+    /// Проверяет настройки перед сохранением
+    pub fn validate(&self) -> Result<(), String> {
+        if self.environment == Environment::Custom {
+            check_base_url(&self.custom_base_url)?;
+        }
+        Ok(())
+    }
+}
+
+/// Адрес API должен быть абсолютным: относительный reqwest отклонит с невнятной ошибкой
+pub fn check_base_url(url: &str) -> Result<(), String> {
+    let url = url.trim();
+    if url.is_empty() {
+        return Err("Не задан адрес API для своего контура: укажите его в настройках".to_string());
+    }
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err(format!(
+            "Адрес API «{}» должен начинаться с http:// или https://",
+            url
+        ));
+    }
+    Ok(())
+}
+
+/// Загружает настройки; при отсутствии или повреждении файла — значения по умолчанию
+pub fn load() -> Settings {
+    crate::storage::settings_path()
+        .ok()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+/// Сохраняет настройки на диск
+pub fn save(settings: &Settings) -> Result<(), String> {
+    let path = crate::storage::settings_path().map_err(|e| e.to_string())?;
+    let json = serde_json::to_string_pretty(settings)
+        .map_err(|e| format!("Не удалось сериализовать настройки: {}", e))?;
+    std::fs::write(&path, json).map_err(|e| format!("Не удалось сохранить настройки: {}", e))
+}
+
+/// Подключение к СУЗ для организации
+pub fn oms_for_inn(inn: &str) -> Option<OmsConnection> {
+    load()
+        .oms_connections
+        .into_iter()
+        .find(|oms| oms.inn.trim() == inn.trim() && !oms.oms_connection.trim().is_empty())
+}
+
+/// Задан ли контур переменными окружения (тогда настройка в интерфейсе не действует)
+pub fn environment_overridden() -> bool {
+    std::env::var(ENV_VAR).is_ok() || std::env::var(BASE_URL_VAR).is_ok()
+}
+
+/// Текущий контур: из `CZN_ENV` / `CZN_API_BASE_URL`, иначе из настроек
+pub fn environment() -> Environment {
+    if let Some(env) = std::env::var(ENV_VAR).ok().and_then(|v| Environment::from_slug(&v)) {
+        return env;
+    }
+    if std::env::var(BASE_URL_VAR).is_ok() {
+        return Environment::Custom;
+    }
+    load().environment
+}
+
+/// Базовый адрес True API текущего контура (без завершающего '/')
+pub fn api_base_url() -> String {
+    let url = match environment() {
+        Environment::Production => PRODUCTION_URL.to_string(),
+        Environment::Sandbox => SANDBOX_URL.to_string(),
+        Environment::Custom => std::env::var(BASE_URL_VAR)
+            .ok()
+            .filter(|url| !url.trim().is_empty())
+            .unwrap_or_else(|| load().custom_base_url),
+        Environment::Demo => crate::demo::base_url(),
+    };
+    url.trim().trim_end_matches('/').to_string()
+}
@@ -1,22 +1,13 @@
 // src/signing.rs
 
-use std::process::Command;
-use std::path::Path;
-use reqwest;
-use serde::Deserialize;
+use std::io::Read;
+use std::process::{Command, Output, Stdio};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use zeroize::Zeroizing;
 use dioxus::prelude::spawn;
 use crate::dispenser;
-
-#[derive(Deserialize, Debug)]
-struct AuthResponse {
-    uuid: String,
-    data: String,
-}
-
-#[derive(Deserialize)]
-struct SignInResponse {
-    token: String,
-}
+use crate::error::SignError;
 
 /// Подготавливает сообщение для отображения в UI
 pub fn prepare_signature_message(cert: &crate::certificate::CertificateInfo) -> String {
@@ -31,79 +22,100 @@ pub fn extract_attr(s: &str, key: &str) -> Option<String> {
         .map(|part| part.trim()[key.len()..].to_string())
 }
 
-/// Основная функция: получает challenge, подписывает, отправляет подпись, сохраняет токен
-pub async fn sign_file_with_certificate(cert: &crate::certificate::CertificateInfo) -> Result<String, String> {
+/// Сколько ждать cryptcp.exe: без PIN защищённый контейнер может ждать ввода бесконечно
+const CRYPTCP_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Основная функция: выполняет вход, делает сессию выбранной и запускает
+/// выгрузку задач по её организации в фоне.
+/// `pin` — PIN-код контейнера; если не задан, используется сохранённый в сеансе.
+pub async fn sign_file_with_certificate(
+    cert: &crate::certificate::CertificateInfo,
+    pin: Option<&str>,
+) -> Result<String, SignError> {
+    let stored = sign_in(cert, pin).await?;
+    let key = stored.key();
+    if let Err(e) = crate::storage::set_active_session(&key) {
+        eprintln!("⚠️ {}", e);
+    }
+
+    // Запускаем выгрузку задач в фоне
+    spawn(async move {
+        let params = crate::settings::load().export_params;
+        match dispenser::fetch_violation_tasks(dispenser::ExportScope::Session(key), &params).await {
+            Ok(results) => {
+                for msg in results {
+                    eprintln!("{}", msg);
+                }
+            }
+            Err(e) => {
+                eprintln!("❌ Ошибка выгрузки нарушений: {}", e);
+            }
+        }
+    });
+
+    Ok("Авторизация успешна. Выгрузка запрошена.".to_string())
+}
+
+/// Вход: получает challenge, подписывает, отправляет подпись, сохраняет токен.
+/// Используется и для автоматического продления сеанса — без запуска выгрузки.
+pub async fn sign_in(
+    cert: &crate::certificate::CertificateInfo,
+    pin: Option<&str>,
+) -> Result<crate::storage::StoredToken, SignError> {
+    sign_in_to(cert, pin, None).await
+}
+
+/// Вход в СУЗ: те же шаги, но подпись подтверждается на auth/simpleSignIn/{omsConnection}
+pub async fn sign_in_oms(
+    cert: &crate::certificate::CertificateInfo,
+    pin: Option<&str>,
+    oms: &crate::settings::OmsConnection,
+) -> Result<crate::storage::StoredToken, SignError> {
+    sign_in_to(cert, pin, Some(oms)).await
+}
+
+async fn sign_in_to(
+    cert: &crate::certificate::CertificateInfo,
+    pin: Option<&str>,
+    oms: Option<&crate::settings::OmsConnection>,
+) -> Result<crate::storage::StoredToken, SignError> {
     // Получаем пути к временным файлам
-    let key_path = crate::storage::key_path().map_err(|e| format!("Не удалось получить путь к key: {}", e))?;
-    let sig_path = crate::storage::sig_path().map_err(|e| format!("Не удалось получить путь к sig: {}", e))?;
+    let key_path = crate::storage::key_path()
+        .map_err(|e| SignError::File(format!("Не удалось получить путь к key: {}", e)))?;
+    let sig_path = crate::storage::sig_path()
+        .map_err(|e| SignError::File(format!("Не удалось получить путь к sig: {}", e)))?;
 
     // Убеждаемся, что папка .czn / czn-dioxus существует
     let _ = crate::storage::ensure_czn_dir();
 
     // Шаг 1: GET /auth/key — получение данных для подписи
-    let client = reqwest::Client::new();
-    let response: AuthResponse = client
-        .get("https://markirovka.crpt.ru/api/v3/true-api/auth/key")
-        .header("User-Agent", "czn-dioxus/1.0")
-        .send()
-        .await
-        .map_err(|e| format!("Ошибка сети (key): {}", e))?
-        .json()
-        .await
-        .map_err(|e| format!("Ошибка парсинга JSON: {}", e))?;
+    let api = crate::api::TrueApiClient::new()?;
+    let (challenge, server_time) = api.auth_key().await?;
+
+    // Шаг 1.1: Сверяем часы с сервером и сроком сертификата — иначе сервер
+    // отклонит подпись с невнятной ошибкой
+    if let Some(server_time) = server_time {
+        let threshold = crate::settings::load().clock_skew_threshold_secs;
+        if let Err(e) = crate::clock::check(server_time, chrono::Utc::now(), cert, threshold) {
+            crate::storage::debug_log(&format!("🕒 Проверка времени перед входом: {}", e));
+            return Err(SignError::Clock(e));
+        }
+    }
 
-    let uuid = response.uuid;
-    let data = response.data;
+    let uuid = challenge.uuid;
+    let data = challenge.data;
 
     // Шаг 2: Сохраняем данные в временный файл `key`
     std::fs::write(&key_path, data.as_bytes())
-        .map_err(|e| format!("Не удалось записать файл {}: {}", key_path.display(), e))?;
+        .map_err(|e| SignError::File(format!("Не удалось записать файл {}: {}", key_path.display(), e)))?;
 
     // Шаг 3: Подписываем через cryptcp.exe
-    let cryptcp_path = find_cryptcp_path().map_err(|e| format!("Не найден cryptcp.exe: {}", e))?;
-
-    if !Path::new(&cryptcp_path).exists() {
-        return Err("cryptcp.exe не найден".to_string());
-    }
-
-    let thumb = cert.thumbprint.replace(":", "").replace(" ", "").to_uppercase();
-
-    let mut cmd = Command::new(&cryptcp_path);
-    cmd.arg("-sign").arg("-uMy").arg("-yes");
-
-    // Используем отпечаток (thumbprint), если есть
-    if !thumb.is_empty() {
-        cmd.arg("-thumb").arg(&thumb);
-    } else {
-        // Резерв: ищем CN в Subject
-        let cn = extract_attr(&cert.subject_name, "CN=").unwrap_or_default();
-        cmd.arg("-dn").arg(&cn);
-    }
-
-    // Указываем пути к файлам
-    cmd.arg(key_path.to_str().ok_or("Недопустимый путь к key")?)
-        .arg(sig_path.to_str().ok_or("Недопустимый путь к sig")?);
-
-    // Выполняем команду
-    let output = cmd.output().map_err(|e| format!("Ошибка выполнения cryptcp: {}", e))?;
-
-    let stderr = String::from_utf8_lossy(&output.stderr);
-    let stdout = String::from_utf8_lossy(&output.stdout);
-
-    if !output.status.success() {
-        let error = if !stderr.trim().is_empty() {
-            stderr.trim()
-        } else if !stdout.trim().is_empty() {
-            stdout.trim()
-        } else {
-            "Неизвестная ошибка при выполнении cryptcp.exe"
-        };
-        return Err(format!("Ошибка подписи: {}", error));
-    }
+    let pin = resolve_pin(cert, pin);
+    run_cryptcp_sign_async(cert, &key_path, &sig_path, SignatureKind::Attached, false, pin).await?;
 
     // Шаг 4: Читаем и очищаем подпись из key.sig
     let signature_raw = std::fs::read_to_string(&sig_path)
-        .map_err(|e| format!("Не удалось прочитать подпись: {}", e))?;
+        .map_err(|e| SignError::File(format!("Не удалось прочитать подпись: {}", e)))?;
 
     let signature_stripped = signature_raw
         .replace('\r', "")
@@ -112,82 +124,289 @@ pub async fn sign_file_with_certificate(cert: &crate::certificate::CertificateIn
         .to_string();
 
     if signature_stripped.is_empty() {
-        return Err("Подпись пустая после очистки".to_string());
+        return Err(SignError::InvalidSignature("подпись пустая после очистки".to_string()));
+    }
+
+    // Шаг 4.1: Проверяем структуру подписи до отправки на сервер
+    // (демо-подпись не является CMS — её проверяет демо-сервер)
+    let checked = if crate::demo::is_active() {
+        Ok(())
+    } else {
+        crate::verify::check_auth_signature(signature_stripped.as_bytes(), data.as_bytes(), cert)
+    };
+    if let Err(e) = checked {
+        let _ = std::fs::remove_file(&key_path);
+        let _ = std::fs::remove_file(&sig_path);
+        return Err(SignError::InvalidSignature(e.to_string()));
     }
 
     // Шаг 5: Отправляем подпись на сервер
-    let result = send_signature_confirmation(uuid, &signature_stripped).await;
+    let oms_connection = oms.map(|o| o.oms_connection.trim());
+    let result = api.sign_in(&uuid, &signature_stripped, oms_connection).await;
 
     // Шаг 6: Удаляем временные файлы
     let _ = std::fs::remove_file(&key_path);
     let _ = std::fs::remove_file(&sig_path);
 
-    result
+    // Шаг 7: Сохраняем токен вместе со сроком действия и ИНН из JWT
+    let mut stored = crate::session::describe_token(&result?, Some(&cert.thumbprint));
+    if let Some(oms) = oms {
+        crate::session::attach_oms(&mut stored, oms);
+    }
+    // Без сохранённого токена сессия недоступна выгрузке и продлению
+    crate::storage::save_token(&stored)?;
+    crate::session::take_unauthorized(&stored.key());
+
+    Ok(stored)
+}
+
+/// Вид подписи пользовательского файла
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SignatureKind {
+    /// Отсоединённая подпись (.sig), исходный файл не меняется
+    Detached,
+    /// Присоединённая подпись (.p7s), содержит сам файл
+    Attached,
 }
 
-/// Отправляет подтверждённую подпись на сервер для получения токена
-async fn send_signature_confirmation(uuid: String, clean_signature: &str) -> Result<String, String> {
-    let client = reqwest::Client::new();
+impl SignatureKind {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SignatureKind::Detached => "sig",
+            SignatureKind::Attached => "p7s",
+        }
+    }
 
-    let request_body = serde_json::json!({
-        "uuid": uuid,
-        "data": clean_signature
-    });
+    pub fn label(&self) -> &'static str {
+        match self {
+            SignatureKind::Detached => "Отсоединённая (.sig)",
+            SignatureKind::Attached => "Присоединённая (.p7s)",
+        }
+    }
+}
 
-    let response = client
-        .post("https://markirovka.crpt.ru/api/v3/true-api/auth/simpleSignIn")
-        .header("Content-Type", "application/json")
-        .header("User-Agent", "czn-dioxus/1.0")
-        .json(&request_body)
-        .send()
+/// Путь к файлу подписи рядом с исходным: doc.pdf → doc.pdf.sig
+pub fn signature_path_for(path: &Path, kind: SignatureKind) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(kind.extension());
+    PathBuf::from(name)
+}
+
+/// Подписывает произвольный файл пользователя и кладёт подпись рядом с ним
+pub async fn sign_user_file(
+    cert: &crate::certificate::CertificateInfo,
+    path: &Path,
+    kind: SignatureKind,
+    pin: Option<&str>,
+) -> Result<PathBuf, SignError> {
+    if !path.is_file() {
+        return Err(SignError::File(format!("Файл не найден: {}", path.display())));
+    }
+
+    // Прежняя подпись заменяется только готовой новой (со штампом времени, если он включён)
+    let output = signature_path_for(path, kind);
+    let partial = partial_path(&output);
+    let pin = resolve_pin(cert, pin);
+    let result = async {
+        run_cryptcp_sign_async(cert, path, &partial, kind, true, pin).await?;
+        add_timestamp_if_enabled(&partial).await?;
+        std::fs::rename(&partial, &output).map_err(|e| {
+            SignError::File(format!("Не удалось сохранить подпись {}: {}", output.display(), e))
+        })
+    }
+    .await;
+    if result.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+    result.map(|_| output)
+}
+
+/// Временный файл для новой подписи: doc.pdf.sig → doc.pdf.sig.partial
+pub(crate) fn partial_path(output: &Path) -> PathBuf {
+    let mut name = output.as_os_str().to_owned();
+    name.push(".partial");
+    PathBuf::from(name)
+}
+
+/// Дополняет подпись штампом времени, если в настройках включён CAdES-T
+pub(crate) async fn add_timestamp_if_enabled(signature: &Path) -> Result<(), SignError> {
+    let settings = crate::settings::load();
+    if !settings.cades_t {
+        return Ok(());
+    }
+    if crate::demo::is_active() {
+        crate::storage::debug_log("🎓 Демо-режим: штамп времени не запрашивается");
+        return Ok(());
+    }
+
+    crate::tsa::stamp_signature_file(signature, &settings.tsa_url)
         .await
-        .map_err(|e| format!("Ошибка сети (simpleSignIn): {}", e))?;
+        .map_err(SignError::Timestamp)
+}
 
-    if response.status().is_success() {
-        let result: SignInResponse = response
-            .json()
-            .await
-            .map_err(|e| format!("Не удалось распарсить ответ: {}", e))?;
+/// PIN для подписи: явно введённый или сохранённый в кэше сеанса
+pub(crate) fn resolve_pin(
+    cert: &crate::certificate::CertificateInfo,
+    pin: Option<&str>,
+) -> Option<Zeroizing<String>> {
+    match pin {
+        Some(pin) => Some(Zeroizing::new(pin.to_string())),
+        None => crate::pin::cached(&cert.thumbprint),
+    }
+}
 
-        // 🔽 Сохраняем токен в открытом виде
-        if let Err(e) = crate::storage::save_token(&result.token) {
-            eprintln!("⚠️ Не удалось сохранить токен: {}", e);
-        }
+/// То же, что `run_cryptcp_sign`, но в пуле блокирующих задач, чтобы не замораживать UI
+async fn run_cryptcp_sign_async(
+    cert: &crate::certificate::CertificateInfo,
+    input: &Path,
+    output: &Path,
+    kind: SignatureKind,
+    der: bool,
+    pin: Option<Zeroizing<String>>,
+) -> Result<(), SignError> {
+    let cert = cert.clone();
+    let input = input.to_path_buf();
+    let output = output.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        run_cryptcp_sign(&cert, &input, &output, kind, der, pin.as_deref().map(|p| p.as_str()))
+    })
+    .await
+    .map_err(|e| SignError::Csp(format!("подпись прервана: {}", e)))?
+}
 
-        // Запускаем выгрузку задач в фоне
-        spawn(async move {
-            match dispenser::fetch_violation_tasks().await {
-                Ok(results) => {
-                    for msg in results {
-                        eprintln!("{}", msg);
-                    }
-                }
-                Err(e) => {
-                    eprintln!("❌ Ошибка выгрузки нарушений: {}", e);
-                }
+/// Выполняет команду с ограничением по времени; по таймауту процесс завершается.
+/// Вывод читается в отдельных потоках: иначе заполненный канал остановит cryptcp.
+pub(crate) fn output_with_timeout(mut cmd: Command, timeout: Duration) -> Result<Output, SignError> {
+    cmd.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped());
+    let spawned = cmd.spawn();
+    // Аргументы (в том числе PIN) больше не нужны
+    drop(cmd);
+    let mut child = spawned.map_err(|e| SignError::CspNotFound(e.to_string()))?;
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+    let started = Instant::now();
+
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if started.elapsed() >= timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                crate::storage::debug_log(&format!(
+                    "⏱ cryptcp не завершился за {} с и был остановлен",
+                    timeout.as_secs()
+                ));
+                return Err(SignError::Timeout(timeout));
             }
-        });
+            Ok(None) => std::thread::sleep(Duration::from_millis(100)),
+            Err(e) => return Err(SignError::Csp(format!("ошибка выполнения cryptcp: {}", e))),
+        }
+    };
+
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+/// Читает канал процесса до конца в отдельном потоке
+fn drain<R: Read + Send + 'static>(pipe: Option<R>) -> std::thread::JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut buffer = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buffer);
+        }
+        buffer
+    })
+}
+
+/// Запускает cryptcp.exe для подписи `input` в `output`.
+/// `der` — писать подпись в DER вместо Base64, `pin` — PIN-код контейнера.
+pub(crate) fn run_cryptcp_sign(
+    cert: &crate::certificate::CertificateInfo,
+    input: &Path,
+    output: &Path,
+    kind: SignatureKind,
+    der: bool,
+    pin: Option<&str>,
+) -> Result<(), SignError> {
+    if crate::demo::is_active() {
+        return crate::demo::sign_file(cert, input, output, kind).map_err(SignError::File);
+    }
+
+    let cryptcp_path = find_cryptcp_path().map_err(|e| SignError::CspNotFound(e.to_string()))?;
+
+    if !Path::new(&cryptcp_path).exists() {
+        return Err(SignError::CspNotFound(cryptcp_path));
+    }
+
+    let thumb = cert.thumbprint.replace(":", "").replace(" ", "").to_uppercase();
 
-        Ok("Авторизация успешна. Выгрузка запрошена.".to_string())
+    let mut cmd = Command::new(&cryptcp_path);
+    cmd.arg("-sign").arg("-uMy").arg("-yes");
+
+    if kind == SignatureKind::Detached {
+        cmd.arg("-detached");
+    }
+    if der {
+        cmd.arg("-der");
+    }
+    // Копия PIN в аргументах живёт до запуска процесса: `output_with_timeout`
+    // сразу после него освобождает команду. Свои копии держим в Zeroizing.
+    if let Some(pin) = pin {
+        cmd.arg("-pin").arg(pin);
+    }
+
+    // Используем отпечаток (thumbprint), если есть
+    if !thumb.is_empty() {
+        cmd.arg("-thumb").arg(&thumb);
     } else {
-        let status = response.status();
-        let err_text = response
-            .text()
-            .await
-            .unwrap_or_else(|_| "Неизвестная ошибка".to_string());
+        // Резерв: ищем CN в Subject
+        let cn = extract_attr(&cert.subject_name, "CN=").unwrap_or_default();
+        cmd.arg("-dn").arg(&cn);
+    }
+
+    // Указываем пути к файлам
+    let input = input
+        .to_str()
+        .ok_or_else(|| SignError::File("Недопустимый путь к исходному файлу".to_string()))?;
+    let output = output
+        .to_str()
+        .ok_or_else(|| SignError::File("Недопустимый путь к файлу подписи".to_string()))?;
+    cmd.arg(input).arg(output);
 
-        Err(format!("Ошибка сервера: {} — {}", status, err_text.trim()))
+    // Выполняем команду
+    let result = output_with_timeout(cmd, CRYPTCP_TIMEOUT)?;
+
+    let stderr = decode_console(&result.stderr);
+    let stdout = decode_console(&result.stdout);
+
+    if !result.status.success() {
+        if let Some(failure) = crate::pin::classify(&format!("{}\n{}", stdout, stderr)) {
+            // Неверный или заблокированный PIN больше не пробуем
+            if failure != crate::pin::PinFailure::Required {
+                crate::pin::forget(&cert.thumbprint);
+            }
+            return Err(SignError::Pin(failure));
+        }
+
+        let error = if !stderr.trim().is_empty() {
+            stderr.trim()
+        } else if !stdout.trim().is_empty() {
+            stdout.trim()
+        } else {
+            "Неизвестная ошибка при выполнении cryptcp.exe"
+        };
+        return Err(SignError::Csp(error.to_string()));
     }
-}
 
-/// Загружает токен из файла
-/// Используется в dispenser.rs для авторизации при запросах
-pub fn load_auth_token() -> Result<String, String> {
-    crate::storage::load_token()
+    Ok(())
 }
 
 /// Ищет путь к утилите cryptcp.exe (КриптоПро)
-fn find_cryptcp_path() -> Result<String, &'static str> {
+pub(crate) fn find_cryptcp_path() -> Result<String, &'static str> {
     // Сначала — переменная окружения
     if let Ok(path) = std::env::var("CRYPTCP_PATH") {
         if Path::new(&path).exists() {
@@ -210,6 +429,14 @@ fn find_cryptcp_path() -> Result<String, &'static str> {
     Err("cryptcp.exe не найден")
 }
 
+/// Декодирует вывод консольной утилиты: UTF-8 или CP866 (консоль Windows)
+pub(crate) fn decode_console(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => encoding_rs::IBM866.decode(bytes).0.into_owned(),
+    }
+}
+
 /// Удобная функция для извлечения атрибута (например, INN, CN)
 pub fn attr_value(dn: &str, prefix: &str) -> String {
     extract_attr(dn, prefix).unwrap_or_default()
@@ -3,15 +3,20 @@
 use std::path::{Path, PathBuf};
 use std::env;
 use std::fs;
+use std::sync::Mutex;
+use std::time::SystemTime;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use crate::error::StorageError;
 
 /// Возвращает базовую директорию:
 /// - Windows: %APPDATA%\czn-dioxus
 /// - Linux/macOS: ~/.czn
-pub fn base_dir() -> Result<PathBuf, String> {
+pub fn base_dir() -> Result<PathBuf, StorageError> {
     #[cfg(windows)]
     {
         let appdata = env::var("APPDATA")
-            .map_err(|_| "Переменная окружения APPDATA не найдена")?;
+            .map_err(|_| StorageError::Io("переменная окружения APPDATA не найдена".to_string()))?;
         let mut path = PathBuf::from(appdata);
         path.push("czn-dioxus");
         return Ok(path);
@@ -20,78 +25,476 @@ pub fn base_dir() -> Result<PathBuf, String> {
     // Linux/macOS
     let home = env::var("HOME")
         .or_else(|_| env::var("USERPROFILE"))
-        .map_err(|_| "Не удалось определить домашнюю директорию")?;
+        .map_err(|_| StorageError::Io("не удалось определить домашнюю директорию".to_string()))?;
     let mut path = PathBuf::from(home);
     path.push(".czn");
     Ok(path)
 }
 
 /// Создаёт директорию приложения, если её нет
-pub fn ensure_czn_dir() -> Result<PathBuf, String> {
+pub fn ensure_czn_dir() -> Result<PathBuf, StorageError> {
     let path = base_dir()?;
     if let Err(e) = fs::create_dir_all(&path) {
-        return Err(format!("Не удалось создать директорию {}: {}", path.display(), e));
+        return Err(StorageError::Io(format!(
+            "не удалось создать директорию {}: {}",
+            path.display(),
+            e
+        )));
     }
     Ok(path)
 }
 
 /// Путь к временному файлу данных для подписи
-pub fn key_path() -> Result<PathBuf, String> {
+pub fn key_path() -> Result<PathBuf, StorageError> {
     let mut path = base_dir()?;
     path.push("key");
     Ok(path)
 }
 
 /// Путь к файлу подписи
-pub fn sig_path() -> Result<PathBuf, String> {
+pub fn sig_path() -> Result<PathBuf, StorageError> {
     let mut path = base_dir()?;
     path.push("key.sig");
     Ok(path)
 }
 
-/// Путь к файлу с токеном
-pub fn token_path() -> Result<PathBuf, String> {
+/// Путь к файлу с токеном прежних версий (одна сессия)
+pub fn token_path() -> Result<PathBuf, StorageError> {
     let mut path = base_dir()?;
     path.push("token.dat");
     Ok(path)
 }
 
+/// Путь к файлу настроек
+pub fn settings_path() -> Result<PathBuf, StorageError> {
+    let mut path = base_dir()?;
+    path.push("settings.json");
+    Ok(path)
+}
+
 /// Путь к лог-файлу
-pub fn log_path() -> Result<PathBuf, String> {
+pub fn log_path() -> Result<PathBuf, StorageError> {
     let mut path = base_dir()?;
     path.push("debug.log");
     Ok(path)
 }
 
+/// Пишет строку в лог-файл (в фоновом потоке, ошибки игнорируются)
+pub fn debug_log(msg: &str) {
+    let msg = msg.to_string();
+    std::thread::spawn(move || {
+        use std::io::Write;
+
+        let log_path = match log_path() {
+            Ok(p) => p,
+            Err(_) => return,
+        };
+
+        let _ = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(log_path)
+            .and_then(|mut file| {
+                let timestamp = chrono::Local::now().format("[%Y-%m-%d %H:%M:%S]").to_string();
+                writeln!(file, "{} {}", timestamp, msg)
+            });
+    });
+}
+
+/// Перезаписывает файл нулями и удаляет его
+fn shred_file(path: &Path) -> Result<(), StorageError> {
+    use std::io::Write;
+
+    if let Ok(meta) = fs::metadata(path) {
+        let overwritten = fs::OpenOptions::new().write(true).open(path).and_then(|mut file| {
+            file.write_all(&vec![0u8; meta.len() as usize])?;
+            file.sync_all()
+        });
+        if let Err(e) = overwritten {
+            eprintln!("⚠️ Не удалось перезаписать {}: {}", path.display(), e);
+        }
+    }
+
+    match fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(StorageError::Io(format!("не удалось удалить {}: {}", path.display(), e))),
+    }
+}
+
+/// Перезаписывает и удаляет все файлы директории, затем саму директорию
+fn shred_dir(path: &Path) -> Result<(), StorageError> {
+    let Ok(entries) = fs::read_dir(path) else {
+        return Ok(());
+    };
+    for entry in entries.flatten() {
+        let entry_path = entry.path();
+        if entry_path.is_dir() {
+            shred_dir(&entry_path)?;
+        } else {
+            shred_file(&entry_path)?;
+        }
+    }
+    fs::remove_dir(path)
+        .map_err(|e| StorageError::Io(format!("не удалось удалить {}: {}", path.display(), e)))
+}
+
 /// Удаляет временные файлы
-pub fn cleanup_temp_files() -> Result<(), String> {
+pub fn cleanup_temp_files() -> Result<(), StorageError> {
     let _ = fs::remove_file(key_path().unwrap_or_default());
     let _ = fs::remove_file(sig_path().unwrap_or_default());
     Ok(())
 }
 
-/// Сохраняет токен в открытом виде
-pub fn save_token(token: &str) -> Result<(), String> {
-    let path = token_path()?;
-    fs::write(&path, token.trim().as_bytes())
-        .map_err(|e| format!("Не удалось записать токен: {}", e))
+/// Сохранённый токен вместе с метаданными из JWT
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct StoredToken {
+    pub token: String,
+    /// Время выдачи (Unix, секунды)
+    pub issued_at: Option<i64>,
+    /// Время истечения (Unix, секунды)
+    pub expires_at: Option<i64>,
+    /// ИНН организации из токена
+    pub inn: Option<String>,
+    /// Отпечаток сертификата, которым выполнен вход
+    pub thumbprint: Option<String>,
+    /// Подключение СУЗ, если это токен СУЗ, а не True API
+    pub oms_connection: Option<String>,
+    /// Идентификатор СУЗ для токена СУЗ
+    pub oms_id: Option<String>,
 }
 
-/// Загружает токен из файла
-pub fn load_token() -> Result<String, String> {
-    let path = token_path()?;
-    if !path.exists() {
-        return Err("Токен не найден".to_string());
+impl StoredToken {
+    /// Ключ сессии: ИНН организации + отпечаток сертификата
+    /// (+ подключение для токенов СУЗ). Служит именем файла, а ИНН берётся
+    /// из непроверенного JWT, поэтому в ключе остаются только [0-9A-Za-z_-].
+    pub fn key(&self) -> String {
+        let inn = self.inn.as_deref().unwrap_or("unknown");
+        let thumbprint = self
+            .thumbprint
+            .as_deref()
+            .map(|t| t.replace([':', ' '], "").to_uppercase())
+            .unwrap_or_else(|| "unknown".to_string());
+        let key = match &self.oms_connection {
+            Some(connection) => format!("{}_{}_oms_{}", inn, thumbprint, connection),
+            None => format!("{}_{}", inn, thumbprint),
+        };
+        key.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect()
+    }
+
+    /// Токен СУЗ (не подходит для запросов True API)
+    pub fn is_oms(&self) -> bool {
+        self.oms_connection.is_some()
     }
+}
+
+/// Директория с токенами сессий (по файлу на организацию и сертификат).
+/// У каждого контура своя директория: токен песочницы не подходит для промышленного.
+pub fn tokens_dir() -> Result<PathBuf, StorageError> {
+    let mut path = base_dir()?;
+    match crate::settings::environment() {
+        crate::settings::Environment::Production => path.push("tokens"),
+        env => path.push(format!("tokens-{}", env.slug())),
+    }
+    Ok(path)
+}
 
-    fs::read_to_string(&path)
-        .map_err(|e| format!("Не удалось прочитать токен: {}", e))
-        .and_then(|s| {
-            let trimmed = s.trim().to_string();
-            if trimmed.is_empty() {
-                Err("Токен пуст".to_string())
-            } else {
-                Ok(trimmed)
+/// Файл реестра задач выгрузки; как и токены, у каждого контура свой
+pub fn tasks_path() -> Result<PathBuf, StorageError> {
+    let mut path = ensure_czn_dir()?;
+    match crate::settings::environment() {
+        crate::settings::Environment::Production => path.push("tasks.json"),
+        env => path.push(format!("tasks-{}.json", env.slug())),
+    }
+    Ok(path)
+}
+
+/// Путь к файлу токена сессии
+fn session_path(key: &str) -> Result<PathBuf, StorageError> {
+    let mut path = tokens_dir()?;
+    path.push(format!("{}.dat", key));
+    Ok(path)
+}
+
+/// Путь к файлу с ключом выбранной сессии
+fn active_session_path() -> Result<PathBuf, StorageError> {
+    let mut path = base_dir()?;
+    path.push("active_session");
+    Ok(path)
+}
+
+/// Записывает токен в файл в зашифрованном виде (см. `secure`)
+fn write_token_file(path: &Path, token: &StoredToken) -> Result<(), StorageError> {
+    if crate::secure::passphrase_required() {
+        return Err(StorageError::PassphraseRequired);
+    }
+    let json = zeroize::Zeroizing::new(
+        serde_json::to_vec(token).map_err(|e| StorageError::Corrupted(e.to_string()))?,
+    );
+    let encrypted = crate::secure::encrypt(&json).map_err(StorageError::Crypto)?;
+    fs::write(path, encrypted)
+        .map_err(|e| StorageError::Io(format!("не удалось записать токен: {}", e)))
+}
+
+/// Читает файл токена. Второй элемент — признак старого открытого формата
+/// (JSON или только токен), который нужно перезаписать зашифрованным.
+fn read_token_file(path: &Path) -> Result<(StoredToken, bool), StorageError> {
+    let data = fs::read(path)
+        .map_err(|e| StorageError::Io(format!("не удалось прочитать токен: {}", e)))?;
+
+    if crate::secure::is_encrypted(&data) {
+        let plain = zeroize::Zeroizing::new(
+            crate::secure::decrypt(&data).map_err(StorageError::Crypto)?,
+        );
+        return match serde_json::from_slice::<StoredToken>(&plain) {
+            Ok(stored) if !stored.token.is_empty() => Ok((stored, false)),
+            Ok(_) => Err(StorageError::Empty),
+            Err(e) => Err(StorageError::Corrupted(e.to_string())),
+        };
+    }
+
+    let content = String::from_utf8_lossy(&data);
+    let trimmed = content.trim();
+    if trimmed.is_empty() {
+        return Err(StorageError::Empty);
+    }
+
+    match serde_json::from_str::<StoredToken>(trimmed) {
+        Ok(stored) if !stored.token.is_empty() => Ok((stored, true)),
+        Ok(_) => Err(StorageError::Empty),
+        Err(_) => Ok((crate::session::describe_token(trimmed, None), true)),
+    }
+}
+
+/// Переносит единственный token.dat прежних версий в директорию сессий
+/// и делает его выбранной сессией
+fn migrate_legacy_token() {
+    let Ok(legacy) = token_path() else {
+        return;
+    };
+    if !legacy.exists() {
+        return;
+    }
+
+    match read_token_file(&legacy) {
+        Ok((stored, _)) => match save_token(&stored) {
+            Ok(()) => {
+                if active_session_key().is_none() {
+                    let _ = set_active_session(&stored.key());
+                }
+                let _ = fs::remove_file(&legacy);
+            }
+            Err(e) => eprintln!("⚠️ Не удалось перенести сохранённый токен: {}", e),
+        },
+        // Ошибка может быть временной (нет ключа шифрования), поэтому
+        // единственный токен не удаляется, а откладывается в сторону
+        Err(e) => {
+            let backup = legacy.with_extension("dat.bak");
+            eprintln!(
+                "⚠️ Не удалось прочитать сохранённый токен {}: {}. Файл сохранён как {}",
+                legacy.display(),
+                e,
+                backup.display()
+            );
+            if let Err(e) = fs::rename(&legacy, &backup) {
+                eprintln!("⚠️ Не удалось переименовать {}: {}", legacy.display(), e);
             }
+        }
+    }
+}
+
+/// Сохраняет токен сессии; сессия того же ИНН и сертификата перезаписывается
+pub fn save_token(token: &StoredToken) -> Result<(), StorageError> {
+    let dir = tokens_dir()?;
+    fs::create_dir_all(&dir).map_err(|e| {
+        StorageError::Io(format!("не удалось создать директорию {}: {}", dir.display(), e))
+    })?;
+    let result = write_token_file(&session_path(&token.key())?, token);
+    invalidate_sessions();
+    result
+}
+
+/// Загружает токен сессии по ключу. Файл старого открытого формата
+/// сразу перезаписывается в зашифрованном виде.
+pub fn load_session(key: &str) -> Result<StoredToken, StorageError> {
+    let path = session_path(key)?;
+    if !path.exists() {
+        return Err(StorageError::NotFound);
+    }
+
+    let (stored, legacy) = read_token_file(&path)?;
+    if legacy {
+        if let Err(e) = write_token_file(&path, &stored) {
+            eprintln!("⚠️ Не удалось зашифровать сохранённый токен: {}", e);
+        }
+    }
+    Ok(stored)
+}
+
+/// Снимок директории токенов: имя, размер и время изменения каждого файла
+type DirSnapshot = Vec<(String, u64, Option<SystemTime>)>;
+
+/// Расшифрованные сессии и снимок директории, из которой они прочитаны.
+/// Расшифровка (DPAPI, связка ключей, PBKDF2) дорогая, а список запрашивается
+/// часто — файлы перечитываются только при изменении директории.
+struct SessionCache {
+    dir: PathBuf,
+    snapshot: DirSnapshot,
+    sessions: Vec<StoredToken>,
+}
+
+static SESSION_CACHE: Lazy<Mutex<Option<SessionCache>>> = Lazy::new(|| Mutex::new(None));
+
+/// Сбрасывает кэш сессий (после входа, выхода и очистки)
+pub(crate) fn invalidate_sessions() {
+    *SESSION_CACHE.lock().unwrap() = None;
+}
+
+fn snapshot_dir(dir: &Path) -> DirSnapshot {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut snapshot: DirSnapshot = entries
+        .flatten()
+        .filter_map(|entry| {
+            let meta = entry.metadata().ok()?;
+            Some((entry.file_name().to_string_lossy().to_string(), meta.len(), meta.modified().ok()))
         })
+        .collect();
+    snapshot.sort();
+    snapshot
+}
+
+/// Все сохранённые сессии, упорядоченные по ИНН
+pub fn list_sessions() -> Vec<StoredToken> {
+    let Ok(dir) = tokens_dir() else {
+        return Vec::new();
+    };
+    {
+        let cache = SESSION_CACHE.lock().unwrap();
+        if let Some(cache) = cache.as_ref() {
+            if cache.dir == dir && cache.snapshot == snapshot_dir(&dir) {
+                return cache.sessions.clone();
+            }
+        }
+    }
+
+    migrate_legacy_token();
+    // Снимок берётся до чтения: изменение во время чтения вызовет повторное
+    let snapshot = snapshot_dir(&dir);
+    let mut sessions: Vec<StoredToken> = snapshot
+        .iter()
+        .filter_map(|(name, _, _)| name.strip_suffix(".dat"))
+        .filter_map(|key| load_session(key).ok())
+        .collect();
+    sessions.sort_by(|a, b| a.inn.cmp(&b.inn).then(a.thumbprint.cmp(&b.thumbprint)));
+
+    *SESSION_CACHE.lock().unwrap() = Some(SessionCache {
+        dir,
+        snapshot,
+        sessions: sessions.clone(),
+    });
+    sessions
+}
+
+/// Ключ выбранной в интерфейсе сессии
+pub fn active_session_key() -> Option<String> {
+    let key = fs::read_to_string(active_session_path().ok()?).ok()?;
+    let key = key.trim();
+    (!key.is_empty()).then(|| key.to_string())
+}
+
+/// Делает сессию выбранной
+pub fn set_active_session(key: &str) -> Result<(), StorageError> {
+    fs::write(active_session_path()?, key)
+        .map_err(|e| StorageError::Io(format!("не удалось сохранить выбранную сессию: {}", e)))
+}
+
+/// Загружает токен выбранной сессии (если выбранной нет — первой сохранённой)
+pub fn load_stored_token() -> Result<StoredToken, StorageError> {
+    let sessions = list_sessions();
+    let active = active_session_key();
+
+    sessions
+        .iter()
+        .find(|s| Some(s.key()) == active)
+        .or(sessions.first())
+        .cloned()
+        .ok_or(StorageError::NotFound)
+}
+
+/// Выход из сессии: файл токена перезаписывается и удаляется
+pub fn delete_session(key: &str) -> Result<(), StorageError> {
+    invalidate_sessions();
+    shred_file(&session_path(key)?)?;
+    if active_session_key().as_deref() == Some(key) {
+        shred_file(&active_session_path()?)?;
+    }
+    Ok(())
+}
+
+/// Удаляет все учётные данные и следы работы: токены всех контуров,
+/// выбранную сессию, временные файлы подписи, файлы проверки подписей, лог,
+/// кэш отзыва сертификатов и ключ шифрования в Secret Service.
+/// Файлы перед удалением перезаписываются. Настройки сохраняются, кроме пароля прокси:
+/// он зашифрован тем же ключом.
+pub fn forget_everything() -> Result<(), StorageError> {
+    invalidate_sessions();
+    let base = base_dir()?;
+    let mut errors = Vec::new();
+
+    if let Ok(entries) = fs::read_dir(&base) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+            if path.is_dir() && (name.starts_with("tokens") || name == "verify") {
+                if let Err(e) = shred_dir(&path) {
+                    errors.push(summary_text(e));
+                }
+            } else if path.is_file() && name.starts_with("tasks") && name.contains(".json") {
+                // Реестр задач выгрузки (и его резервные копии)
+                if let Err(e) = shred_file(&path) {
+                    errors.push(summary_text(e));
+                }
+            }
+        }
+    }
+
+    let legacy_backup = token_path().map(|p| p.with_extension("dat.bak"));
+    let files = [token_path(), legacy_backup, active_session_path(), key_path(), sig_path(), log_path()];
+    for path in files.into_iter().flatten() {
+        if let Err(e) = shred_file(&path) {
+            errors.push(summary_text(e));
+        }
+    }
+
+    let mut settings = crate::settings::load();
+    if !settings.proxy.password_protected.is_empty() {
+        settings.proxy.password_protected.clear();
+        if let Err(e) = crate::settings::save(&settings) {
+            errors.push(e);
+        }
+    }
+    if let Err(e) = crate::secure::forget_keys() {
+        errors.push(e);
+    }
+    if let Err(e) = crate::verify::clear_revocation_cache() {
+        errors.push(e.to_string());
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(StorageError::Io(errors.join("; ")))
+    }
+}
+
+/// Текст ошибки для общей сводки: без префикса, который добавит итоговая ошибка
+fn summary_text(e: StorageError) -> String {
+    match e {
+        StorageError::Io(e) => e,
+        other => other.to_string(),
+    }
 }
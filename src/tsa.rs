@@ -0,0 +1,407 @@
+// src/tsa.rs
+
+//! Штампы времени RFC 3161 и усовершенствование подписи до CAdES-T.
+
+use crate::asn1::{self, Tlv, TAG_BOOLEAN, TAG_INTEGER, TAG_NULL, TAG_OCTET_STRING, TAG_SEQUENCE, TAG_SET};
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// id-aa-signatureTimeStampToken — неподписанный атрибут со штампом времени
+pub const OID_TIMESTAMP_TOKEN: &str = "1.2.840.113549.1.9.16.2.14";
+const OID_SHA256: &str = "2.16.840.1.101.3.4.2.1";
+
+const TAG_UNSIGNED_ATTRS: u8 = asn1::context(1);
+
+/// Содержимое TSTInfo, нужное для проверки
+#[derive(Clone, Debug, PartialEq)]
+pub struct TstInfo {
+    pub hash_oid: String,
+    pub imprint: Vec<u8>,
+    pub gen_time: Option<DateTime<Utc>>,
+    pub nonce: Option<Vec<u8>>,
+    pub tsa_name: String,
+}
+
+/// Вычисляет хэш по OID алгоритма (поддерживаются SHA-2)
+pub fn digest(oid: &str, data: &[u8]) -> Option<Vec<u8>> {
+    match oid {
+        "2.16.840.1.101.3.4.2.1" => Some(Sha256::digest(data).to_vec()),
+        "2.16.840.1.101.3.4.2.2" => Some(Sha384::digest(data).to_vec()),
+        "2.16.840.1.101.3.4.2.3" => Some(Sha512::digest(data).to_vec()),
+        _ => None,
+    }
+}
+
+fn trim_zeros(bytes: &[u8]) -> &[u8] {
+    let start = bytes.iter().position(|&b| b != 0).unwrap_or(bytes.len());
+    &bytes[start..]
+}
+
+fn make_nonce() -> [u8; 8] {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    let mut bytes = (nanos as u64).to_be_bytes();
+    bytes[0] &= 0x7F;
+    bytes
+}
+
+/// Собирает TimeStampReq с хэшем SHA-256 и запросом сертификата TSA
+fn build_request(imprint: &[u8], nonce: &[u8]) -> Vec<u8> {
+    let algorithm = asn1::encode_parts(
+        TAG_SEQUENCE,
+        &[&asn1::encode_oid(OID_SHA256), &asn1::encode(TAG_NULL, &[])],
+    );
+    let message_imprint = asn1::encode_parts(
+        TAG_SEQUENCE,
+        &[&algorithm, &asn1::encode(TAG_OCTET_STRING, imprint)],
+    );
+    asn1::encode_parts(
+        TAG_SEQUENCE,
+        &[
+            &asn1::encode_unsigned(&[1]),
+            &message_imprint,
+            &asn1::encode_unsigned(nonce),
+            &asn1::encode(TAG_BOOLEAN, &[0xFF]),
+        ],
+    )
+}
+
+/// Извлекает TimeStampToken из TimeStampResp
+fn parse_response(body: &[u8]) -> Result<Vec<u8>, String> {
+    let (resp, _) = asn1::read_tlv(body)?;
+    let parts = resp.expect(TAG_SEQUENCE)?.children()?;
+    let status_info = parts
+        .first()
+        .ok_or("Пустой ответ TSA")?
+        .expect(TAG_SEQUENCE)?
+        .children()?;
+    let status = status_info
+        .first()
+        .ok_or("Ответ TSA без статуса")?
+        .expect(TAG_INTEGER)?
+        .content
+        .last()
+        .copied()
+        .unwrap_or(0xFF);
+
+    // 0 — granted, 1 — grantedWithMods
+    if status > 1 {
+        let text = status_info
+            .get(1)
+            .and_then(|s| s.children().ok())
+            .and_then(|s| s.first().map(asn1::decode_string))
+            .unwrap_or_default();
+        return Err(format!("TSA отклонила запрос (статус {}): {}", status, text));
+    }
+
+    parts
+        .get(1)
+        .map(|token| token.raw.to_vec())
+        .ok_or_else(|| "Ответ TSA не содержит штампа времени".to_string())
+}
+
+/// Разбирает TimeStampToken: TSTInfo и имя службы из сертификата подписанта
+pub fn parse_token(token: &[u8]) -> Result<TstInfo, String> {
+    let cms = crate::verify::inspect_signature(token).map_err(|e| e.to_string())?;
+    let content = cms.content.ok_or("Штамп времени не содержит TSTInfo")?;
+
+    let (tst, _) = asn1::read_tlv(&content)?;
+    let fields = tst.expect(TAG_SEQUENCE)?.children()?;
+    let imprint = fields
+        .get(2)
+        .ok_or("Неполная структура TSTInfo")?
+        .expect(TAG_SEQUENCE)?
+        .children()?;
+    let hash_oid = match imprint.first() {
+        Some(alg) => match alg.children()?.first() {
+            Some(oid) => asn1::oid(oid)?,
+            None => String::new(),
+        },
+        None => String::new(),
+    };
+    let hashed = imprint
+        .get(1)
+        .ok_or("Штамп времени без отпечатка данных")?
+        .expect(TAG_OCTET_STRING)?
+        .content
+        .to_vec();
+
+    let tsa_name = cms
+        .signers
+        .first()
+        .and_then(|s| s.certificate.as_ref())
+        .map(|c| c.subject_name.clone())
+        .unwrap_or_default();
+
+    Ok(TstInfo {
+        hash_oid,
+        imprint: hashed,
+        gen_time: fields.get(4).and_then(asn1::decode_time),
+        nonce: fields
+            .iter()
+            .skip(5)
+            .find(|t| t.tag == TAG_INTEGER)
+            .map(|t| t.content.to_vec()),
+        tsa_name,
+    })
+}
+
+/// Запрашивает штамп времени на данные; возвращает TimeStampToken (DER)
+pub async fn request_timestamp(tsa_url: &str, data: &[u8]) -> Result<Vec<u8>, String> {
+    let imprint = Sha256::digest(data).to_vec();
+    let nonce = make_nonce();
+    let request = build_request(&imprint, &nonce);
+
+    // Через клиент API: повторы после сбоев сети и запись обмена в журнал
+    let client = crate::api::TrueApiClient::external().map_err(|e| e.to_string())?;
+    let body = client
+        .post_binary(tsa_url, "application/timestamp-query", &request)
+        .await
+        .map_err(|e| format!("Служба штампов времени: {}", e))?;
+
+    let token = parse_response(&body)?;
+    let info = parse_token(&token)?;
+
+    if info.imprint != imprint {
+        return Err("Штамп времени выдан на другие данные".to_string());
+    }
+    if info.nonce.as_deref().map(trim_zeros) != Some(trim_zeros(&nonce)) {
+        return Err("Nonce в ответе TSA не совпадает с запросом".to_string());
+    }
+
+    Ok(token)
+}
+
+/// Находит элементы SignedData внутри ContentInfo
+fn open_signed_data(der: &[u8]) -> Result<(Tlv<'_>, Vec<Tlv<'_>>), String> {
+    let (content_info, _) = asn1::read_tlv(der)?;
+    let parts = content_info.expect(TAG_SEQUENCE)?.children()?;
+    let content_type = *parts.first().ok_or("Пустой ContentInfo")?;
+    let signed_data = parts
+        .get(1)
+        .ok_or("ContentInfo без содержимого")?
+        .expect(asn1::context(0))?
+        .children()?
+        .into_iter()
+        .next()
+        .ok_or("Пустой блок SignedData")?
+        .expect(TAG_SEQUENCE)?;
+    Ok((content_type, signed_data.children()?))
+}
+
+/// Индекс signerInfos — последнего SET в SignedData
+fn signer_infos_index(items: &[Tlv]) -> Result<usize, String> {
+    items
+        .iter()
+        .rposition(|t| t.tag == TAG_SET)
+        .filter(|&idx| idx >= 3)
+        .ok_or_else(|| "В подписи нет signerInfos".to_string())
+}
+
+/// Значение подписи (последняя OCTET STRING в SignerInfo)
+pub fn signature_value<'a>(signer_fields: &[Tlv<'a>]) -> Option<&'a [u8]> {
+    signer_fields
+        .iter()
+        .rev()
+        .find(|f| f.tag == TAG_OCTET_STRING)
+        .map(|f| f.content)
+}
+
+/// Встраивает штампы времени как неподписанные атрибуты — по одному на подписанта
+fn embed_tokens(der: &[u8], tokens: &[Vec<u8>]) -> Result<Vec<u8>, String> {
+    let (content_type, items) = open_signed_data(der)?;
+    let idx = signer_infos_index(&items)?;
+    let signers = items[idx].children()?;
+
+    if signers.len() != tokens.len() {
+        return Err("Число штампов не совпадает с числом подписантов".to_string());
+    }
+
+    let mut new_signers = Vec::new();
+    for (signer, token) in signers.iter().zip(tokens) {
+        let fields = signer.children()?;
+        let attribute = asn1::encode_parts(
+            TAG_SEQUENCE,
+            &[&asn1::encode_oid(OID_TIMESTAMP_TOKEN), &asn1::encode(TAG_SET, token)],
+        );
+
+        let mut parts: Vec<Vec<u8>> = fields.iter().map(|f| f.raw.to_vec()).collect();
+        match fields.iter().position(|f| f.tag == TAG_UNSIGNED_ATTRS) {
+            Some(i) => {
+                let mut content = fields[i].content.to_vec();
+                content.extend(attribute);
+                parts[i] = asn1::encode(TAG_UNSIGNED_ATTRS, &content);
+            }
+            None => parts.push(asn1::encode(TAG_UNSIGNED_ATTRS, &attribute)),
+        }
+        new_signers.push(asn1::encode(TAG_SEQUENCE, &parts.concat()));
+    }
+
+    let mut signed_parts: Vec<Vec<u8>> = items.iter().map(|t| t.raw.to_vec()).collect();
+    signed_parts[idx] = asn1::encode(TAG_SET, &new_signers.concat());
+    let signed_data = asn1::encode(TAG_SEQUENCE, &signed_parts.concat());
+
+    Ok(asn1::encode_parts(
+        TAG_SEQUENCE,
+        &[content_type.raw, &asn1::encode(asn1::context(0), &signed_data)],
+    ))
+}
+
+/// Дополняет файл подписи штампами времени (CAdES-BES → CAdES-T).
+/// Результат записывается в DER поверх исходного файла.
+pub async fn stamp_signature_file(path: &Path, tsa_url: &str) -> Result<(), String> {
+    if tsa_url.trim().is_empty() {
+        return Err("Не задан адрес службы штампов времени".to_string());
+    }
+
+    let raw = std::fs::read(path)
+        .map_err(|e| format!("Не удалось прочитать подпись {}: {}", path.display(), e))?;
+    let der = asn1::to_der(&raw)?;
+
+    let values: Vec<Vec<u8>> = {
+        let (_, items) = open_signed_data(&der)?;
+        let idx = signer_infos_index(&items)?;
+        let mut values = Vec::new();
+        for signer in items[idx].children()? {
+            let fields = signer.children()?;
+            let value = signature_value(&fields).ok_or("SignerInfo без значения подписи")?;
+            values.push(value.to_vec());
+        }
+        values
+    };
+
+    let mut tokens = Vec::new();
+    for value in &values {
+        tokens.push(request_timestamp(tsa_url.trim(), value).await?);
+    }
+
+    let stamped = embed_tokens(&der, &tokens)?;
+    std::fs::write(path, stamped)
+        .map_err(|e| format!("Не удалось записать подпись {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asn1::{TAG_GENERALIZED_TIME, TAG_OID};
+
+    const OID_TST_INFO: &str = "1.2.840.113549.1.9.16.1.4";
+    const OID_SIGNED_DATA: &str = "1.2.840.113549.1.7.2";
+
+    /// TimeStampToken без подписантов: SignedData с TSTInfo внутри
+    fn fake_token(imprint: &[u8], nonce: &[u8]) -> Vec<u8> {
+        let algorithm = asn1::encode_parts(
+            TAG_SEQUENCE,
+            &[&asn1::encode_oid(OID_SHA256), &asn1::encode(TAG_NULL, &[])],
+        );
+        let message_imprint = asn1::encode_parts(
+            TAG_SEQUENCE,
+            &[&algorithm, &asn1::encode(TAG_OCTET_STRING, imprint)],
+        );
+        let tst_info = asn1::encode_parts(
+            TAG_SEQUENCE,
+            &[
+                &asn1::encode_unsigned(&[1]),
+                &asn1::encode_oid("1.2.3.4"),
+                &message_imprint,
+                &asn1::encode_unsigned(&[0x2A]),
+                &asn1::encode(TAG_GENERALIZED_TIME, b"20240102030405Z"),
+                &asn1::encode_unsigned(nonce),
+            ],
+        );
+        let encap = asn1::encode_parts(
+            TAG_SEQUENCE,
+            &[
+                &asn1::encode_oid(OID_TST_INFO),
+                &asn1::encode(asn1::context(0), &asn1::encode(TAG_OCTET_STRING, &tst_info)),
+            ],
+        );
+        let signed_data = asn1::encode_parts(
+            TAG_SEQUENCE,
+            &[
+                &asn1::encode_unsigned(&[3]),
+                &asn1::encode(TAG_SET, &[]),
+                &encap,
+                &asn1::encode(TAG_SET, &[]),
+            ],
+        );
+        asn1::encode_parts(
+            TAG_SEQUENCE,
+            &[
+                &asn1::encode_oid(OID_SIGNED_DATA),
+                &asn1::encode(asn1::context(0), &signed_data),
+            ],
+        )
+    }
+
+    fn fake_response(status: u8, token: Option<&[u8]>) -> Vec<u8> {
+        let mut status_info = vec![asn1::encode_unsigned(&[status])];
+        if status > 1 {
+            status_info.push(asn1::encode(TAG_SEQUENCE, &asn1::encode(0x0C, b"bad request")));
+        }
+        let status_refs: Vec<&[u8]> = status_info.iter().map(|p| p.as_slice()).collect();
+        let status_info = asn1::encode_parts(TAG_SEQUENCE, &status_refs);
+        match token {
+            Some(token) => asn1::encode_parts(TAG_SEQUENCE, &[&status_info, token]),
+            None => asn1::encode(TAG_SEQUENCE, &status_info),
+        }
+    }
+
+    #[test]
+    fn request_contains_imprint_and_nonce() {
+        let imprint = Sha256::digest(b"data").to_vec();
+        let request = build_request(&imprint, &[0x01, 0x02]);
+        let (req, rest) = asn1::read_tlv(&request).unwrap();
+        assert!(rest.is_empty());
+        let fields = req.expect(TAG_SEQUENCE).unwrap().children().unwrap();
+        assert_eq!(fields.len(), 4);
+        let message_imprint = fields[1].children().unwrap();
+        let algorithm = message_imprint[0].children().unwrap();
+        assert_eq!(algorithm[0].tag, TAG_OID);
+        assert_eq!(asn1::oid(&algorithm[0]).unwrap(), OID_SHA256);
+        assert_eq!(message_imprint[1].content, imprint.as_slice());
+        assert_eq!(fields[2].content, &[0x01, 0x02]);
+    }
+
+    #[test]
+    fn granted_response_yields_token() {
+        let imprint = Sha256::digest(b"data").to_vec();
+        let token = fake_token(&imprint, &[0x05]);
+        let extracted = parse_response(&fake_response(0, Some(&token))).unwrap();
+        assert_eq!(extracted, token);
+
+        let info = parse_token(&extracted).unwrap();
+        assert_eq!(info.hash_oid, OID_SHA256);
+        assert_eq!(info.imprint, imprint);
+        assert_eq!(info.nonce, Some(vec![0x05]));
+        assert_eq!(
+            info.gen_time.unwrap().to_rfc3339(),
+            "2024-01-02T03:04:05+00:00"
+        );
+        assert!(info.tsa_name.is_empty());
+    }
+
+    #[test]
+    fn rejected_response_reports_status_text() {
+        let error = parse_response(&fake_response(2, None)).unwrap_err();
+        assert!(error.contains("статус 2"), "{}", error);
+        assert!(error.contains("bad request"), "{}", error);
+    }
+
+    #[test]
+    fn granted_response_without_token_is_error() {
+        assert!(parse_response(&fake_response(0, None)).is_err());
+        assert!(parse_response(&[0x30, 0x05, 0x01]).is_err());
+    }
+
+    #[test]
+    fn digest_supports_sha2_only() {
+        assert_eq!(digest(OID_SHA256, b"").unwrap().len(), 32);
+        assert_eq!(digest("2.16.840.1.101.3.4.2.3", b"").unwrap().len(), 64);
+        assert!(digest("1.2.643.7.1.1.2.2", b"").is_none());
+    }
+}
@@ -0,0 +1,554 @@
+// src/verify.rs
+
+use crate::asn1::{self, Tlv, TAG_INTEGER, TAG_SEQUENCE, TAG_SET};
+use crate::certificate::CertificateInfo;
+use crate::error::{SignError, VerifyError};
+use chrono::Local;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// --- OID ---
+const OID_SIGNED_DATA: &str = "1.2.840.113549.1.7.2";
+const OID_SIGNING_TIME: &str = "1.2.840.113549.1.9.5";
+
+const TAG_CERTIFICATES: u8 = asn1::context(0);
+const TAG_SIGNED_ATTRS: u8 = asn1::context(0);
+const TAG_UNSIGNED_ATTRS: u8 = asn1::context(1);
+
+/// `-verall` проверяет отзыв сертификатов по сети (CRL, OCSP), поэтому
+/// лимит больше, чем у подписи
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Краткие сведения о сертификате, вложенном в подпись
+#[derive(Clone, Debug, PartialEq)]
+pub struct CertSummary {
+    pub subject_name: String,
+    pub issuer_name: String,
+    pub serial_number: String,
+    pub valid_from: String,
+    pub valid_to: String,
+}
+
+/// Штамп времени (CAdES-T), вложенный в подпись
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimestampDetails {
+    pub time: String,
+    pub tsa_name: String,
+    /// Совпадает ли отпечаток в штампе со значением подписи
+    /// (None — алгоритм хэширования не поддерживается)
+    pub imprint_ok: Option<bool>,
+    /// Результат проверки подписи самого штампа через cryptcp
+    pub token_valid: Option<bool>,
+    pub token: Vec<u8>,
+}
+
+/// Сведения о подписанте
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignerDetails {
+    pub issuer_name: String,
+    pub serial_number: String,
+    pub digest_algorithm: String,
+    pub signing_time: Option<String>,
+    pub certificate: Option<CertSummary>,
+    pub timestamps: Vec<TimestampDetails>,
+}
+
+/// Результат разбора CMS/PKCS#7 SignedData
+#[derive(Clone, Debug, PartialEq)]
+pub struct CmsInfo {
+    pub detached: bool,
+    pub content: Option<Vec<u8>>,
+    pub signers: Vec<SignerDetails>,
+    pub certificates: Vec<CertSummary>,
+}
+
+/// Итог проверки подписи для отображения в UI
+#[derive(Clone, Debug, PartialEq)]
+pub struct VerificationReport {
+    pub signature_path: PathBuf,
+    pub original_path: Option<PathBuf>,
+    pub info: CmsInfo,
+    pub valid: bool,
+    pub message: String,
+}
+
+/// Разбирает подпись (DER, Base64 или PEM) без криптографической проверки
+pub fn inspect_signature(bytes: &[u8]) -> Result<CmsInfo, VerifyError> {
+    asn1::to_der(bytes)
+        .and_then(|der| parse_cms(&der))
+        .map_err(VerifyError::Format)
+}
+
+/// Разбор ASN.1; ошибки — строкой, как в модуле `asn1`
+fn parse_cms(der: &[u8]) -> Result<CmsInfo, String> {
+    let (content_info, _) = asn1::read_tlv(der)?;
+    let fields = content_info.expect(TAG_SEQUENCE)?.children()?;
+    if fields.len() < 2 || asn1::oid(&fields[0])? != OID_SIGNED_DATA {
+        return Err("Файл не является подписью CMS (SignedData)".to_string());
+    }
+
+    let signed_data = fields[1]
+        .expect(asn1::context(0))?
+        .children()?
+        .into_iter()
+        .next()
+        .ok_or("Пустой блок SignedData")?
+        .expect(TAG_SEQUENCE)?;
+    let items = signed_data.children()?;
+    if items.len() < 4 {
+        return Err("Неполная структура SignedData".to_string());
+    }
+
+    // encapContentInfo: если eContent отсутствует — подпись отсоединённая
+    let encap = items[2].expect(TAG_SEQUENCE)?.children()?;
+    let content = match encap.get(1) {
+        Some(explicit) => match explicit.expect(asn1::context(0))?.children()?.first() {
+            Some(octets) => Some(octets.octets()?),
+            None => None,
+        },
+        None => None,
+    };
+
+    let mut certificates = Vec::new();
+    let mut signer_infos = Vec::new();
+    for item in &items[3..] {
+        match item.tag {
+            TAG_CERTIFICATES => {
+                for cert in item.children()? {
+                    if cert.tag == TAG_SEQUENCE {
+                        certificates.push(parse_certificate(&cert)?);
+                    }
+                }
+            }
+            TAG_SET => signer_infos = item.children()?,
+            _ => {}
+        }
+    }
+
+    let mut signers = Vec::new();
+    for signer in &signer_infos {
+        let mut details = parse_signer_info(signer)?;
+        details.certificate = certificates
+            .iter()
+            .find(|c| c.serial_number == details.serial_number && c.issuer_name == details.issuer_name)
+            .cloned();
+        signers.push(details);
+    }
+
+    Ok(CmsInfo {
+        detached: content.is_none(),
+        content,
+        signers,
+        certificates,
+    })
+}
+
+fn parse_certificate(cert: &Tlv) -> Result<CertSummary, String> {
+    let parts = cert.children()?;
+    let tbs = parts
+        .first()
+        .ok_or("Пустой сертификат")?
+        .expect(TAG_SEQUENCE)?
+        .children()?;
+
+    // Поле version ([0] EXPLICIT) необязательное
+    let offset = match tbs.first() {
+        Some(first) if first.tag == asn1::context(0) => 1,
+        _ => 0,
+    };
+    let field = |i: usize| tbs.get(offset + i).ok_or("Неполный сертификат".to_string());
+
+    let serial = field(0)?.expect(TAG_INTEGER)?;
+    let issuer = field(2)?;
+    let validity = field(3)?.children()?;
+    let subject = field(4)?;
+
+    let format_time = |t: Option<&Tlv>| {
+        t.and_then(asn1::decode_time)
+            .map(|t| t.with_timezone(&Local).format("%d.%m.%Y").to_string())
+            .unwrap_or_else(|| "Unknown".to_string())
+    };
+
+    Ok(CertSummary {
+        subject_name: asn1::decode_name(subject)?,
+        issuer_name: asn1::decode_name(issuer)?,
+        serial_number: asn1::format_integer(&serial),
+        valid_from: format_time(validity.first()),
+        valid_to: format_time(validity.get(1)),
+    })
+}
+
+fn parse_signer_info(signer: &Tlv) -> Result<SignerDetails, String> {
+    let fields = signer.expect(TAG_SEQUENCE)?.children()?;
+    if fields.len() < 5 {
+        return Err("Неполная структура SignerInfo".to_string());
+    }
+
+    // sid: IssuerAndSerialNumber или [0] SubjectKeyIdentifier
+    let (issuer_name, serial_number) = if fields[1].tag == TAG_SEQUENCE {
+        let sid = fields[1].children()?;
+        match (sid.first(), sid.get(1)) {
+            (Some(issuer), Some(serial)) => (asn1::decode_name(issuer)?, asn1::format_integer(serial)),
+            _ => return Err("Некорректный идентификатор подписанта".to_string()),
+        }
+    } else {
+        (String::new(), hex::encode_upper(fields[1].content))
+    };
+
+    let digest_oid = match fields[2].expect(TAG_SEQUENCE)?.children()?.first() {
+        Some(oid) => asn1::oid(oid)?,
+        None => String::new(),
+    };
+
+    let mut signing_time = None;
+    if let Some(attrs) = fields.iter().find(|f| f.tag == TAG_SIGNED_ATTRS) {
+        for attr in attrs.children()? {
+            let parts = attr.expect(TAG_SEQUENCE)?.children()?;
+            if parts.len() < 2 || asn1::oid(&parts[0])? != OID_SIGNING_TIME {
+                continue;
+            }
+            signing_time = parts[1]
+                .children()?
+                .first()
+                .and_then(asn1::decode_time)
+                .map(|t| t.with_timezone(&Local).format("%d.%m.%Y %H:%M:%S").to_string());
+        }
+    }
+
+    let mut timestamps = Vec::new();
+    if let Some(attrs) = fields.iter().find(|f| f.tag == TAG_UNSIGNED_ATTRS) {
+        let signature = crate::tsa::signature_value(&fields).unwrap_or_default();
+        for attr in attrs.children()? {
+            let parts = attr.expect(TAG_SEQUENCE)?.children()?;
+            if parts.len() < 2 || asn1::oid(&parts[0])? != crate::tsa::OID_TIMESTAMP_TOKEN {
+                continue;
+            }
+            for token in parts[1].children()? {
+                timestamps.push(parse_timestamp(token.raw, signature));
+            }
+        }
+    }
+
+    Ok(SignerDetails {
+        issuer_name,
+        serial_number,
+        digest_algorithm: digest_name(&digest_oid),
+        signing_time,
+        certificate: None,
+        timestamps,
+    })
+}
+
+/// Разбирает штамп времени и сверяет его отпечаток со значением подписи
+fn parse_timestamp(token: &[u8], signature: &[u8]) -> TimestampDetails {
+    match crate::tsa::parse_token(token) {
+        Ok(info) => TimestampDetails {
+            time: info
+                .gen_time
+                .map(|t| t.with_timezone(&Local).format("%d.%m.%Y %H:%M:%S").to_string())
+                .unwrap_or_else(|| "Unknown".to_string()),
+            tsa_name: info.tsa_name,
+            imprint_ok: crate::tsa::digest(&info.hash_oid, signature).map(|h| h == info.imprint),
+            token_valid: None,
+            token: token.to_vec(),
+        },
+        Err(e) => TimestampDetails {
+            time: format!("Не удалось разобрать штамп: {}", e),
+            tsa_name: String::new(),
+            imprint_ok: Some(false),
+            token_valid: Some(false),
+            token: token.to_vec(),
+        },
+    }
+}
+
+/// Человекочитаемое название алгоритма хэширования
+fn digest_name(oid: &str) -> String {
+    match oid {
+        "1.2.643.7.1.1.2.2" => "ГОСТ Р 34.11-2012 (256 бит)".to_string(),
+        "1.2.643.7.1.1.2.3" => "ГОСТ Р 34.11-2012 (512 бит)".to_string(),
+        "1.2.643.2.2.9" => "ГОСТ Р 34.11-94".to_string(),
+        "1.3.14.3.2.26" => "SHA-1".to_string(),
+        "2.16.840.1.101.3.4.2.1" => "SHA-256".to_string(),
+        "2.16.840.1.101.3.4.2.2" => "SHA-384".to_string(),
+        "2.16.840.1.101.3.4.2.3" => "SHA-512".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Сравнивает серийный номер из подписи (DER, big-endian) с номером,
+/// который выдаёт CryptoAPI (байты в обратном порядке)
+fn serial_matches(der_serial: &str, cert: &CertificateInfo) -> bool {
+    let parse = |s: &str| hex::decode(s.replace([':', ' '], "")).unwrap_or_default();
+    let mut from_store = parse(&cert.serial_number);
+    from_store.reverse();
+    let trim = |v: Vec<u8>| v.into_iter().skip_while(|&b| b == 0).collect::<Vec<_>>();
+    trim(parse(der_serial)) == trim(from_store)
+}
+
+/// Проверяет key.sig перед отправкой на сервер: подпись присоединённая,
+/// содержит ровно подписанный challenge и выполнена выбранным сертификатом
+pub fn check_auth_signature(signature: &[u8], data: &[u8], cert: &CertificateInfo) -> Result<(), VerifyError> {
+    let info = inspect_signature(signature)?;
+    let mismatch = |e: &str| Err(VerifyError::Mismatch(e.to_string()));
+
+    match info.content.as_deref() {
+        Some(content) if content == data => {}
+        Some(_) => return mismatch("Подписанные данные не совпадают с полученными от сервера"),
+        None => return mismatch("Ожидалась присоединённая подпись, получена отсоединённая"),
+    }
+
+    if info.signers.is_empty() {
+        return mismatch("Подпись не содержит сведений о подписанте");
+    }
+
+    if !info.signers.iter().any(|s| serial_matches(&s.serial_number, cert)) {
+        return mismatch("Подпись выполнена не выбранным сертификатом");
+    }
+
+    Ok(())
+}
+
+/// Проверяет подпись через cryptcp и разбирает её содержимое.
+/// Для отсоединённой подписи нужен исходный файл.
+/// cryptcp работает в пуле блокирующих задач, чтобы не замораживать UI.
+pub async fn verify_signature(
+    signature_path: PathBuf,
+    original_path: Option<PathBuf>,
+) -> Result<VerificationReport, VerifyError> {
+    tokio::task::spawn_blocking(move || verify_blocking(signature_path, original_path))
+        .await
+        .map_err(|e| VerifyError::Interrupted(e.to_string()))?
+}
+
+fn verify_blocking(
+    signature_path: PathBuf,
+    original_path: Option<PathBuf>,
+) -> Result<VerificationReport, VerifyError> {
+    let raw = std::fs::read(&signature_path).map_err(|e| {
+        VerifyError::File(format!("Не удалось прочитать {}: {}", signature_path.display(), e))
+    })?;
+    let info = inspect_signature(&raw)?;
+
+    if info.detached && original_path.is_none() {
+        return Err(VerifyError::OriginalRequired);
+    }
+
+    let (valid, message) = match (&original_path, info.detached) {
+        (Some(original), true) => run_cryptcp_verify_detached(&signature_path, original)?,
+        _ => run_cryptcp_verify_attached(&signature_path)?,
+    };
+
+    // Штампы времени — самостоятельные подписи TSA, проверяем каждый
+    let mut info = info;
+    for signer in info.signers.iter_mut() {
+        for stamp in signer.timestamps.iter_mut().filter(|t| t.token_valid.is_none()) {
+            let dir = VerifyDir::create()?;
+            let token_path = dir.path.join("timestamp.p7s");
+            std::fs::write(&token_path, &stamp.token)
+                .map_err(|e| VerifyError::File(format!("Не удалось подготовить штамп к проверке: {}", e)))?;
+            let result = run_cryptcp_verify_attached(&token_path);
+            stamp.token_valid = Some(result.map(|(ok, _)| ok).unwrap_or(false));
+        }
+    }
+
+    Ok(VerificationReport {
+        signature_path,
+        original_path,
+        info,
+        valid,
+        message,
+    })
+}
+
+/// Очищает кэш списков отзыва (CRL), накопленный CryptoAPI при проверке
+/// цепочек (`-verall`). Кэш общий для учётной записи Windows: списки отзыва
+/// удаляются и для других программ. Остальные записи URL-кэша
+/// (сертификаты, ответы OCSP) не трогаются — отобрать их certutil не умеет.
+#[cfg(windows)]
+pub fn clear_revocation_cache() -> Result<(), VerifyError> {
+    let output = Command::new("certutil")
+        .args(["-urlcache", "crl", "delete"])
+        .output()
+        .map_err(|e| VerifyError::RevocationCache(e.to_string()))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(VerifyError::RevocationCache(summarize_output(&output)))
+    }
+}
+
+/// Вне Windows проверка идёт без кэша CryptoAPI — очищать нечего
+#[cfg(not(windows))]
+pub fn clear_revocation_cache() -> Result<(), VerifyError> {
+    Ok(())
+}
+
+/// Отдельная временная папка на каждый вызов cryptcp, чтобы одновременные
+/// проверки не затирали файлы друг друга; удаляется вместе с содержимым
+struct VerifyDir {
+    path: PathBuf,
+}
+
+impl VerifyDir {
+    fn create() -> Result<Self, VerifyError> {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let mut path = crate::storage::ensure_czn_dir().map_err(|e| VerifyError::File(e.to_string()))?;
+        path.push("verify");
+        path.push(format!(
+            "{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&path)
+            .map_err(|e| {
+                VerifyError::File(format!("Не удалось создать директорию {}: {}", path.display(), e))
+            })?;
+        Ok(Self { path })
+    }
+}
+
+impl Drop for VerifyDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+fn run_cryptcp_verify_attached(signature_path: &Path) -> Result<(bool, String), VerifyError> {
+    let cryptcp_path = cryptcp_path()?;
+    let dir = VerifyDir::create()?;
+    let out_path = dir.path.join("content.out");
+
+    let mut cmd = Command::new(&cryptcp_path);
+    cmd.arg("-verify")
+        .arg("-verall")
+        .arg(signature_path)
+        .arg(&out_path);
+    run_verify(cmd)
+}
+
+fn run_cryptcp_verify_detached(signature_path: &Path, original: &Path) -> Result<(bool, String), VerifyError> {
+    let cryptcp_path = cryptcp_path()?;
+
+    // cryptcp ищет подпись как <каталог>/<имя исходного файла>.sig
+    let file_name = original
+        .file_name()
+        .ok_or_else(|| VerifyError::File("Недопустимый путь к исходному файлу".to_string()))?
+        .to_string_lossy()
+        .to_string();
+    let dir = VerifyDir::create()?;
+    let expected_sig = dir.path.join(format!("{}.sig", file_name));
+    std::fs::copy(signature_path, &expected_sig)
+        .map_err(|e| VerifyError::File(format!("Не удалось подготовить подпись к проверке: {}", e)))?;
+
+    let mut cmd = Command::new(&cryptcp_path);
+    cmd.arg("-vsignf")
+        .arg("-dir")
+        .arg(&dir.path)
+        .arg("-verall")
+        .arg(original);
+    run_verify(cmd)
+}
+
+fn cryptcp_path() -> Result<String, VerifyError> {
+    crate::signing::find_cryptcp_path()
+        .map_err(|e| VerifyError::Csp(SignError::CspNotFound(e.to_string())))
+}
+
+/// Запускает проверку с ограничением по времени; зависший cryptcp завершается
+fn run_verify(cmd: Command) -> Result<(bool, String), VerifyError> {
+    let output = crate::signing::output_with_timeout(cmd, VERIFY_TIMEOUT).map_err(VerifyError::Csp)?;
+    Ok((output.status.success(), summarize_output(&output)))
+}
+
+/// Оставляет из вывода cryptcp только последние содержательные строки
+fn summarize_output(output: &std::process::Output) -> String {
+    let mut text = crate::signing::decode_console(&output.stdout);
+    text.push('\n');
+    text.push_str(&crate::signing::decode_console(&output.stderr));
+
+    let lines: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with("CryptCP") && !l.starts_with("Утилита"))
+        .collect();
+    let tail = lines.len().saturating_sub(3);
+    lines[tail..].join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Подписи OpenSSL (ECDSA P-256, SHA-256) на строку "challenge-data"
+    const ATTACHED: &[u8] = include_bytes!("../tests/fixtures/attached.p7s");
+    const DETACHED: &[u8] = include_bytes!("../tests/fixtures/detached.sig");
+    const DETACHED_NO_CERTS: &[u8] = include_bytes!("../tests/fixtures/detached-nocerts.pem");
+    const DATA: &[u8] = b"challenge-data";
+
+    #[test]
+    fn attached_signature() {
+        let info = inspect_signature(ATTACHED).unwrap();
+        assert!(!info.detached);
+        assert_eq!(info.content.as_deref(), Some(DATA));
+        assert_eq!(info.certificates.len(), 1);
+        assert_eq!(info.signers.len(), 1);
+
+        let signer = &info.signers[0];
+        assert_eq!(signer.serial_number, "12:34");
+        assert_eq!(signer.digest_algorithm, "SHA-256");
+        assert!(signer.signing_time.is_some());
+        assert!(signer.timestamps.is_empty());
+
+        let cert = signer.certificate.as_ref().unwrap();
+        assert_eq!(cert, &info.certificates[0]);
+        assert!(cert.subject_name.contains("Test Signer"), "{}", cert.subject_name);
+        assert_eq!(cert.subject_name, cert.issuer_name);
+    }
+
+    #[test]
+    fn detached_signature() {
+        let info = inspect_signature(DETACHED).unwrap();
+        assert!(info.detached);
+        assert_eq!(info.content, None);
+        assert_eq!(info.signers.len(), 1);
+        assert!(info.signers[0].certificate.is_some());
+    }
+
+    #[test]
+    fn pem_signature_without_certificates() {
+        let info = inspect_signature(DETACHED_NO_CERTS).unwrap();
+        assert!(info.detached);
+        assert!(info.certificates.is_empty());
+        // Подписант известен, но его сертификата в подписи нет
+        assert_eq!(info.signers[0].serial_number, "12:34");
+        assert_eq!(info.signers[0].certificate, None);
+    }
+
+    #[test]
+    fn rejects_other_data() {
+        assert!(inspect_signature(b"not a signature").is_err());
+        assert!(inspect_signature(&ATTACHED[..ATTACHED.len() / 2]).is_err());
+        // SEQUENCE { OID data } — не SignedData
+        let data = asn1::encode(TAG_SEQUENCE, &asn1::encode_oid("1.2.840.113549.1.7.1"));
+        assert!(inspect_signature(&data).is_err());
+    }
+
+    #[test]
+    fn serial_from_store_is_little_endian() {
+        let cert = CertificateInfo {
+            subject_name: String::new(),
+            issuer_name: String::new(),
+            serial_number: "34 12 00".to_string(),
+            thumbprint: String::new(),
+            valid_from: String::new(),
+            valid_to: String::new(),
+            not_before: crate::certificate::system_time_to_filetime(std::time::SystemTime::now()),
+            not_after: crate::certificate::system_time_to_filetime(std::time::SystemTime::now()),
+        };
+        assert!(serial_matches("12:34", &cert));
+        assert!(!serial_matches("34:12", &cert));
+    }
+}